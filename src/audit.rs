@@ -0,0 +1,155 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use bitcoin::hashes::Hash;
+
+use crate::{
+    search::NearMiss,
+    types::{NearMissLevel, NearMissOptions, NearMissRecord, Payload, Variant},
+    worker::{bitwork_difficulty, BitworkMatcher},
+};
+
+/// A near-miss count less likely than this is taken for a broken hash path.
+pub const IMPLAUSIBLE: f64 = 1e-9;
+
+/// Counts the txids of a run that meet progressively shorter prefixes of the
+/// target bitwork, optionally journaling each one.
+#[derive(Debug)]
+pub struct NearMisses {
+    /// Longest prefix first, so a txid meeting one level meets all after it.
+    levels: Vec<Level>,
+    attempts: AtomicU64,
+    journal: Option<Mutex<File>>,
+}
+
+#[derive(Debug)]
+struct Level {
+    prefix: String,
+    matcher: BitworkMatcher,
+    observed: AtomicU64,
+}
+
+impl NearMisses {
+    /// Levels one to `options.levels` nibbles short of `prefix`, keeping at
+    /// least one nibble.
+    pub fn new(prefix: &str, options: &NearMissOptions) -> Result<Self> {
+        let levels = (1..=options.levels as usize)
+            .take_while(|short| *short < prefix.len())
+            .map(|short| {
+                let prefix = prefix[..prefix.len() - short].to_string();
+                let matcher = BitworkMatcher::new(&Some(prefix.clone()), &None)
+                    .ok_or_else(|| anyhow!("invalid bitwork prefix `{}`", prefix))?;
+                Ok(Level {
+                    prefix,
+                    matcher,
+                    observed: AtomicU64::new(0),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if levels.is_empty() {
+            bail!(
+                "bitwork prefix `{}` is too short to count near misses",
+                prefix
+            );
+        }
+        let journal = match &options.journal {
+            Some(path) => Some(Mutex::new(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .with_context(|| format!("failed to open near-miss journal {}", path))?,
+            )),
+            None => None,
+        };
+        Ok(NearMisses {
+            levels,
+            attempts: AtomicU64::new(0),
+            journal,
+        })
+    }
+
+    /// The shortest level, which every near miss meets.
+    pub fn matcher(&self) -> &BitworkMatcher {
+        &self.levels.last().expect("at least one level").matcher
+    }
+
+    /// Counts a searched batch of `attempts` candidates of `payload` and the
+    /// near misses among them.
+    pub fn record(&self, payload: &Payload, attempts: u64, near_misses: &[NearMiss]) {
+        self.attempts.fetch_add(attempts, Ordering::Relaxed);
+        for near_miss in near_misses {
+            let hash = near_miss.txid.to_byte_array();
+            let Some(first) = self
+                .levels
+                .iter()
+                .position(|level| level.matcher.matches(&hash))
+            else {
+                continue;
+            };
+            for level in &self.levels[first..] {
+                level.observed.fetch_add(1, Ordering::Relaxed);
+            }
+            if let Some(journal) = &self.journal {
+                let candidate = near_miss.candidate;
+                let record = NearMissRecord {
+                    nonce: payload.copied_data.args.nonce,
+                    time: payload.copied_data.args.time,
+                    sequence: candidate.sequence,
+                    variant: Some(candidate.variant).filter(|v| *v != Variant::default()),
+                    txid: near_miss.txid.to_string(),
+                    prefix: self.levels[first].prefix.clone(),
+                };
+                let line = serde_json::to_string(&record).expect("serializable record");
+                if let Err(err) = writeln!(journal.lock().unwrap(), "{}", line) {
+                    eprintln!("Error: failed to journal near miss: {}", err);
+                }
+            }
+        }
+    }
+
+    /// Observed against expected counts per level, longest prefix first.
+    pub fn report(&self) -> Vec<NearMissLevel> {
+        let attempts = self.attempts.load(Ordering::Relaxed);
+        self.levels
+            .iter()
+            .map(|level| {
+                let observed = level.observed.load(Ordering::Relaxed);
+                let expected = attempts as f64 / bitwork_difficulty(&level.prefix, None);
+                NearMissLevel {
+                    prefix: level.prefix.clone(),
+                    observed,
+                    expected,
+                    probability: tail_bound(observed, expected),
+                }
+            })
+            .collect()
+    }
+
+    /// Whether any level's count is too unlikely for a working hash path.
+    pub fn is_implausible(&self) -> bool {
+        self.report()
+            .iter()
+            .any(|level| level.probability < IMPLAUSIBLE)
+    }
+}
+
+/// Chernoff bound on the chance of a Poisson count with mean `expected`
+/// landing at least as far from it as `observed`.
+fn tail_bound(observed: u64, expected: f64) -> f64 {
+    if expected <= 0.0 {
+        return if observed == 0 { 1.0 } else { 0.0 };
+    }
+    let k = observed as f64;
+    if k == 0.0 {
+        return (-expected).exp();
+    }
+    (k - expected - k * (k / expected).ln()).exp().min(1.0)
+}
@@ -0,0 +1,134 @@
+use std::{fmt, str::FromStr};
+
+use anyhow::{bail, Result};
+use bitcoin::{Network, Script, Transaction, Txid};
+
+use crate::{
+    bitcoind::BitcoindClient,
+    electrum::ElectrumClient,
+    esplora::EsploraClient,
+    types::{FundingUtxo, MempoolAcceptance, WorkerOptions},
+};
+
+/// The chain queries and broadcasts the tool needs, independent of the server.
+pub trait ChainBackend {
+    /// Lists the unspent outputs locked by `script_pubkey`.
+    fn list_unspent(&self, script_pubkey: &Script) -> Result<Vec<FundingUtxo>>;
+
+    /// Checks whether the transactions, in order, would enter the mempool.
+    fn test_mempool_accept(&self, _txs: &[Transaction]) -> Result<Vec<MempoolAcceptance>> {
+        bail!("this backend cannot test mempool acceptance")
+    }
+
+    /// Estimated fee rate in sat/vB for confirmation within `target_blocks`.
+    fn fee_rate(&self, _target_blocks: u16) -> Result<u64> {
+        bail!("this backend cannot estimate fees")
+    }
+
+    /// Confirmation count of `txid`: `Some(0)` while in the mempool and
+    /// `None` when the backend does not know the transaction.
+    fn confirmations(&self, _txid: &Txid) -> Result<Option<u32>> {
+        bail!("this backend cannot look up transactions")
+    }
+
+    /// [`ChainBackend::confirmations`] of `tx`, for backends that can find
+    /// a transaction they do not index through its outputs.
+    fn tx_confirmations(&self, tx: &Transaction) -> Result<Option<u32>> {
+        self.confirmations(&tx.txid())
+    }
+
+    fn broadcast(&self, tx: &Transaction) -> Result<Txid>;
+}
+
+/// An error reported by the server itself, as opposed to the transport.
+#[derive(Debug)]
+pub struct ServerError {
+    pub method: String,
+    pub code: Option<i64>,
+    pub message: String,
+}
+
+impl fmt::Display for ServerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.code {
+            Some(code) => write!(f, "{} failed ({}): {}", self.method, code, self.message),
+            None => write!(f, "{} failed: {}", self.method, self.message),
+        }
+    }
+}
+
+impl std::error::Error for ServerError {}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BackendKind {
+    Electrum,
+    Bitcoind,
+    Esplora,
+}
+
+impl FromStr for BackendKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "electrum" => Ok(BackendKind::Electrum),
+            "bitcoind" => Ok(BackendKind::Bitcoind),
+            "esplora" => Ok(BackendKind::Esplora),
+            other => Err(format!("unknown backend `{}`", other)),
+        }
+    }
+}
+
+/// Builds the requested backend, or the first configured one of bitcoind,
+/// esplora and electrum.
+pub fn from_options(
+    options: &WorkerOptions,
+    network: Network,
+    kind: Option<BackendKind>,
+) -> Result<Box<dyn ChainBackend>> {
+    let kind = kind.unwrap_or(if options.bitcoind_rpc.is_some() {
+        BackendKind::Bitcoind
+    } else if options.esplora_url.is_some() {
+        BackendKind::Esplora
+    } else {
+        BackendKind::Electrum
+    });
+    Ok(match kind {
+        BackendKind::Electrum => Box::new(ElectrumClient::new(&options.electrum_api)?),
+        BackendKind::Bitcoind => match &options.bitcoind_rpc {
+            Some(rpc) => Box::new(BitcoindClient::new(rpc)?),
+            None => bail!("workerOptions.bitcoindRpc is not set"),
+        },
+        BackendKind::Esplora => match &options.esplora_url {
+            Some(url) => Box::new(EsploraClient::new(url, network)),
+            None => bail!("workerOptions.esploraUrl is not set"),
+        },
+    })
+}
+
+/// Replaces `satsbyte` with the backend's estimate when the job asks for a
+/// confirmation target.
+pub fn apply_fee_target(options: &mut WorkerOptions, backend: &dyn ChainBackend) -> Result<()> {
+    if let Some(target_blocks) = options.fee_target_blocks {
+        options.satsbyte = backend.fee_rate(target_blocks)?;
+    }
+    Ok(())
+}
+
+impl ChainBackend for ElectrumClient {
+    fn list_unspent(&self, script_pubkey: &Script) -> Result<Vec<FundingUtxo>> {
+        ElectrumClient::list_unspent(self, script_pubkey)
+    }
+
+    fn fee_rate(&self, target_blocks: u16) -> Result<u64> {
+        ElectrumClient::fee_rate(self, target_blocks)
+    }
+
+    fn confirmations(&self, txid: &Txid) -> Result<Option<u32>> {
+        ElectrumClient::confirmations(self, txid)
+    }
+
+    fn broadcast(&self, tx: &Transaction) -> Result<Txid> {
+        ElectrumClient::broadcast(self, tx)
+    }
+}
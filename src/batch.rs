@@ -0,0 +1,106 @@
+use anyhow::{bail, Result};
+use bitcoin::consensus::encode::serialize_hex;
+
+use crate::{
+    cancel::CancellationToken,
+    descriptor, interpreter,
+    miner::{self, MineOutcome},
+    policy, reveal,
+    search::{self, SearchBackend},
+    types::{BatchReport, ChainedMint, FundingUtxo, Root},
+    utils::get_output_value_for_commit,
+    worker,
+};
+
+/// Default mempool limit on a transaction's in-mempool ancestors or
+/// descendants, itself included.
+pub const MAX_PACKAGE_COUNT: usize = 25;
+
+/// Longest chain that fits the limits: the first commit's descendants are
+/// every later commit and every reveal. Assumes a confirmed funding UTXO.
+pub const MAX_BATCH: usize = MAX_PACKAGE_COUNT / 2;
+
+/// Vout of the change output in a commit that has one.
+const CHANGE_VOUT: u32 = 1;
+
+/// Mines up to `count` mints in a chain, each commit funded by the change
+/// output of the one before. Stops early when a run ends without a hit or a
+/// commit's change cannot fund the next.
+pub fn mine_batch(
+    msg: &Root,
+    count: usize,
+    token: &CancellationToken,
+    backend: &dyn SearchBackend,
+) -> Result<BatchReport> {
+    if count == 0 || count > MAX_BATCH {
+        bail!(
+            "batch size must be between 1 and {} to stay within mempool chain limits",
+            MAX_BATCH
+        );
+    }
+    if miner::fan_out(msg) > 1 {
+        bail!("a batch chains single-output commits; drop fanOut");
+    }
+    if msg.resume.is_some() {
+        bail!("a batch cannot resume a checkpoint; mine each link afresh");
+    }
+    let hasher = search::hasher_by_name(msg.search_backend.as_deref())?;
+    let mut msg = msg.clone();
+    let mut report = BatchReport {
+        mints: vec![],
+        stopped: None,
+        next_funding: None,
+    };
+    while report.mints.len() < count {
+        token.rearm();
+        let success = match miner::mine_with(&msg, token, backend)? {
+            MineOutcome::Found(success) => success,
+            MineOutcome::Stopped(stop) => {
+                report.stopped = Some(stop);
+                break;
+            }
+        };
+        let mut payload = miner::get_payload(msg.clone(), Some(success.time), Some(success.nonce))?;
+        payload.variant = success.variant.unwrap_or_default();
+        let commit = worker::build_commit_tx(success.sequence as u32, &payload)?;
+        let template = reveal::reveal_template(&msg, &payload, commit.txid())?;
+        let reveal = reveal::mine_reveal_tx(
+            &template,
+            &payload,
+            payload.copied_data.args.bitworkr.as_deref(),
+            miner::sequence_range(&msg.sequence_policy),
+            token,
+            hasher,
+        )?;
+        interpreter::verify_mint(&payload, &commit, std::slice::from_ref(&reveal))?;
+        policy::ensure_mint_standard(&msg, &payload, &commit, std::slice::from_ref(&reveal))?;
+        report.next_funding = commit
+            .output
+            .get(CHANGE_VOUT as usize)
+            .map(|change| FundingUtxo {
+                txid: commit.txid().to_string(),
+                tx_id: commit.txid().to_string(),
+                output_index: CHANGE_VOUT as u64,
+                index: CHANGE_VOUT,
+                vout: CHANGE_VOUT,
+                value: change.value.to_sat(),
+            });
+        report.mints.push(ChainedMint {
+            result: success,
+            commit_txid: commit.txid().to_string(),
+            commit_tx: serialize_hex(&commit),
+            reveal_txid: reveal.txid().to_string(),
+            reveal_tx: serialize_hex(&reveal),
+            commit_outputs: descriptor::commit_outputs(&msg, &payload)?,
+        });
+        if report.mints.len() == count {
+            break;
+        }
+        let required = get_output_value_for_commit(msg.fees) + msg.fees.commit_fee_only;
+        match &report.next_funding {
+            Some(change) if change.value >= required => msg.funding_utxo = change.clone(),
+            _ => break,
+        }
+    }
+    Ok(report)
+}
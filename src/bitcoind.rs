@@ -0,0 +1,216 @@
+use std::{fs, time::Duration};
+
+use anyhow::{anyhow, bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use bitcoin::{consensus::encode::serialize_hex, Amount, Script, Transaction, Txid};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::{json, Value};
+
+use crate::{
+    backend::{ChainBackend, ServerError},
+    types::{BitcoindRpc, FundingUtxo, MempoolAcceptance},
+};
+
+/// Bitcoin Core JSON-RPC client.
+#[derive(Debug, Clone)]
+pub struct BitcoindClient {
+    url: String,
+    authorization: String,
+    agent: ureq::Agent,
+}
+
+#[derive(Debug, Deserialize)]
+struct RpcResponse {
+    result: Option<Value>,
+    error: Option<RpcError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ScanResult {
+    unspents: Vec<ScanUnspent>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ScanUnspent {
+    txid: String,
+    vout: u32,
+    amount: f64,
+}
+
+#[derive(Debug, Deserialize)]
+struct SmartFee {
+    feerate: Option<f64>,
+    #[serde(default)]
+    errors: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawTransaction {
+    #[serde(default)]
+    confirmations: u32,
+}
+
+#[derive(Debug, Deserialize)]
+struct UnspentOutput {
+    confirmations: u32,
+}
+
+#[derive(Debug, Deserialize)]
+struct AcceptResult {
+    txid: String,
+    allowed: Option<bool>,
+    #[serde(rename = "reject-reason")]
+    reject_reason: Option<String>,
+}
+
+/// `RPC_INVALID_ADDRESS_OR_KEY`, returned for unknown transactions.
+const RPC_NOT_FOUND: i64 = -5;
+
+/// `RPC_VERIFY_ALREADY_IN_CHAIN`, returned when rebroadcasting a confirmed
+/// transaction that still has unspent outputs.
+const RPC_ALREADY_IN_CHAIN: i64 = -27;
+
+impl BitcoindClient {
+    pub fn new(rpc: &BitcoindRpc) -> Result<Self> {
+        let credentials = match (&rpc.cookie_file, &rpc.user, &rpc.password) {
+            (Some(path), _, _) => fs::read_to_string(path)
+                .with_context(|| format!("failed to read cookie file {}", path))?
+                .trim()
+                .to_string(),
+            (None, Some(user), Some(password)) => format!("{}:{}", user, password),
+            _ => bail!("bitcoindRpc needs a cookieFile or a user and password"),
+        };
+        Ok(BitcoindClient {
+            url: rpc.url.clone(),
+            authorization: format!("Basic {}", STANDARD.encode(credentials)),
+            agent: ureq::AgentBuilder::new()
+                .timeout(Duration::from_secs(30))
+                .build(),
+        })
+    }
+
+    pub fn call<T: DeserializeOwned>(&self, method: &str, params: Value) -> Result<T> {
+        let request = json!({
+            "jsonrpc": "1.0",
+            "id": "psbt",
+            "method": method,
+            "params": params,
+        });
+        // Bitcoin Core reports RPC errors with a 500 status and a JSON body.
+        let response = match self
+            .agent
+            .post(&self.url)
+            .set("Authorization", &self.authorization)
+            .send_json(request)
+        {
+            Ok(response) | Err(ureq::Error::Status(500, response)) => response,
+            Err(ureq::Error::Status(code, _)) => bail!("{} failed with HTTP {}", method, code),
+            Err(err) => return Err(err).with_context(|| format!("{} failed", method)),
+        };
+        let body: RpcResponse = response.into_json()?;
+        if let Some(error) = body.error {
+            return Err(ServerError {
+                method: method.to_string(),
+                code: Some(error.code),
+                message: error.message,
+            }
+            .into());
+        }
+        // A null result is only an answer to calls that may return nothing.
+        match body.result {
+            Some(result) => Ok(serde_json::from_value(result)?),
+            None => serde_json::from_value(Value::Null)
+                .map_err(|_| anyhow!("{} returned no result", method)),
+        }
+    }
+}
+
+impl ChainBackend for BitcoindClient {
+    /// Uses `scantxoutset`, so no wallet needs to be loaded.
+    fn list_unspent(&self, script_pubkey: &Script) -> Result<Vec<FundingUtxo>> {
+        let descriptor = format!("raw({})", hex::encode(script_pubkey.as_bytes()));
+        let scan: ScanResult = self.call("scantxoutset", json!(["start", [descriptor]]))?;
+        scan.unspents
+            .into_iter()
+            .map(|utxo| {
+                Ok(FundingUtxo {
+                    txid: utxo.txid.clone(),
+                    tx_id: utxo.txid,
+                    output_index: utxo.vout as u64,
+                    index: utxo.vout,
+                    vout: utxo.vout,
+                    value: Amount::from_btc(utxo.amount)?.to_sat(),
+                })
+            })
+            .collect()
+    }
+
+    fn test_mempool_accept(&self, txs: &[Transaction]) -> Result<Vec<MempoolAcceptance>> {
+        let raw: Vec<String> = txs.iter().map(serialize_hex).collect();
+        let results: Vec<AcceptResult> = self.call("testmempoolaccept", json!([raw]))?;
+        Ok(results
+            .into_iter()
+            .map(|result| MempoolAcceptance {
+                txid: result.txid,
+                allowed: result.allowed.unwrap_or(false),
+                reject_reason: result.reject_reason,
+            })
+            .collect())
+    }
+
+    /// `estimatesmartfee` answers in BTC/kvB.
+    fn fee_rate(&self, target_blocks: u16) -> Result<u64> {
+        let fee: SmartFee = self.call("estimatesmartfee", json!([target_blocks]))?;
+        match fee.feerate {
+            Some(btc_per_kvb) => Ok((btc_per_kvb * 100_000.0).ceil() as u64),
+            None => bail!("estimatesmartfee failed: {}", fee.errors.join(", ")),
+        }
+    }
+
+    /// Relies on the mempool or `-txindex` to find the transaction.
+    fn confirmations(&self, txid: &Txid) -> Result<Option<u32>> {
+        let tx: Result<RawTransaction> =
+            self.call("getrawtransaction", json!([txid.to_string(), true]));
+        match tx {
+            Ok(tx) => Ok(Some(tx.confirmations)),
+            Err(err) if has_code(&err, RPC_NOT_FOUND) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Without `-txindex` a confirmed transaction is only found through its
+    /// unspent outputs, so those are looked up when `getrawtransaction` fails.
+    fn tx_confirmations(&self, tx: &Transaction) -> Result<Option<u32>> {
+        if let Some(confirmations) = self.confirmations(&tx.txid())? {
+            return Ok(Some(confirmations));
+        }
+        for vout in 0..tx.output.len() {
+            let output: Option<UnspentOutput> =
+                self.call("gettxout", json!([tx.txid().to_string(), vout, true]))?;
+            if let Some(output) = output {
+                return Ok(Some(output.confirmations));
+            }
+        }
+        Ok(None)
+    }
+
+    fn broadcast(&self, tx: &Transaction) -> Result<Txid> {
+        let txid: Result<String> = self.call("sendrawtransaction", json!([serialize_hex(tx)]));
+        match txid {
+            Ok(txid) => Ok(txid.parse()?),
+            Err(err) if has_code(&err, RPC_ALREADY_IN_CHAIN) => Ok(tx.txid()),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+fn has_code(err: &anyhow::Error, code: i64) -> bool {
+    err.downcast_ref::<ServerError>()
+        .is_some_and(|error| error.code == Some(code))
+}
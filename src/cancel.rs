@@ -0,0 +1,43 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+/// Stops a running search from another thread or a signal handler.
+///
+/// Cancelling raises the same `found` flag the workers already poll, so they
+/// wind down exactly as they would after a hit.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    found: Arc<AtomicBool>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+        self.found.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Lowers the flag a hit raised so the token can drive another search,
+    /// unless it was cancelled. Cancelling raises the flag after marking the
+    /// token, so a concurrent cancel is never lost.
+    pub(crate) fn rearm(&self) {
+        self.found.store(false, Ordering::SeqCst);
+        if self.is_cancelled() {
+            self.found.store(true, Ordering::SeqCst);
+        }
+    }
+
+    pub(crate) fn found_flag(&self) -> &AtomicBool {
+        &self.found
+    }
+}
@@ -0,0 +1,756 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    io::{BufRead, BufReader, Write},
+    net::{Shutdown, TcpListener, TcpStream, ToSocketAddrs},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Result};
+use bitcoin::{
+    consensus::{deserialize, encode::serialize_hex},
+    hashes::Hash,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cancel::CancellationToken,
+    hash::{HashBackend, TxidTemplate},
+    miner::{self, MineOutcome},
+    scheduler::{Scheduler, Task, BATCH_SIZE},
+    search::{Candidate, Search, TemplateSearch},
+    types::{Payload, Root, SequenceRange, StopReason, Success, Variant},
+    utils::parse_bitwork,
+    worker::{
+        self, bitwork_difficulty, grade_bitwork, is_share_bitwork, predicate, BitworkGrade,
+        BitworkMatcher,
+    },
+};
+
+/// Chunks handed out per worker thread at a time.
+pub const CHUNKS_PER_THREAD: usize = 10;
+
+/// Most threads credited to one connection, whatever its hello claims.
+pub const MAX_WORKER_THREADS: usize = 1024;
+
+/// How often workers report that they are alive.
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// A worker silent for this long is dropped and its assignment handed out
+/// again.
+pub const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// One line of JSON on a coordinator connection.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Message {
+    /// Sent by a worker once connected. Shares are credited to `name`.
+    Hello {
+        threads: usize,
+        #[serde(default)]
+        name: Option<String>,
+    },
+    /// What a worker needs to search one owner's commits, sent once per
+    /// connection before its first assignment of that owner.
+    Setup(OwnerSetup),
+    Assign(Assignment),
+    Heartbeat,
+    /// The assignment was searched without a hit.
+    Done {
+        id: u64,
+    },
+    /// A txid meeting the share bitwork but not the target.
+    Share {
+        id: u64,
+        sequence: u32,
+        variant: Variant,
+    },
+    Found {
+        id: u64,
+        sequence: u32,
+        variant: Variant,
+    },
+    /// The job is over.
+    Stop,
+}
+
+/// Sequences of an owner's commits for a worker to search, over every
+/// variant in the owner's setup.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Assignment {
+    pub id: u64,
+    pub owner: usize,
+    pub range: SequenceRange,
+}
+
+/// The bitwork and unsigned commits of one owner. Workers only ever see
+/// unsigned commits; the coordinator keeps the key and signs the winner.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OwnerSetup {
+    pub owner: usize,
+    pub prefix: Option<String>,
+    pub ext: Option<u8>,
+    /// Share bitwork in pool mode.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub share_prefix: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub share_ext: Option<u8>,
+    pub templates: Vec<CommitTemplate>,
+}
+
+/// Shares credited to one worker name in pool mode.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Contribution {
+    pub worker: String,
+    pub shares: u64,
+    /// Invalid or duplicate shares.
+    pub rejected: u64,
+    /// Expected candidates searched to find the shares.
+    pub work: f64,
+}
+
+/// How a coordinated job ended, with the contributions of each worker in
+/// pool mode.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Coordinated {
+    pub outcome: MineOutcome,
+    pub contributions: Vec<Contribution>,
+}
+
+/// An unsigned commit (hex) with `nSequence` 0.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CommitTemplate {
+    pub variant: Variant,
+    pub tx: String,
+}
+
+enum Event {
+    Connected(u64, TcpStream),
+    Received(u64, Message),
+    Closed(u64),
+}
+
+struct Connection {
+    stream: TcpStream,
+    name: String,
+    /// Zero until the worker says hello.
+    threads: usize,
+    last_seen: Instant,
+    assignment: Option<u64>,
+    /// Owners whose setup was sent.
+    owners: HashSet<usize>,
+}
+
+struct Assigned {
+    connection: u64,
+    owner: usize,
+    tasks: Vec<Task>,
+}
+
+/// The coordinator's view of the job. Only the coordinating thread touches
+/// it; connections feed it events over a channel.
+struct Coordinator<'a> {
+    msg: &'a Root,
+    payloads: Vec<Payload>,
+    variants: Vec<Variant>,
+    templates: Vec<Vec<(CommitTemplate, TxidTemplate)>>,
+    /// Share prefix and extension in pool mode.
+    share: Option<(String, Option<u8>)>,
+    shares: HashSet<(usize, u32, Variant)>,
+    contributions: BTreeMap<String, Contribution>,
+    pending: VecDeque<Task>,
+    assigned: HashMap<u64, Assigned>,
+    connections: HashMap<u64, Connection>,
+    completed: Vec<Vec<SequenceRange>>,
+    searched: u64,
+    next_id: u64,
+}
+
+/// Hands out the job's `(nonce, sequence)` space to workers connecting on
+/// `listener` until one reports a hit that signs, the space is searched or
+/// `token` is cancelled. With `msg.share_bitwork` set, runs as a pool.
+pub fn coordinate(
+    msg: &Root,
+    listener: TcpListener,
+    token: &CancellationToken,
+) -> Result<Coordinated> {
+    let target = &msg.worker_bitwork_info_commit;
+    let share = match &msg.share_bitwork {
+        Some(bitwork) => {
+            let (prefix, ext) = parse_bitwork(bitwork)?;
+            let weaker = target.prefix.as_deref().is_some_and(|target_prefix| {
+                is_share_bitwork((target_prefix, target.ext), (&prefix, ext))
+            });
+            if !weaker {
+                bail!(
+                    "share bitwork `{}` must be met by every commit meeting the target",
+                    bitwork
+                );
+            }
+            Some((prefix, ext))
+        }
+        None => None,
+    };
+    let workers = miner::job_workers(msg)?;
+    let pairs: Vec<(u64, u64)> = workers.iter().map(|w| (w.time, w.nonce)).collect();
+    let payloads = miner::worker_payloads(msg, &pairs)?;
+    let variants = match payloads.first() {
+        Some(payload) => {
+            miner::search_space(msg, payload)?;
+            miner::variants(payload)?
+        }
+        None => vec![Variant::default()],
+    };
+    let templates = payloads
+        .iter()
+        .map(|payload| commit_templates(payload, &variants))
+        .collect::<Result<Vec<_>>>()?;
+    let mut coordinator = Coordinator {
+        msg,
+        payloads,
+        variants,
+        templates,
+        share,
+        shares: HashSet::new(),
+        contributions: BTreeMap::new(),
+        pending: miner::pending_chunks(&workers).into(),
+        assigned: HashMap::new(),
+        connections: HashMap::new(),
+        completed: vec![vec![]; workers.len()],
+        searched: 0,
+        next_id: 0,
+    };
+
+    let (events, received) = mpsc::channel();
+    accept(listener, events);
+    let found = loop {
+        if token.is_cancelled()
+            || (coordinator.pending.is_empty() && coordinator.assigned.is_empty())
+        {
+            break None;
+        }
+        match received.recv_timeout(Duration::from_millis(100)) {
+            Ok(Event::Connected(id, stream)) => {
+                let connection = Connection {
+                    stream,
+                    name: format!("worker-{}", id),
+                    threads: 0,
+                    last_seen: Instant::now(),
+                    assignment: None,
+                    owners: HashSet::new(),
+                };
+                coordinator.connections.insert(id, connection);
+            }
+            Ok(Event::Received(id, message)) => {
+                if let Some(success) = coordinator.handle(id, message)? {
+                    break Some(success);
+                }
+            }
+            Ok(Event::Closed(id)) => coordinator.drop_connection(id),
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => bail!("listener closed"),
+        }
+        coordinator.drop_silent();
+        coordinator.dispatch();
+    };
+
+    for connection in coordinator.connections.values_mut() {
+        let _ = send(&mut connection.stream, &Message::Stop);
+        let _ = connection.stream.shutdown(Shutdown::Both);
+    }
+    let outcome = match found {
+        Some(success) => MineOutcome::Found(success),
+        None => {
+            let status = if token.is_cancelled() {
+                StopReason::Cancelled
+            } else {
+                StopReason::Exhausted
+            };
+            MineOutcome::Stopped(miner::stop_report(
+                status,
+                coordinator.searched,
+                workers,
+                coordinator.completed,
+            ))
+        }
+    };
+    let difficulty = coordinator
+        .share
+        .as_ref()
+        .map_or(0.0, |(prefix, ext)| bitwork_difficulty(prefix, *ext));
+    let contributions = coordinator
+        .contributions
+        .into_values()
+        .map(|contribution| Contribution {
+            work: contribution.shares as f64 * difficulty,
+            ..contribution
+        })
+        .collect();
+    Ok(Coordinated {
+        outcome,
+        contributions,
+    })
+}
+
+impl Coordinator<'_> {
+    /// Handles a message from connection `id`, returning the job's result
+    /// once a reported hit signs.
+    fn handle(&mut self, id: u64, message: Message) -> Result<Option<Success>> {
+        let Some(connection) = self.connections.get_mut(&id) else {
+            return Ok(None);
+        };
+        connection.last_seen = Instant::now();
+        match message {
+            Message::Hello { threads, name } => {
+                connection.threads = threads.clamp(1, MAX_WORKER_THREADS);
+                if let Some(name) = name {
+                    connection.name = name;
+                }
+            }
+            Message::Heartbeat => {}
+            Message::Done { id: done } => {
+                if let Some(work) = self.take_assignment(id, done) {
+                    let sequences: u64 = work
+                        .tasks
+                        .iter()
+                        .map(|task| (task.range.end - task.range.start) as u64 + 1)
+                        .sum();
+                    self.searched += sequences * self.variants.len() as u64;
+                    self.completed[work.owner].extend(work.tasks.iter().map(|task| task.range));
+                }
+            }
+            Message::Share {
+                id: assignment,
+                sequence,
+                variant,
+            } => {
+                let name = connection.name.clone();
+                let accepted = self.is_new_share(id, assignment, sequence, variant);
+                let contribution =
+                    self.contributions
+                        .entry(name.clone())
+                        .or_insert_with(|| Contribution {
+                            worker: name,
+                            shares: 0,
+                            rejected: 0,
+                            work: 0.0,
+                        });
+                if accepted {
+                    contribution.shares += 1;
+                } else {
+                    contribution.rejected += 1;
+                }
+            }
+            Message::Found {
+                id: found,
+                sequence,
+                variant,
+            } => {
+                let Some(work) = self.take_assignment(id, found) else {
+                    return Ok(None);
+                };
+                let in_range = work
+                    .tasks
+                    .iter()
+                    .any(|task| task.range.start <= sequence && sequence <= task.range.end);
+                let payload = Payload {
+                    variant,
+                    ..self.payloads[work.owner].clone()
+                };
+                if in_range && self.variants.contains(&variant) && predicate(sequence, &payload)? {
+                    let hit = Candidate { sequence, variant };
+                    return Ok(Some(miner::success(self.msg, &payload, hit)));
+                }
+                eprintln!(
+                    "Error: worker {} reported a false hit at sequence {}",
+                    id, sequence
+                );
+                requeue(&mut self.pending, work);
+                self.drop_connection(id);
+            }
+            Message::Setup(_) | Message::Assign(_) | Message::Stop => self.drop_connection(id),
+        }
+        Ok(None)
+    }
+
+    /// Hands pending work to every connected worker without an assignment.
+    fn dispatch(&mut self) {
+        let mut idle: Vec<u64> = self
+            .connections
+            .iter()
+            .filter(|(_, connection)| connection.threads > 0 && connection.assignment.is_none())
+            .map(|(id, _)| *id)
+            .collect();
+        idle.sort();
+        for id in idle {
+            let connection = self.connections.get_mut(&id).unwrap();
+            let tasks = next_assignment(
+                &mut self.pending,
+                connection.threads.saturating_mul(CHUNKS_PER_THREAD),
+            );
+            let (Some(first), Some(last)) = (tasks.first().copied(), tasks.last().copied()) else {
+                return;
+            };
+            let mut sent = Ok(());
+            if connection.owners.insert(first.owner) {
+                let payload = &self.payloads[first.owner];
+                let setup = OwnerSetup {
+                    owner: first.owner,
+                    prefix: payload.valid_prefix.clone(),
+                    ext: payload.valid_ext,
+                    share_prefix: self.share.as_ref().map(|(prefix, _)| prefix.clone()),
+                    share_ext: self.share.as_ref().and_then(|(_, ext)| *ext),
+                    templates: self.templates[first.owner]
+                        .iter()
+                        .map(|(template, _)| template.clone())
+                        .collect(),
+                };
+                sent = send(&mut connection.stream, &Message::Setup(setup));
+            }
+            let assignment = Assignment {
+                id: self.next_id,
+                owner: first.owner,
+                range: SequenceRange {
+                    start: first.range.start,
+                    end: last.range.end,
+                },
+            };
+            connection.assignment = Some(self.next_id);
+            if sent.is_ok() {
+                sent = send(&mut connection.stream, &Message::Assign(assignment));
+            }
+            self.assigned.insert(
+                self.next_id,
+                Assigned {
+                    connection: id,
+                    owner: first.owner,
+                    tasks,
+                },
+            );
+            self.next_id += 1;
+            if sent.is_err() {
+                self.drop_connection(id);
+            }
+        }
+    }
+
+    /// Checks a share against the commit template it claims, which takes one
+    /// txid rather than a signature. Each share is only credited once.
+    fn is_new_share(&mut self, connection: u64, id: u64, sequence: u32, variant: Variant) -> bool {
+        let (Some((prefix, ext)), Some(work)) = (&self.share, self.assigned.get(&id)) else {
+            return false;
+        };
+        let in_range = work
+            .tasks
+            .iter()
+            .any(|task| task.range.start <= sequence && sequence <= task.range.end);
+        let Some((_, template)) = self.templates[work.owner]
+            .iter()
+            .find(|(template, _)| template.variant == variant)
+        else {
+            return false;
+        };
+        let payload = &self.payloads[work.owner];
+        let txid = template.txid(HashBackend::detect(), sequence).to_string();
+        let grade = grade_bitwork(
+            &txid,
+            (&payload.valid_prefix, &payload.valid_ext),
+            (&Some(prefix.clone()), ext),
+        );
+        work.connection == connection
+            && in_range
+            && grade == BitworkGrade::Share
+            && self.shares.insert((work.owner, sequence, variant))
+    }
+
+    fn drop_silent(&mut self) {
+        let silent: Vec<u64> = self
+            .connections
+            .iter()
+            .filter(|(_, connection)| connection.last_seen.elapsed() > HEARTBEAT_TIMEOUT)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in silent {
+            self.drop_connection(id);
+        }
+    }
+
+    /// Removes assignment `id` if it belongs to `connection`.
+    fn take_assignment(&mut self, connection: u64, id: u64) -> Option<Assigned> {
+        if self.assigned.get(&id)?.connection != connection {
+            return None;
+        }
+        if let Some(connection) = self.connections.get_mut(&connection) {
+            connection.assignment = None;
+        }
+        self.assigned.remove(&id)
+    }
+
+    /// Closes a connection and puts its assignment back at the front.
+    fn drop_connection(&mut self, id: u64) {
+        let Some(connection) = self.connections.remove(&id) else {
+            return;
+        };
+        let _ = connection.stream.shutdown(Shutdown::Both);
+        if let Some(work) = connection
+            .assignment
+            .and_then(|id| self.assigned.remove(&id))
+        {
+            requeue(&mut self.pending, work);
+        }
+    }
+}
+
+/// Connects to a coordinator and searches what it assigns on `threads`
+/// threads until told to stop, a hit is reported or the connection closes.
+/// In pool mode shares are submitted as they are found.
+pub fn work(
+    addr: impl ToSocketAddrs,
+    hasher: HashBackend,
+    threads: usize,
+    name: Option<String>,
+) -> Result<()> {
+    let stream = TcpStream::connect(addr)?;
+    let writer = Arc::new(Mutex::new(stream.try_clone()?));
+    send(
+        &mut writer.lock().unwrap(),
+        &Message::Hello { threads, name },
+    )?;
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let (messages, received) = mpsc::channel();
+    {
+        let (stream, stop) = (stream.try_clone()?, stop.clone());
+        thread::spawn(move || {
+            for line in BufReader::new(stream).lines() {
+                match line.ok().and_then(|line| serde_json::from_str(&line).ok()) {
+                    Some(message @ (Message::Setup(_) | Message::Assign(_))) => {
+                        if messages.send(message).is_err() {
+                            break;
+                        }
+                    }
+                    Some(Message::Stop) | None => break,
+                    Some(_) => {}
+                }
+            }
+            stop.store(true, Ordering::Relaxed);
+        });
+    }
+    {
+        let (writer, stop) = (writer.clone(), stop.clone());
+        thread::spawn(move || {
+            let tick = Duration::from_millis(100);
+            let mut since = Duration::ZERO;
+            while !stop.load(Ordering::Relaxed) {
+                thread::sleep(tick);
+                since += tick;
+                if since >= HEARTBEAT_INTERVAL {
+                    since = Duration::ZERO;
+                    if send(&mut writer.lock().unwrap(), &Message::Heartbeat).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+    }
+
+    let result = search_assignments(&received, &writer, &stop, hasher, threads);
+    stop.store(true, Ordering::Relaxed);
+    let _ = stream.shutdown(Shutdown::Both);
+    result
+}
+
+/// Searches assignments as they arrive, with the setup of each owner cached
+/// for the connection.
+fn search_assignments(
+    received: &mpsc::Receiver<Message>,
+    writer: &Mutex<TcpStream>,
+    stop: &AtomicBool,
+    hasher: HashBackend,
+    threads: usize,
+) -> Result<()> {
+    let mut owners: HashMap<usize, (TemplateSearch, BitworkMatcher)> = HashMap::new();
+    while let Ok(message) = received.recv() {
+        let assignment = match message {
+            Message::Setup(setup) => {
+                owners.insert(setup.owner, owner_search(&setup, hasher)?);
+                continue;
+            }
+            Message::Assign(assignment) => assignment,
+            _ => continue,
+        };
+        let Some((search, target)) = owners.get(&assignment.owner) else {
+            bail!(
+                "assignment {} is for owner {} without a setup",
+                assignment.id,
+                assignment.owner
+            );
+        };
+        let tasks = miner::chunks(assignment.range.start, assignment.range.end)
+            .map(|range| Task { owner: 0, range })
+            .collect();
+        let scheduler = Scheduler {
+            threads,
+            batch_size: (BATCH_SIZE / search.variants() as u32).max(1),
+            ..Scheduler::default()
+        };
+        let run = scheduler.run(tasks, 1, stop, |_, batch| {
+            for hit in search.search(batch) {
+                if search
+                    .txid(&hit)
+                    .is_some_and(|txid| target.matches(&txid.to_byte_array()))
+                {
+                    return Some(hit);
+                }
+                let share = Message::Share {
+                    id: assignment.id,
+                    sequence: hit.sequence,
+                    variant: hit.variant,
+                };
+                let _ = send(&mut writer.lock().unwrap(), &share);
+            }
+            None
+        });
+        // After a hit the coordinator either ends the job or drops us.
+        if let Some(hit) = run.hit {
+            let found = Message::Found {
+                id: assignment.id,
+                sequence: hit.found.sequence,
+                variant: hit.found.variant,
+            };
+            return send(&mut writer.lock().unwrap(), &found);
+        }
+        if stop.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        send(
+            &mut writer.lock().unwrap(),
+            &Message::Done { id: assignment.id },
+        )?;
+    }
+    Ok(())
+}
+
+/// A search for the owner's share bitwork, or its target outside pool mode,
+/// and a matcher for the target.
+fn owner_search(
+    setup: &OwnerSetup,
+    hasher: HashBackend,
+) -> Result<(TemplateSearch, BitworkMatcher)> {
+    let templates = setup
+        .templates
+        .iter()
+        .map(|template| {
+            let tx = deserialize(&hex::decode(&template.tx)?)?;
+            Ok((template.variant, TxidTemplate::new(&tx, 0)?))
+        })
+        .collect::<Result<_>>()?;
+    let target = BitworkMatcher::new(&setup.prefix, &setup.ext)
+        .ok_or_else(|| anyhow!("owner {} has no usable bitwork", setup.owner))?;
+    let matcher = match &setup.share_prefix {
+        Some(_) => BitworkMatcher::new(&setup.share_prefix, &setup.share_ext)
+            .ok_or_else(|| anyhow!("owner {} has no usable share bitwork", setup.owner))?,
+        None => target.clone(),
+    };
+    Ok((
+        TemplateSearch::new(templates, Some(matcher), hasher),
+        target,
+    ))
+}
+
+/// The commit of `payload` for each variant, without its signature. Variants
+/// are patched into one unsigned commit rather than each built and signed.
+fn commit_templates(
+    payload: &Payload,
+    variants: &[Variant],
+) -> Result<Vec<(CommitTemplate, TxidTemplate)>> {
+    let unsigned = worker::unsigned_commit_tx(0, payload);
+    let base = TxidTemplate::new(&unsigned, 0)?;
+    let change = worker::change_vout(payload);
+    variants
+        .iter()
+        .map(|&variant| {
+            let mut tx = unsigned.clone();
+            worker::apply_variant(&mut tx, payload, variant);
+            let value = change.map(|vout| (vout, worker::change_value(payload, variant)));
+            let template = CommitTemplate {
+                variant,
+                tx: serialize_hex(&tx),
+            };
+            Ok((
+                template,
+                base.with_variant(variant.version, variant.lock_time, value)?,
+            ))
+        })
+        .collect()
+}
+
+/// Accepts connections on a background thread, reading each on its own.
+fn accept(listener: TcpListener, events: mpsc::Sender<Event>) {
+    thread::spawn(move || {
+        for (id, stream) in listener.incoming().enumerate() {
+            let Ok(stream) = stream else {
+                continue;
+            };
+            let id = id as u64;
+            let Ok(reader) = stream.try_clone() else {
+                continue;
+            };
+            if events.send(Event::Connected(id, stream)).is_err() {
+                break;
+            }
+            let events = events.clone();
+            thread::spawn(move || {
+                for line in BufReader::new(reader).lines() {
+                    let Some(message) = line.ok().and_then(|line| serde_json::from_str(&line).ok())
+                    else {
+                        break;
+                    };
+                    if events.send(Event::Received(id, message)).is_err() {
+                        return;
+                    }
+                }
+                let _ = events.send(Event::Closed(id));
+            });
+        }
+    });
+}
+
+fn send(stream: &mut TcpStream, message: &Message) -> Result<()> {
+    let mut line = serde_json::to_vec(message)?;
+    line.push(b'\n');
+    stream.write_all(&line)?;
+    Ok(())
+}
+
+/// Up to `chunks` consecutive pending chunks of one owner.
+fn next_assignment(pending: &mut VecDeque<Task>, chunks: usize) -> Vec<Task> {
+    let mut tasks: Vec<Task> = vec![];
+    while tasks.len() < chunks {
+        match (pending.front(), tasks.last()) {
+            (Some(next), Some(last))
+                if next.owner != last.owner
+                    || last.range.end.checked_add(1) != Some(next.range.start) =>
+            {
+                break
+            }
+            (Some(_), _) => tasks.push(pending.pop_front().unwrap()),
+            (None, _) => break,
+        }
+    }
+    tasks
+}
+
+fn requeue(pending: &mut VecDeque<Task>, work: Assigned) {
+    for task in work.tasks.into_iter().rev() {
+        pending.push_front(task);
+    }
+}
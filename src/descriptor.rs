@@ -0,0 +1,145 @@
+use anyhow::{anyhow, bail, Result};
+use bitcoin::{
+    taproot::{LeafVersion, TaprootSpendInfo},
+    Address, ScriptBuf, TapLeafHash,
+};
+
+use crate::{
+    miner::fan_out_nonce,
+    types::{CommitLeaf, CommitOutput, Payload, Root},
+    utils,
+};
+
+const INPUT_CHARSET: &str =
+    "0123456789()[],'/*abcdefgh@:$%{}IJKLMNOPQRSTUVWXYZ&+-.;<=>?!^_|~ijklmnopqrstuvwxyzABCDEFGH`#\"\\ ";
+const CHECKSUM_CHARSET: &[u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+/// Spending information for every envelope output of the commit built for
+/// `payload`, in output order, so watch-only wallets can track and spend
+/// them without rebuilding the envelope.
+pub fn commit_outputs(msg: &Root, payload: &Payload) -> Result<Vec<CommitOutput>> {
+    let script_pubkeys =
+        std::iter::once(&payload.fixed_output_script_pubkey).chain(&payload.fan_out_script_pubkeys);
+    script_pubkeys
+        .enumerate()
+        .map(|(output, script_pubkey)| {
+            let mut copied_data = payload.copied_data.clone();
+            copied_data.args.nonce = fan_out_nonce(copied_data.args.nonce, output);
+            let (spend_info, envelope) = utils::get_spend_info_by_copied_data(
+                &payload.secp,
+                &payload.xonly_pub_key,
+                &copied_data,
+                &msg.worker_options.op_type,
+                payload.refund_leaf.as_ref(),
+            );
+            let address =
+                Address::p2tr_tweaked(spend_info.output_key(), msg.network.clone().into());
+            if address.script_pubkey() != *script_pubkey {
+                bail!(
+                    "spend info of commit output {} does not match its script",
+                    output
+                );
+            }
+            let leaves: Vec<ScriptBuf> = std::iter::once(envelope)
+                .chain(payload.refund_leaf.clone())
+                .collect();
+            Ok(CommitOutput {
+                vout: output as u32,
+                address: address.to_string(),
+                descriptor: descriptor(&spend_info, &leaves)?,
+                watch_descriptor: with_checksum(format!("rawtr({})", spend_info.output_key()))?,
+                internal_key: spend_info.internal_key().to_string(),
+                merkle_root: spend_info
+                    .merkle_root()
+                    .map(|root| root.to_string())
+                    .unwrap_or_default(),
+                leaves: leaves
+                    .iter()
+                    .map(|script| leaf(&spend_info, script))
+                    .collect::<Result<_>>()?,
+            })
+        })
+        .collect()
+}
+
+fn leaf(spend_info: &TaprootSpendInfo, script: &ScriptBuf) -> Result<CommitLeaf> {
+    let control_block = spend_info
+        .control_block(&(script.clone(), LeafVersion::TapScript))
+        .ok_or_else(|| anyhow!("leaf missing from the taproot tree"))?;
+    Ok(CommitLeaf {
+        script: hex::encode(script.as_bytes()),
+        leaf_hash: TapLeafHash::from_script(script, LeafVersion::TapScript).to_string(),
+        control_block: hex::encode(control_block.serialize()),
+    })
+}
+
+/// `tr(KEY,rawleaf(HEX))`, or a `{..,..}` pair of them with a refund leaf,
+/// with its checksum. Tapscripts outside miniscript are given as `rawleaf`,
+/// which no wallet parses, so this documents the tree rather than imports it.
+fn descriptor(spend_info: &TaprootSpendInfo, leaves: &[ScriptBuf]) -> Result<String> {
+    let leaves: Vec<String> = leaves
+        .iter()
+        .map(|script| format!("rawleaf({})", hex::encode(script.as_bytes())))
+        .collect();
+    let tree = match &leaves[..] {
+        [leaf] => leaf.clone(),
+        _ => format!("{{{}}}", leaves.join(",")),
+    };
+    with_checksum(format!("tr({},{})", spend_info.internal_key(), tree))
+}
+
+fn with_checksum(descriptor: String) -> Result<String> {
+    let checksum = descriptor_checksum(&descriptor)?;
+    Ok(format!("{}#{}", descriptor, checksum))
+}
+
+/// The BIP-380 checksum of `descriptor`.
+pub fn descriptor_checksum(descriptor: &str) -> Result<String> {
+    fn polymod(c: u64, value: u64) -> u64 {
+        let top = c >> 35;
+        let mut c = ((c & 0x7_ffff_ffff) << 5) ^ value;
+        for (bit, generator) in [
+            0xf5dee51989,
+            0xa9fdca3312,
+            0x1bab10e32d,
+            0x3706b1677a,
+            0x644d626ffd,
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            if top >> bit & 1 == 1 {
+                c ^= generator;
+            }
+        }
+        c
+    }
+
+    let mut c = 1;
+    let mut class = 0;
+    let mut class_count = 0;
+    for ch in descriptor.chars() {
+        let position = INPUT_CHARSET
+            .find(ch)
+            .ok_or_else(|| anyhow!("invalid descriptor character {:?}", ch))?
+            as u64;
+        c = polymod(c, position & 31);
+        class = class * 3 + (position >> 5);
+        class_count += 1;
+        if class_count == 3 {
+            c = polymod(c, class);
+            class = 0;
+            class_count = 0;
+        }
+    }
+    if class_count > 0 {
+        c = polymod(c, class);
+    }
+    for _ in 0..8 {
+        c = polymod(c, 0);
+    }
+    c ^= 1;
+    Ok((0..8)
+        .map(|j| CHECKSUM_CHARSET[(c >> (5 * (7 - j)) & 31) as usize] as char)
+        .collect())
+}
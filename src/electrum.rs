@@ -0,0 +1,150 @@
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use bitcoin::{
+    consensus::encode::serialize_hex,
+    hashes::{sha256, Hash},
+    Script, Transaction, Txid,
+};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::{json, Value};
+
+use crate::{
+    backend::ServerError,
+    types::{ElectrumApi, FundingUtxo},
+};
+
+/// Client for the atomicals-electrumx HTTP proxy described by
+/// `WorkerOptions::electrum_api`.
+#[derive(Debug, Clone)]
+pub struct ElectrumClient {
+    base_url: String,
+    use_post: bool,
+    agent: ureq::Agent,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProxyResponse {
+    success: Option<bool>,
+    response: Option<Value>,
+    message: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Unspent {
+    tx_hash: String,
+    tx_pos: u32,
+    value: u64,
+}
+
+impl ElectrumClient {
+    pub fn new(api: &ElectrumApi) -> Result<Self> {
+        if api.base_url.is_empty() {
+            bail!("electrumApi.baseUrl is not set");
+        }
+        Ok(ElectrumClient {
+            base_url: api.base_url.trim_end_matches('/').to_string(),
+            use_post: api.use_post,
+            agent: ureq::AgentBuilder::new()
+                .timeout(Duration::from_secs(30))
+                .build(),
+        })
+    }
+
+    /// Calls an electrumx method, as a JSON POST body or a GET query string
+    /// depending on `use_post`.
+    pub fn call<T: DeserializeOwned>(&self, method: &str, params: Value) -> Result<T> {
+        let url = format!("{}/{}", self.base_url, method);
+        let response = if self.use_post {
+            self.agent.post(&url).send_json(json!({ "params": params }))
+        } else {
+            self.agent
+                .get(&url)
+                .query("params", &params.to_string())
+                .call()
+        };
+        let body: ProxyResponse = match response {
+            Ok(response) => response.into_json()?,
+            Err(ureq::Error::Status(code, response)) => {
+                let text = response.into_string().unwrap_or_default();
+                bail!("{} failed with HTTP {}: {}", method, code, text);
+            }
+            Err(err) => return Err(err).with_context(|| format!("{} failed", method)),
+        };
+        if body.success == Some(false) {
+            return Err(ServerError {
+                method: method.to_string(),
+                code: None,
+                message: body.message.unwrap_or_else(|| "unknown error".to_string()),
+            }
+            .into());
+        }
+        let response = body
+            .response
+            .ok_or_else(|| anyhow!("{} returned no response", method))?;
+        Ok(serde_json::from_value(response)?)
+    }
+
+    /// Lists the unspent outputs locked by `script_pubkey`.
+    pub fn list_unspent(&self, script_pubkey: &Script) -> Result<Vec<FundingUtxo>> {
+        let unspent: Vec<Unspent> = self.call(
+            "blockchain.scripthash.listunspent",
+            json!([script_hash(script_pubkey)]),
+        )?;
+        Ok(unspent
+            .into_iter()
+            .map(|utxo| FundingUtxo {
+                txid: utxo.tx_hash.clone(),
+                tx_id: utxo.tx_hash,
+                output_index: utxo.tx_pos as u64,
+                index: utxo.tx_pos,
+                vout: utxo.tx_pos,
+                value: utxo.value,
+            })
+            .collect())
+    }
+
+    pub fn get_by_ticker(&self, ticker: &str) -> Result<Value> {
+        self.call("blockchain.atomicals.get_by_ticker", json!([ticker]))
+    }
+
+    /// `blockchain.estimatefee` answers in BTC/kB, or -1 when it has no data.
+    pub fn fee_rate(&self, target_blocks: u16) -> Result<u64> {
+        let btc_per_kb: f64 = self.call("blockchain.estimatefee", json!([target_blocks]))?;
+        if btc_per_kb <= 0.0 {
+            bail!(
+                "electrum server has no fee estimate for {} blocks",
+                target_blocks
+            );
+        }
+        Ok((btc_per_kb * 100_000.0).ceil() as u64)
+    }
+
+    /// Unknown transactions make electrumx return an error, reported as `None`.
+    pub fn confirmations(&self, txid: &Txid) -> Result<Option<u32>> {
+        let tx: Result<Value> = self.call(
+            "blockchain.transaction.get",
+            json!([txid.to_string(), true]),
+        );
+        match tx {
+            Ok(tx) => Ok(Some(tx["confirmations"].as_u64().unwrap_or(0) as u32)),
+            Err(err) if err.is::<ServerError>() => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    pub fn broadcast(&self, tx: &Transaction) -> Result<Txid> {
+        let txid: String = self.call(
+            "blockchain.transaction.broadcast",
+            json!([serialize_hex(tx)]),
+        )?;
+        Ok(txid.parse()?)
+    }
+}
+
+/// The electrum script hash: sha256 of the script, byte-reversed.
+pub fn script_hash(script_pubkey: &Script) -> String {
+    let mut hash = sha256::Hash::hash(script_pubkey.as_bytes()).to_byte_array();
+    hash.reverse();
+    hex::encode(hash)
+}
@@ -0,0 +1,123 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use anyhow::{anyhow, bail, Context, Result};
+use bitcoin::{consensus::encode::serialize_hex, Address, Network, Script, Transaction, Txid};
+use serde::{de::DeserializeOwned, Deserialize};
+
+use crate::{backend::ChainBackend, types::FundingUtxo};
+
+/// Client for an Esplora or mempool.space compatible REST API.
+#[derive(Debug, Clone)]
+pub struct EsploraClient {
+    base_url: String,
+    network: Network,
+    agent: ureq::Agent,
+}
+
+#[derive(Debug, Deserialize)]
+struct TxStatus {
+    confirmed: bool,
+    block_height: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Utxo {
+    txid: String,
+    vout: u32,
+    value: u64,
+}
+
+impl EsploraClient {
+    pub fn new(base_url: &str, network: Network) -> Self {
+        EsploraClient {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            network,
+            agent: ureq::AgentBuilder::new()
+                .timeout(Duration::from_secs(30))
+                .build(),
+        }
+    }
+
+    fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T> {
+        let url = format!("{}{}", self.base_url, path);
+        match self.agent.get(&url).call() {
+            Ok(response) => Ok(response.into_json()?),
+            Err(ureq::Error::Status(code, response)) => {
+                let text = response.into_string().unwrap_or_default();
+                bail!("GET {} failed with HTTP {}: {}", path, code, text);
+            }
+            Err(err) => Err(err).with_context(|| format!("GET {} failed", path)),
+        }
+    }
+
+    /// Fee estimates in sat/vB keyed by confirmation target.
+    pub fn fee_estimates(&self) -> Result<BTreeMap<u16, f64>> {
+        let estimates: BTreeMap<String, f64> = self.get("/fee-estimates")?;
+        estimates
+            .into_iter()
+            .map(|(target, rate)| Ok((target.parse()?, rate)))
+            .collect()
+    }
+}
+
+/// Picks the estimate for the largest target not above `target_blocks`,
+/// falling back to the fastest one, rounded up to whole sat/vB.
+pub fn fee_rate_for_target(estimates: &BTreeMap<u16, f64>, target_blocks: u16) -> Result<u64> {
+    let rate = estimates
+        .range(..=target_blocks)
+        .next_back()
+        .or_else(|| estimates.iter().next())
+        .map(|(_, rate)| *rate)
+        .ok_or_else(|| anyhow!("no fee estimates available"))?;
+    Ok(rate.ceil().max(1.0) as u64)
+}
+
+impl ChainBackend for EsploraClient {
+    fn list_unspent(&self, script_pubkey: &Script) -> Result<Vec<FundingUtxo>> {
+        let address = Address::from_script(script_pubkey, self.network)?;
+        let utxos: Vec<Utxo> = self.get(&format!("/address/{}/utxo", address))?;
+        Ok(utxos
+            .into_iter()
+            .map(|utxo| FundingUtxo {
+                txid: utxo.txid.clone(),
+                tx_id: utxo.txid,
+                output_index: utxo.vout as u64,
+                index: utxo.vout,
+                vout: utxo.vout,
+                value: utxo.value,
+            })
+            .collect())
+    }
+
+    fn fee_rate(&self, target_blocks: u16) -> Result<u64> {
+        fee_rate_for_target(&self.fee_estimates()?, target_blocks)
+    }
+
+    fn confirmations(&self, txid: &Txid) -> Result<Option<u32>> {
+        let url = format!("{}/tx/{}/status", self.base_url, txid);
+        let status: TxStatus = match self.agent.get(&url).call() {
+            Ok(response) => response.into_json()?,
+            Err(ureq::Error::Status(404, _)) => return Ok(None),
+            Err(err) => return Err(err).context("transaction status lookup failed"),
+        };
+        match (status.confirmed, status.block_height) {
+            (true, Some(height)) => {
+                let tip: u32 = self.get("/blocks/tip/height")?;
+                Ok(Some(tip.saturating_sub(height) + 1))
+            }
+            _ => Ok(Some(0)),
+        }
+    }
+
+    fn broadcast(&self, tx: &Transaction) -> Result<Txid> {
+        let url = format!("{}/tx", self.base_url);
+        match self.agent.post(&url).send_string(&serialize_hex(tx)) {
+            Ok(response) => Ok(response.into_string()?.trim().parse()?),
+            Err(ureq::Error::Status(code, response)) => {
+                let text = response.into_string().unwrap_or_default();
+                bail!("broadcast failed with HTTP {}: {}", code, text);
+            }
+            Err(err) => Err(err).context("broadcast failed"),
+        }
+    }
+}
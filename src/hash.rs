@@ -0,0 +1,752 @@
+use std::{fmt, str::FromStr, sync::OnceLock};
+
+use anyhow::{anyhow, bail, Result};
+use bitcoin::{consensus::serialize, hashes::Hash, Amount, Sequence, Transaction, Txid};
+
+const H0: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+const K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+/// Preference order used by [`HashBackend::detect`]: sixteen AVX-512 lanes
+/// outrun SHA-NI, which roughly matches eight AVX2 lanes.
+const PREFERENCE: [HashBackend; 6] = [
+    HashBackend::Avx512,
+    HashBackend::ShaNi,
+    HashBackend::ArmSha,
+    HashBackend::Avx2,
+    HashBackend::Sse2,
+    HashBackend::Scalar,
+];
+
+/// An implementation of double-SHA256 over several equal-length messages.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum HashBackend {
+    Scalar,
+    /// x86 SHA extensions, one message at a time.
+    ShaNi,
+    /// 4 messages per pass.
+    Sse2,
+    /// 8 messages per pass.
+    Avx2,
+    /// 16 messages per pass.
+    Avx512,
+    /// ARMv8 SHA2 instructions, one message at a time.
+    ArmSha,
+}
+
+impl HashBackend {
+    pub const ALL: [HashBackend; 6] = PREFERENCE;
+
+    pub fn name(self) -> &'static str {
+        match self {
+            HashBackend::Scalar => "scalar",
+            HashBackend::ShaNi => "sha-ni",
+            HashBackend::Sse2 => "sse2",
+            HashBackend::Avx2 => "avx2",
+            HashBackend::Avx512 => "avx512",
+            HashBackend::ArmSha => "armv8-sha",
+        }
+    }
+
+    /// Messages hashed per pass.
+    pub fn lanes(self) -> usize {
+        match self {
+            HashBackend::Scalar | HashBackend::ShaNi | HashBackend::ArmSha => 1,
+            HashBackend::Sse2 => 4,
+            HashBackend::Avx2 => 8,
+            HashBackend::Avx512 => 16,
+        }
+    }
+
+    /// Whether the running CPU has the instructions this backend needs.
+    pub fn is_supported(self) -> bool {
+        match self {
+            HashBackend::Scalar => true,
+            #[cfg(target_arch = "x86_64")]
+            HashBackend::ShaNi => {
+                is_x86_feature_detected!("sha")
+                    && is_x86_feature_detected!("sse4.1")
+                    && is_x86_feature_detected!("ssse3")
+            }
+            #[cfg(target_arch = "x86_64")]
+            HashBackend::Sse2 => is_x86_feature_detected!("sse2"),
+            #[cfg(target_arch = "x86_64")]
+            HashBackend::Avx2 => is_x86_feature_detected!("avx2"),
+            #[cfg(target_arch = "x86_64")]
+            HashBackend::Avx512 => is_x86_feature_detected!("avx512f"),
+            #[cfg(target_arch = "aarch64")]
+            HashBackend::ArmSha => std::arch::is_aarch64_feature_detected!("sha2"),
+            #[allow(unreachable_patterns)]
+            _ => false,
+        }
+    }
+
+    /// Checks the backend against known vectors, and every lane against the
+    /// scalar reference, before it is trusted with a search.
+    pub fn self_test(self) -> Result<()> {
+        if !self.is_supported() {
+            bail!("{} is not supported by this CPU", self);
+        }
+        let genesis = bitcoin::blockdata::constants::genesis_block(bitcoin::Network::Bitcoin);
+        let coinbase = serialize(&genesis.txdata[0]);
+        let vectors: [(&[u8], &str); 3] = [
+            (
+                b"",
+                "5df6e0e2761359d30a8275058e299fcc0381534545f55cf43e41983f5d4c9456",
+            ),
+            (
+                b"abc",
+                "4f8b42c22dd3729b519ba6f68d2da7cc5b2d606d05daed5ad5128cc03e6c6358",
+            ),
+            (
+                &coinbase,
+                "3ba3edfd7a7b12b27ac72c3e67768f617fc81bc3888a51323a9fb8aa4b1e5e4a",
+            ),
+        ];
+        for (message, expected) in vectors {
+            let mut out = vec![[0; 32]; self.lanes()];
+            self.sha256d(&vec![message; self.lanes()], &mut out);
+            for digest in out {
+                if hex::encode(digest) != expected {
+                    bail!("{} hashed a {} byte vector wrongly", self, message.len());
+                }
+            }
+        }
+        // Lanes must not bleed into each other, whatever the block count.
+        for len in [0, 55, 56, 63, 64, 119, 120, 200] {
+            let messages: Vec<Vec<u8>> = (0..self.lanes())
+                .map(|lane| (0..len).map(|i| (i * 7 + lane * 31) as u8).collect())
+                .collect();
+            let refs: Vec<&[u8]> = messages.iter().map(Vec::as_slice).collect();
+            let mut out = vec![[0; 32]; self.lanes()];
+            self.sha256d(&refs, &mut out);
+            for (message, digest) in messages.iter().zip(out) {
+                let expected = bitcoin::hashes::sha256d::Hash::hash(message).to_byte_array();
+                if digest != expected {
+                    bail!("{} disagrees with the reference on {} bytes", self, len);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The preferred backend that is supported and passes its self-test.
+    /// Detection runs once per process.
+    pub fn detect() -> HashBackend {
+        static DETECTED: OnceLock<HashBackend> = OnceLock::new();
+        *DETECTED.get_or_init(|| {
+            PREFERENCE
+                .into_iter()
+                .find(|backend| backend.self_test().is_ok())
+                .unwrap_or(HashBackend::Scalar)
+        })
+    }
+
+    /// Double-SHA256 of each message. All messages must have the same length.
+    pub fn sha256d(self, messages: &[&[u8]], out: &mut [[u8; 32]]) {
+        assert_eq!(messages.len(), out.len());
+        let padded: Vec<Vec<u8>> = messages.iter().map(|message| pad(message)).collect();
+        for (inputs, out) in padded
+            .chunks(self.lanes())
+            .zip(out.chunks_mut(self.lanes()))
+        {
+            let mut lanes: Vec<&[u8]> = inputs.iter().map(Vec::as_slice).collect();
+            lanes.resize(self.lanes(), lanes[0]);
+            self.sha256d_padded(&lanes, out);
+        }
+    }
+
+    /// Hashes one pass of `lanes()` padded inputs of equal length, writing as
+    /// many digests as `out` holds.
+    fn sha256d_padded(self, inputs: &[&[u8]], out: &mut [[u8; 32]]) {
+        assert!(self.is_supported(), "{} is not supported by this CPU", self);
+        match self {
+            HashBackend::Scalar => out[0] = sha256d_single(inputs[0], compress_scalar),
+            #[cfg(target_arch = "x86_64")]
+            HashBackend::ShaNi => {
+                out[0] = sha256d_single(inputs[0], |state, blocks| unsafe {
+                    shani::compress(state, blocks)
+                })
+            }
+            #[cfg(target_arch = "x86_64")]
+            HashBackend::Sse2 => unsafe { sse2::sha256d(inputs.try_into().unwrap(), out) },
+            #[cfg(target_arch = "x86_64")]
+            HashBackend::Avx2 => unsafe { avx2::sha256d(inputs.try_into().unwrap(), out) },
+            #[cfg(target_arch = "x86_64")]
+            HashBackend::Avx512 => unsafe { avx512::sha256d(inputs.try_into().unwrap(), out) },
+            #[cfg(target_arch = "aarch64")]
+            HashBackend::ArmSha => {
+                out[0] = sha256d_single(inputs[0], |state, blocks| unsafe {
+                    armsha::compress(state, blocks)
+                })
+            }
+            #[allow(unreachable_patterns)]
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl fmt::Display for HashBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for HashBackend {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        HashBackend::ALL
+            .into_iter()
+            .find(|backend| backend.name() == s)
+            .ok_or_else(|| {
+                let names: Vec<_> = HashBackend::ALL.iter().map(|b| b.name()).collect();
+                anyhow!(
+                    "unknown hash backend `{}`, expected one of {}",
+                    s,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+/// The txid preimage of a transaction, padded for SHA256, with the position
+/// of one input's `nSequence` so candidates can be hashed without rebuilding
+/// or signing the transaction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TxidTemplate {
+    padded: Vec<u8>,
+    sequence_offset: usize,
+    lock_time_offset: usize,
+    value_offsets: Vec<usize>,
+}
+
+impl TxidTemplate {
+    pub fn new(tx: &Transaction, input: usize) -> Result<Self> {
+        let mut tx = tx.clone();
+        for txin in &mut tx.input {
+            txin.witness.clear();
+        }
+        let txin = tx
+            .input
+            .get_mut(input)
+            .ok_or_else(|| anyhow!("transaction has no input {}", input))?;
+        txin.sequence = Sequence(0);
+        let low = serialize(&tx);
+        tx.input[input].sequence = Sequence(u32::MAX);
+        let high = serialize(&tx);
+        let sequence_offset = first_difference(&low, &high)
+            .ok_or_else(|| anyhow!("nSequence not found in the serialization"))?;
+        tx.input[input].sequence = Sequence(0);
+        let value_offsets = (0..tx.output.len())
+            .map(|output| {
+                let mut other = tx.clone();
+                other.output[output].value = Amount::from_sat(!tx.output[output].value.to_sat());
+                first_difference(&low, &serialize(&other))
+                    .ok_or_else(|| anyhow!("output {} not found in the serialization", output))
+            })
+            .collect::<Result<_>>()?;
+        Ok(TxidTemplate {
+            padded: pad(&low),
+            sequence_offset,
+            // A transaction without witnesses ends with its lock time.
+            lock_time_offset: low.len() - 4,
+            value_offsets,
+        })
+    }
+
+    /// The template of the same transaction with another version, lock time
+    /// and value of output `value.0`, patched rather than rebuilt.
+    pub fn with_variant(
+        &self,
+        version: i32,
+        lock_time: u32,
+        value: Option<(usize, Amount)>,
+    ) -> Result<Self> {
+        let mut template = self.clone();
+        template.padded[..4].copy_from_slice(&version.to_le_bytes());
+        template.padded[self.lock_time_offset..self.lock_time_offset + 4]
+            .copy_from_slice(&lock_time.to_le_bytes());
+        if let Some((output, value)) = value {
+            let offset = *self
+                .value_offsets
+                .get(output)
+                .ok_or_else(|| anyhow!("transaction has no output {}", output))?;
+            template.padded[offset..offset + 8].copy_from_slice(&value.to_sat().to_le_bytes());
+        }
+        Ok(template)
+    }
+
+    /// Txid, in internal byte order, of the candidate with `nSequence`
+    /// `first + i` for each slot `i` of `out`.
+    pub fn txids(&self, backend: HashBackend, first: u32, out: &mut [[u8; 32]]) {
+        let lanes = backend.lanes();
+        let mut buffers = vec![self.padded.clone(); lanes];
+        for (group, out) in out.chunks_mut(lanes).enumerate() {
+            for (lane, buffer) in buffers.iter_mut().enumerate() {
+                let sequence = first.wrapping_add((group * lanes + lane) as u32);
+                buffer[self.sequence_offset..self.sequence_offset + 4]
+                    .copy_from_slice(&sequence.to_le_bytes());
+            }
+            let inputs: Vec<&[u8]> = buffers.iter().map(Vec::as_slice).collect();
+            backend.sha256d_padded(&inputs, out);
+        }
+    }
+
+    pub fn txid(&self, backend: HashBackend, sequence: u32) -> Txid {
+        let mut out = [[0; 32]];
+        self.txids(backend, sequence, &mut out);
+        Txid::from_byte_array(out[0])
+    }
+}
+
+fn first_difference(a: &[u8], b: &[u8]) -> Option<usize> {
+    a.iter().zip(b).position(|(a, b)| a != b)
+}
+
+/// Appends the SHA256 padding: a one bit, zeros, and the bit length.
+fn pad(message: &[u8]) -> Vec<u8> {
+    let mut padded = message.to_vec();
+    padded.push(0x80);
+    while padded.len() % 64 != 56 {
+        padded.push(0);
+    }
+    padded.extend_from_slice(&(message.len() as u64 * 8).to_be_bytes());
+    padded
+}
+
+fn sha256d_single(padded: &[u8], compress: impl Fn(&mut [u32; 8], &[u8])) -> [u8; 32] {
+    let mut state = H0;
+    compress(&mut state, padded);
+    let mut block = [0u8; 64];
+    for (chunk, word) in block.chunks_exact_mut(4).zip(state) {
+        chunk.copy_from_slice(&word.to_be_bytes());
+    }
+    block[32] = 0x80;
+    block[62] = 0x01; // 256 bits
+    let mut state = H0;
+    compress(&mut state, &block);
+    let mut digest = [0u8; 32];
+    for (chunk, word) in digest.chunks_exact_mut(4).zip(state) {
+        chunk.copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
+
+fn compress_scalar(state: &mut [u32; 8], blocks: &[u8]) {
+    for block in blocks.chunks_exact(64) {
+        let mut w = [0u32; 64];
+        for (t, word) in block.chunks_exact(4).enumerate() {
+            w[t] = u32::from_be_bytes(word.try_into().unwrap());
+        }
+        for t in 16..64 {
+            let s0 = w[t - 15].rotate_right(7) ^ w[t - 15].rotate_right(18) ^ (w[t - 15] >> 3);
+            let s1 = w[t - 2].rotate_right(17) ^ w[t - 2].rotate_right(19) ^ (w[t - 2] >> 10);
+            w[t] = w[t - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[t - 7])
+                .wrapping_add(s1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = *state;
+        for t in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let t1 = h
+                .wrapping_add(s1)
+                .wrapping_add(ch)
+                .wrapping_add(K[t])
+                .wrapping_add(w[t]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(maj);
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+        for (word, value) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *word = word.wrapping_add(value);
+        }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod shani {
+    use std::arch::x86_64::*;
+
+    use super::K;
+
+    #[target_feature(enable = "sha,sse2,ssse3,sse4.1")]
+    unsafe fn schedule(v0: __m128i, v1: __m128i, v2: __m128i, v3: __m128i) -> __m128i {
+        let t1 = _mm_sha256msg1_epu32(v0, v1);
+        let t2 = _mm_alignr_epi8::<4>(v3, v2);
+        _mm_sha256msg2_epu32(_mm_add_epi32(t1, t2), v3)
+    }
+
+    #[target_feature(enable = "sha,sse2,ssse3,sse4.1")]
+    pub(super) unsafe fn compress(state: &mut [u32; 8], blocks: &[u8]) {
+        let mask = _mm_set_epi64x(0x0c0d_0e0f_0809_0a0b, 0x0405_0607_0001_0203);
+        let dcba = _mm_loadu_si128(state.as_ptr() as *const __m128i);
+        let efgh = _mm_loadu_si128(state.as_ptr().add(4) as *const __m128i);
+        let cdab = _mm_shuffle_epi32::<0xb1>(dcba);
+        let efgh = _mm_shuffle_epi32::<0x1b>(efgh);
+        let mut abef = _mm_alignr_epi8::<8>(cdab, efgh);
+        let mut cdgh = _mm_blend_epi16::<0xf0>(efgh, cdab);
+
+        for block in blocks.chunks_exact(64) {
+            let (abef_save, cdgh_save) = (abef, cdgh);
+            let mut w = [_mm_setzero_si128(); 4];
+            for (i, word) in w.iter_mut().enumerate() {
+                let data = _mm_loadu_si128(block.as_ptr().add(16 * i) as *const __m128i);
+                *word = _mm_shuffle_epi8(data, mask);
+            }
+            for i in 0..16 {
+                if i >= 4 {
+                    w[i % 4] = schedule(w[i % 4], w[(i + 1) % 4], w[(i + 2) % 4], w[(i + 3) % 4]);
+                }
+                let k = _mm_loadu_si128(K.as_ptr().add(4 * i) as *const __m128i);
+                let t1 = _mm_add_epi32(w[i % 4], k);
+                cdgh = _mm_sha256rnds2_epu32(cdgh, abef, t1);
+                abef = _mm_sha256rnds2_epu32(abef, cdgh, _mm_shuffle_epi32::<0x0e>(t1));
+            }
+            abef = _mm_add_epi32(abef, abef_save);
+            cdgh = _mm_add_epi32(cdgh, cdgh_save);
+        }
+
+        let feba = _mm_shuffle_epi32::<0x1b>(abef);
+        let dchg = _mm_shuffle_epi32::<0xb1>(cdgh);
+        let dcba = _mm_blend_epi16::<0xf0>(feba, dchg);
+        let hgef = _mm_alignr_epi8::<8>(dchg, feba);
+        _mm_storeu_si128(state.as_mut_ptr() as *mut __m128i, dcba);
+        _mm_storeu_si128(state.as_mut_ptr().add(4) as *mut __m128i, hgef);
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod armsha {
+    use std::arch::aarch64::*;
+
+    use super::K;
+
+    #[target_feature(enable = "sha2")]
+    pub(super) unsafe fn compress(state: &mut [u32; 8], blocks: &[u8]) {
+        let mut abcd = vld1q_u32(state.as_ptr());
+        let mut efgh = vld1q_u32(state.as_ptr().add(4));
+        for block in blocks.chunks_exact(64) {
+            let (abcd_save, efgh_save) = (abcd, efgh);
+            let mut w = [vdupq_n_u32(0); 4];
+            for (i, word) in w.iter_mut().enumerate() {
+                *word = vreinterpretq_u32_u8(vrev32q_u8(vld1q_u8(block.as_ptr().add(16 * i))));
+            }
+            for i in 0..16 {
+                if i >= 4 {
+                    w[i % 4] = vsha256su1q_u32(
+                        vsha256su0q_u32(w[i % 4], w[(i + 1) % 4]),
+                        w[(i + 2) % 4],
+                        w[(i + 3) % 4],
+                    );
+                }
+                let t = vaddq_u32(w[i % 4], vld1q_u32(K.as_ptr().add(4 * i)));
+                let abcd_prev = abcd;
+                abcd = vsha256hq_u32(abcd_prev, efgh, t);
+                efgh = vsha256h2q_u32(efgh, abcd_prev, t);
+            }
+            abcd = vaddq_u32(abcd, abcd_save);
+            efgh = vaddq_u32(efgh, efgh_save);
+        }
+        vst1q_u32(state.as_mut_ptr(), abcd);
+        vst1q_u32(state.as_mut_ptr().add(4), efgh);
+    }
+}
+
+/// The multi-lane compression function, shared by every vector width. Each
+/// module provides `LANES`, the vector type `V` and the lane-wise operations
+/// used here.
+#[cfg(target_arch = "x86_64")]
+macro_rules! multi_lane {
+    ($feature:literal) => {
+        #[target_feature(enable = $feature)]
+        unsafe fn compress(state: &mut [V; 8], block: &[V; 16]) {
+            let mut w = [set1(0); 64];
+            w[..16].copy_from_slice(block);
+            for t in 16..64 {
+                let s0 = xor(
+                    xor(rotr::<7, 25>(w[t - 15]), rotr::<18, 14>(w[t - 15])),
+                    shr::<3>(w[t - 15]),
+                );
+                let s1 = xor(
+                    xor(rotr::<17, 15>(w[t - 2]), rotr::<19, 13>(w[t - 2])),
+                    shr::<10>(w[t - 2]),
+                );
+                w[t] = add(add(w[t - 16], s0), add(w[t - 7], s1));
+            }
+            let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = *state;
+            for t in 0..64 {
+                let s1 = xor(xor(rotr::<6, 26>(e), rotr::<11, 21>(e)), rotr::<25, 7>(e));
+                let ch = xor(and(e, f), andnot(e, g));
+                let t1 = add(add(h, s1), add(add(ch, set1(K[t])), w[t]));
+                let s0 = xor(xor(rotr::<2, 30>(a), rotr::<13, 19>(a)), rotr::<22, 10>(a));
+                let maj = or(and(a, b), and(c, xor(a, b)));
+                let t2 = add(s0, maj);
+                h = g;
+                g = f;
+                f = e;
+                e = add(d, t1);
+                d = c;
+                c = b;
+                b = a;
+                a = add(t1, t2);
+            }
+            for (word, value) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+                *word = add(*word, value);
+            }
+        }
+
+        /// Double-SHA256 of `LANES` padded inputs of equal length.
+        #[target_feature(enable = $feature)]
+        pub(super) unsafe fn sha256d(inputs: &[&[u8]; LANES], out: &mut [[u8; 32]]) {
+            let mut state = H0.map(|word| set1(word));
+            for offset in (0..inputs[0].len()).step_by(64) {
+                let mut block = [set1(0); 16];
+                for (t, word) in block.iter_mut().enumerate() {
+                    let mut words = [0u32; LANES];
+                    for (lane, input) in inputs.iter().enumerate() {
+                        let at = offset + 4 * t;
+                        words[lane] = u32::from_be_bytes(input[at..at + 4].try_into().unwrap());
+                    }
+                    *word = load(&words);
+                }
+                compress(&mut state, &block);
+            }
+            // The second pass hashes the 32 byte digest: one padded block.
+            let mut block = [set1(0); 16];
+            block[..8].copy_from_slice(&state);
+            block[8] = set1(0x8000_0000);
+            block[15] = set1(256);
+            let mut digest = H0.map(|word| set1(word));
+            compress(&mut digest, &block);
+            let words = digest.map(|word| store(word));
+            for (lane, out) in out.iter_mut().take(LANES).enumerate() {
+                for (chunk, word) in out.chunks_exact_mut(4).zip(&words) {
+                    chunk.copy_from_slice(&word[lane].to_be_bytes());
+                }
+            }
+        }
+    };
+}
+
+#[cfg(target_arch = "x86_64")]
+mod sse2 {
+    use std::arch::x86_64::*;
+
+    use super::{H0, K};
+
+    const LANES: usize = 4;
+    type V = __m128i;
+
+    #[inline]
+    #[target_feature(enable = "sse2")]
+    fn set1(x: u32) -> V {
+        _mm_set1_epi32(x as i32)
+    }
+    #[inline]
+    #[target_feature(enable = "sse2")]
+    fn load(words: &[u32; LANES]) -> V {
+        unsafe { _mm_loadu_si128(words.as_ptr() as *const V) }
+    }
+    #[inline]
+    #[target_feature(enable = "sse2")]
+    fn store(v: V) -> [u32; LANES] {
+        let mut words = [0; LANES];
+        unsafe { _mm_storeu_si128(words.as_mut_ptr() as *mut V, v) };
+        words
+    }
+    #[inline]
+    #[target_feature(enable = "sse2")]
+    fn add(a: V, b: V) -> V {
+        _mm_add_epi32(a, b)
+    }
+    #[inline]
+    #[target_feature(enable = "sse2")]
+    fn xor(a: V, b: V) -> V {
+        _mm_xor_si128(a, b)
+    }
+    #[inline]
+    #[target_feature(enable = "sse2")]
+    fn and(a: V, b: V) -> V {
+        _mm_and_si128(a, b)
+    }
+    /// `!a & b`
+    #[inline]
+    #[target_feature(enable = "sse2")]
+    fn andnot(a: V, b: V) -> V {
+        _mm_andnot_si128(a, b)
+    }
+    #[inline]
+    #[target_feature(enable = "sse2")]
+    fn or(a: V, b: V) -> V {
+        _mm_or_si128(a, b)
+    }
+    #[inline]
+    #[target_feature(enable = "sse2")]
+    fn shr<const N: i32>(a: V) -> V {
+        _mm_srli_epi32::<N>(a)
+    }
+    /// Rotates right by `R`; `L` must be `32 - R`.
+    #[inline]
+    #[target_feature(enable = "sse2")]
+    fn rotr<const R: i32, const L: i32>(a: V) -> V {
+        _mm_or_si128(_mm_srli_epi32::<R>(a), _mm_slli_epi32::<L>(a))
+    }
+
+    multi_lane!("sse2");
+}
+
+#[cfg(target_arch = "x86_64")]
+mod avx2 {
+    use std::arch::x86_64::*;
+
+    use super::{H0, K};
+
+    const LANES: usize = 8;
+    type V = __m256i;
+
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    fn set1(x: u32) -> V {
+        _mm256_set1_epi32(x as i32)
+    }
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    fn load(words: &[u32; LANES]) -> V {
+        unsafe { _mm256_loadu_si256(words.as_ptr() as *const V) }
+    }
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    fn store(v: V) -> [u32; LANES] {
+        let mut words = [0; LANES];
+        unsafe { _mm256_storeu_si256(words.as_mut_ptr() as *mut V, v) };
+        words
+    }
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    fn add(a: V, b: V) -> V {
+        _mm256_add_epi32(a, b)
+    }
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    fn xor(a: V, b: V) -> V {
+        _mm256_xor_si256(a, b)
+    }
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    fn and(a: V, b: V) -> V {
+        _mm256_and_si256(a, b)
+    }
+    /// `!a & b`
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    fn andnot(a: V, b: V) -> V {
+        _mm256_andnot_si256(a, b)
+    }
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    fn or(a: V, b: V) -> V {
+        _mm256_or_si256(a, b)
+    }
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    fn shr<const N: i32>(a: V) -> V {
+        _mm256_srli_epi32::<N>(a)
+    }
+    /// Rotates right by `R`; `L` must be `32 - R`.
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    fn rotr<const R: i32, const L: i32>(a: V) -> V {
+        _mm256_or_si256(_mm256_srli_epi32::<R>(a), _mm256_slli_epi32::<L>(a))
+    }
+
+    multi_lane!("avx2");
+}
+
+#[cfg(target_arch = "x86_64")]
+mod avx512 {
+    use std::arch::x86_64::*;
+
+    use super::{H0, K};
+
+    const LANES: usize = 16;
+    type V = __m512i;
+
+    #[inline]
+    #[target_feature(enable = "avx512f")]
+    fn set1(x: u32) -> V {
+        _mm512_set1_epi32(x as i32)
+    }
+    #[inline]
+    #[target_feature(enable = "avx512f")]
+    fn load(words: &[u32; LANES]) -> V {
+        unsafe { _mm512_loadu_si512(words.as_ptr() as *const V) }
+    }
+    #[inline]
+    #[target_feature(enable = "avx512f")]
+    fn store(v: V) -> [u32; LANES] {
+        let mut words = [0; LANES];
+        unsafe { _mm512_storeu_si512(words.as_mut_ptr() as *mut V, v) };
+        words
+    }
+    #[inline]
+    #[target_feature(enable = "avx512f")]
+    fn add(a: V, b: V) -> V {
+        _mm512_add_epi32(a, b)
+    }
+    #[inline]
+    #[target_feature(enable = "avx512f")]
+    fn xor(a: V, b: V) -> V {
+        _mm512_xor_si512(a, b)
+    }
+    #[inline]
+    #[target_feature(enable = "avx512f")]
+    fn and(a: V, b: V) -> V {
+        _mm512_and_si512(a, b)
+    }
+    /// `!a & b`
+    #[inline]
+    #[target_feature(enable = "avx512f")]
+    fn andnot(a: V, b: V) -> V {
+        _mm512_andnot_si512(a, b)
+    }
+    #[inline]
+    #[target_feature(enable = "avx512f")]
+    fn or(a: V, b: V) -> V {
+        _mm512_or_si512(a, b)
+    }
+    #[inline]
+    #[target_feature(enable = "avx512f")]
+    fn shr<const N: u32>(a: V) -> V {
+        _mm512_srli_epi32::<N>(a)
+    }
+    /// Rotates right by `R`; AVX-512 has a native rotate, so `L` is unused.
+    #[inline]
+    #[target_feature(enable = "avx512f")]
+    fn rotr<const R: i32, const L: i32>(a: V) -> V {
+        _mm512_ror_epi32::<R>(a)
+    }
+
+    multi_lane!("avx512f");
+}
@@ -0,0 +1,324 @@
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, Result};
+use bitcoin::{key::Secp256k1, taproot::ControlBlock, Transaction, XOnlyPublicKey};
+use minicbor::{data::Type, Decoder};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    utils::{decode_envelope_payload, parse_bitwork},
+    worker::has_valid_bitwork,
+};
+
+/// Longest ticker the indexer accepts.
+pub const MAX_TICKER_LENGTH: usize = 21;
+
+/// What the deployed ticker demands of each mint. Unset fields are not
+/// checked, since they can only be learned from the indexer.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MintRules {
+    pub ticker: Option<String>,
+    pub mint_amount: Option<u64>,
+    pub mint_bitworkc: Option<String>,
+    pub mint_bitworkr: Option<String>,
+}
+
+/// One reason the indexer would not credit a mint.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum Violation {
+    NoEnvelope,
+    NotSpendingCommit {
+        input: usize,
+        outpoint: String,
+    },
+    CommitmentMismatch {
+        input: usize,
+        reason: String,
+    },
+    UnsupportedOpType {
+        op_type: String,
+    },
+    MalformedPayload {
+        reason: String,
+    },
+    MissingArg {
+        key: String,
+    },
+    WrongArgType {
+        key: String,
+        expected: String,
+    },
+    InvalidBitwork {
+        key: String,
+        value: String,
+    },
+    BitworkNotMet {
+        key: String,
+        txid: String,
+        bitwork: String,
+    },
+    RequirementNotMet {
+        key: String,
+        required: String,
+        actual: Option<String>,
+    },
+    InvalidTicker {
+        ticker: String,
+    },
+    TickerMismatch {
+        expected: String,
+        actual: String,
+    },
+    MintAmountMismatch {
+        expected: u64,
+        actual: Option<u64>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MintValidation {
+    pub credited: bool,
+    pub commit_txid: String,
+    pub reveal_txid: String,
+    pub op_type: Option<String>,
+    pub ticker: Option<String>,
+    pub violations: Vec<Violation>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum ArgValue {
+    Int(u64),
+    Str(String),
+    Other,
+}
+
+/// Checks a commit/reveal pair the way the Atomicals indexer would credit a
+/// `dmt` mint. Every problem found is reported rather than stopping at the
+/// first.
+pub fn validate_mint(
+    commit: &Transaction,
+    reveal: &Transaction,
+    rules: &MintRules,
+) -> MintValidation {
+    let commit_txid = commit.txid().to_string();
+    let reveal_txid = reveal.txid().to_string();
+    let mut validation = MintValidation {
+        credited: false,
+        commit_txid: commit_txid.clone(),
+        reveal_txid: reveal_txid.clone(),
+        op_type: None,
+        ticker: None,
+        violations: vec![],
+    };
+    let violations = &mut validation.violations;
+
+    // The indexer takes the first input that carries an envelope.
+    let Some((input, op_type, cbor)) = reveal.input.iter().enumerate().find_map(|(i, input)| {
+        let script = input.witness.tapscript()?;
+        let (_, op_type, cbor) = decode_envelope_payload(script).ok()?;
+        Some((i, op_type, cbor))
+    }) else {
+        violations.push(Violation::NoEnvelope);
+        return validation;
+    };
+    validation.op_type = Some(op_type.clone());
+
+    let outpoint = reveal.input[input].previous_output;
+    if outpoint.txid != commit.txid() {
+        violations.push(Violation::NotSpendingCommit {
+            input,
+            outpoint: outpoint.to_string(),
+        });
+        return validation;
+    }
+    if let Err(reason) = check_commitment(commit, reveal, input) {
+        violations.push(Violation::CommitmentMismatch { input, reason });
+    }
+
+    if op_type != "dmt" {
+        violations.push(Violation::UnsupportedOpType { op_type });
+        return validation;
+    }
+    let args = match decode_args(&cbor) {
+        Ok(args) => args,
+        Err(err) => {
+            violations.push(Violation::MalformedPayload {
+                reason: err.to_string(),
+            });
+            return validation;
+        }
+    };
+
+    for key in ["time", "nonce"] {
+        match args.get(key) {
+            None => violations.push(Violation::MissingArg {
+                key: key.to_string(),
+            }),
+            Some(ArgValue::Int(_)) => {}
+            Some(_) => violations.push(Violation::WrongArgType {
+                key: key.to_string(),
+                expected: "unsigned integer".to_string(),
+            }),
+        }
+    }
+    for (key, txid, required) in [
+        ("bitworkc", &commit_txid, &rules.mint_bitworkc),
+        ("bitworkr", &reveal_txid, &rules.mint_bitworkr),
+    ] {
+        let value = match args.get(key) {
+            None => None,
+            Some(ArgValue::Str(value)) => Some(value.clone()),
+            Some(_) => {
+                violations.push(Violation::WrongArgType {
+                    key: key.to_string(),
+                    expected: "string".to_string(),
+                });
+                None
+            }
+        };
+        if let Some(value) = &value {
+            match parse_bitwork(value) {
+                Ok((prefix, _)) if prefix.is_empty() => {
+                    violations.push(Violation::InvalidBitwork {
+                        key: key.to_string(),
+                        value: value.clone(),
+                    });
+                }
+                Ok((prefix, ext)) => {
+                    if !has_valid_bitwork(txid, &Some(prefix), &ext) {
+                        violations.push(Violation::BitworkNotMet {
+                            key: key.to_string(),
+                            txid: txid.clone(),
+                            bitwork: value.clone(),
+                        });
+                    }
+                }
+                Err(_) => violations.push(Violation::InvalidBitwork {
+                    key: key.to_string(),
+                    value: value.clone(),
+                }),
+            }
+        }
+        if let Some(required) = required {
+            if value.as_ref() != Some(required) {
+                violations.push(Violation::RequirementNotMet {
+                    key: key.to_string(),
+                    required: required.clone(),
+                    actual: value,
+                });
+            }
+        }
+    }
+
+    match args.get("mint_ticker") {
+        None => violations.push(Violation::MissingArg {
+            key: "mint_ticker".to_string(),
+        }),
+        Some(ArgValue::Str(ticker)) => {
+            if !is_valid_ticker(ticker) {
+                violations.push(Violation::InvalidTicker {
+                    ticker: ticker.clone(),
+                });
+            }
+            if let Some(expected) = rules.ticker.as_ref().filter(|t| *t != ticker) {
+                violations.push(Violation::TickerMismatch {
+                    expected: expected.clone(),
+                    actual: ticker.clone(),
+                });
+            }
+            validation.ticker = Some(ticker.clone());
+        }
+        Some(_) => violations.push(Violation::WrongArgType {
+            key: "mint_ticker".to_string(),
+            expected: "string".to_string(),
+        }),
+    }
+
+    // The minted tokens are assigned to the first output of the reveal.
+    if let Some(expected) = rules.mint_amount {
+        let actual = reveal.output.first().map(|output| output.value.to_sat());
+        if actual != Some(expected) {
+            violations.push(Violation::MintAmountMismatch { expected, actual });
+        }
+    }
+
+    validation.credited = validation.violations.is_empty();
+    validation
+}
+
+/// Tickers are 1 to 21 lowercase letters or digits.
+pub fn is_valid_ticker(ticker: &str) -> bool {
+    (1..=MAX_TICKER_LENGTH).contains(&ticker.len())
+        && ticker
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
+}
+
+/// Checks that the commit output spent by `reveal.input[input]` is a taproot
+/// output committing to the envelope leaf.
+fn check_commitment(
+    commit: &Transaction,
+    reveal: &Transaction,
+    input: usize,
+) -> Result<(), String> {
+    let txin = &reveal.input[input];
+    let prevout = commit
+        .output
+        .get(txin.previous_output.vout as usize)
+        .ok_or("commit has no such output")?;
+    if !prevout.script_pubkey.is_p2tr() {
+        return Err("commit output is not a taproot output".to_string());
+    }
+    let output_key = XOnlyPublicKey::from_slice(&prevout.script_pubkey.as_bytes()[2..])
+        .map_err(|err| err.to_string())?;
+    let script = txin
+        .witness
+        .tapscript()
+        .ok_or("input is not a script spend")?;
+    // The control block follows the script unless an annex comes last.
+    let elements: Vec<&[u8]> = txin.witness.iter().collect();
+    let has_annex = elements.len() >= 3 && elements[elements.len() - 1].first() == Some(&0x50);
+    let control_block = elements[elements.len() - 1 - usize::from(has_annex)];
+    let control_block = ControlBlock::decode(control_block).map_err(|err| err.to_string())?;
+    if !control_block.verify_taproot_commitment(&Secp256k1::verification_only(), output_key, script)
+    {
+        return Err("control block does not commit to the envelope leaf".to_string());
+    }
+    Ok(())
+}
+
+/// Reads the `args` map of an envelope payload, keeping unknown keys.
+fn decode_args(cbor: &[u8]) -> Result<BTreeMap<String, ArgValue>> {
+    let mut decoder = Decoder::new(cbor);
+    let entries = decoder
+        .map()?
+        .ok_or_else(|| anyhow!("indefinite maps are not supported"))?;
+    let mut args = None;
+    for _ in 0..entries {
+        if decoder.str()? != "args" {
+            decoder.skip()?;
+            continue;
+        }
+        let mut map = BTreeMap::new();
+        let entries = decoder
+            .map()?
+            .ok_or_else(|| anyhow!("indefinite maps are not supported"))?;
+        for _ in 0..entries {
+            let key = decoder.str()?.to_string();
+            let value = match decoder.datatype()? {
+                Type::U8 | Type::U16 | Type::U32 | Type::U64 => ArgValue::Int(decoder.u64()?),
+                Type::String => ArgValue::Str(decoder.str()?.to_string()),
+                _ => {
+                    decoder.skip()?;
+                    ArgValue::Other
+                }
+            };
+            map.insert(key, value);
+        }
+        args = Some(map);
+    }
+    args.ok_or_else(|| anyhow!("payload has no `args` map"))
+}
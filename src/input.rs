@@ -0,0 +1,94 @@
+use std::{
+    env, fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{anyhow, Context, Result};
+use serde::de::DeserializeOwned;
+
+/// Environment variable consulted when no other input is given.
+pub const DEFAULT_JOB_ENV: &str = "PSBT_JOB";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InputSource {
+    File(PathBuf),
+    Stdin,
+    Env(String),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum InputFormat {
+    Json,
+    Toml,
+}
+
+impl FromStr for InputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "json" => Ok(InputFormat::Json),
+            "toml" => Ok(InputFormat::Toml),
+            other => Err(format!("unknown input format `{}`", other)),
+        }
+    }
+}
+
+impl InputFormat {
+    /// Picks a format from the file extension, falling back to the content.
+    pub fn detect(path: Option<&Path>, text: &str) -> Self {
+        match path.and_then(|p| p.extension()).and_then(|e| e.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("toml") => InputFormat::Toml,
+            Some(ext) if ext.eq_ignore_ascii_case("json") => InputFormat::Json,
+            _ if text.trim_start().starts_with('{') => InputFormat::Json,
+            _ => InputFormat::Toml,
+        }
+    }
+}
+
+/// Reads and parses a job (or any other document) from `source`.
+pub fn load<T: DeserializeOwned>(source: &InputSource, format: Option<InputFormat>) -> Result<T> {
+    let (text, path) = match source {
+        InputSource::File(path) => (
+            fs::read_to_string(path)
+                .with_context(|| format!("failed to read {}", path.display()))?,
+            Some(path.as_path()),
+        ),
+        InputSource::Stdin => {
+            let mut text = String::new();
+            io::stdin()
+                .read_to_string(&mut text)
+                .context("failed to read stdin")?;
+            (text, None)
+        }
+        InputSource::Env(name) => (
+            env::var(name).with_context(|| format!("environment variable {} is not set", name))?,
+            None,
+        ),
+    };
+    let format = format.unwrap_or_else(|| InputFormat::detect(path, &text));
+    parse(&text, format)
+}
+
+/// Deserializes `text`, reporting the path of the offending field on failure.
+pub fn parse<T: DeserializeOwned>(text: &str, format: InputFormat) -> Result<T> {
+    match format {
+        InputFormat::Json => {
+            let de = &mut serde_json::Deserializer::from_str(text);
+            serde_path_to_error::deserialize(de)
+                .map_err(|err| anyhow!("invalid input at `{}`: {}", err.path(), err.inner()))
+        }
+        InputFormat::Toml => {
+            let de = toml::Deserializer::new(text);
+            serde_path_to_error::deserialize(de).map_err(|err| {
+                anyhow!(
+                    "invalid input at `{}`: {}",
+                    err.path(),
+                    err.inner().message()
+                )
+            })
+        }
+    }
+}
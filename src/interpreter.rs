@@ -0,0 +1,340 @@
+//! Script verification of the transactions this tool emits.
+//!
+//! This is not a consensus library. The `bitcoinconsensus` feature of
+//! `bitcoin` 0.31 links libbitcoinconsensus, which cannot verify taproot
+//! spends, so key paths are checked with secp256k1 and script paths by a
+//! small tapscript interpreter covering the opcodes the tool writes. Leaves
+//! with OP_SUCCESSx, which consensus accepts unconditionally, are refused,
+//! and BIP-342's signature operation budget is enforced.
+
+use anyhow::{anyhow, bail, Result};
+use bitcoin::{
+    blockdata::constants::MAX_SCRIPT_ELEMENT_SIZE,
+    hashes::Hash,
+    key::Secp256k1,
+    opcodes::{
+        all::{
+            OP_CHECKSIG, OP_CHECKSIGVERIFY, OP_CSV, OP_DROP, OP_ELSE, OP_ENDIF, OP_IF, OP_NOTIF,
+            OP_VERIFY,
+        },
+        Class, ClassifyContext,
+    },
+    script::Instruction,
+    secp256k1::{self, Message},
+    sighash::{Prevouts, SighashCache},
+    taproot::{self, ControlBlock, LeafVersion},
+    Script, TapLeafHash, Transaction, TxOut, XOnlyPublicKey,
+};
+
+use crate::{
+    reveal,
+    types::Payload,
+    verify::verify_key_spend,
+    worker::{
+        funding_prevout, SEQUENCE_LOCK_TIME_DISABLE_FLAG, SEQUENCE_LOCK_TIME_MASK,
+        SEQUENCE_LOCK_TIME_TYPE_FLAG,
+    },
+};
+
+/// First byte of a taproot annex.
+const ANNEX_TAG: u8 = 0x50;
+
+/// BIP-342 signature budget of a script path spend, besides its witness
+/// size.
+const VALIDATION_WEIGHT_OFFSET: i64 = 50;
+
+/// Budget spent by each signature check with a non-empty signature.
+const VALIDATION_WEIGHT_PER_SIGOP_PASSED: i64 = 50;
+
+/// Verifies every input of `tx` against the outputs it spends, naming the
+/// first input that fails.
+pub fn verify_tx(
+    secp: &Secp256k1<secp256k1::All>,
+    tx: &Transaction,
+    prevouts: &[TxOut],
+) -> Result<()> {
+    if prevouts.len() != tx.input.len() {
+        bail!(
+            "{} prevouts given for {} inputs",
+            prevouts.len(),
+            tx.input.len()
+        );
+    }
+    for (index, input) in tx.input.iter().enumerate() {
+        verify_input(secp, tx, index, prevouts).map_err(|reason| {
+            anyhow!(
+                "input {} of {} (spending {}) fails script verification: {}",
+                index,
+                tx.txid(),
+                input.previous_output,
+                reason
+            )
+        })?;
+    }
+    Ok(())
+}
+
+/// Verifies a signed commit and the reveals spending it before they are
+/// emitted.
+pub fn verify_mint(payload: &Payload, commit: &Transaction, reveals: &[Transaction]) -> Result<()> {
+    verify_tx(&payload.secp, commit, &[funding_prevout(payload)])?;
+    for tx in reveals {
+        verify_tx(&payload.secp, tx, &reveal::spent_outputs(commit, tx)?)?;
+    }
+    Ok(())
+}
+
+/// Runs the taproot spend of `tx.input[index]`, by key or by script path.
+pub fn verify_input(
+    secp: &Secp256k1<secp256k1::All>,
+    tx: &Transaction,
+    index: usize,
+    prevouts: &[TxOut],
+) -> Result<(), String> {
+    let input = tx.input.get(index).ok_or("input does not exist")?;
+    let prevout = prevouts.get(index).ok_or("missing prevout")?;
+    if !prevout.script_pubkey.is_p2tr() {
+        return Err("prevout is not a taproot output".to_string());
+    }
+    let mut stack: Vec<&[u8]> = input.witness.iter().collect();
+    if stack.len() >= 2 && stack.last().and_then(|item| item.first()) == Some(&ANNEX_TAG) {
+        return Err("annexes are not supported".to_string());
+    }
+    if stack.len() < 2 {
+        return verify_key_spend(secp, tx, index, prevouts);
+    }
+
+    let control_block =
+        ControlBlock::decode(stack.pop().expect("control block")).map_err(|err| err.to_string())?;
+    let script = Script::from_bytes(stack.pop().expect("leaf script"));
+    if control_block.leaf_version != LeafVersion::TapScript {
+        return Err(format!(
+            "unsupported leaf version {}",
+            control_block.leaf_version
+        ));
+    }
+    let output_key = XOnlyPublicKey::from_slice(&prevout.script_pubkey.as_bytes()[2..])
+        .map_err(|err| err.to_string())?;
+    if !control_block.verify_taproot_commitment(secp, output_key, script) {
+        return Err("control block does not commit to the leaf script".to_string());
+    }
+    // Consensus accepts any leaf holding OP_SUCCESSx without running it.
+    for instruction in script.instructions() {
+        if let Instruction::Op(op) = instruction.map_err(|err| err.to_string())? {
+            if op.classify(ClassifyContext::TapScript) == Class::SuccessOp {
+                return Err(format!("leaf holds {}, which succeeds unconditionally", op));
+            }
+        }
+    }
+    let leaf_hash = TapLeafHash::from_script(script, LeafVersion::TapScript);
+    let stack = stack.into_iter().map(<[u8]>::to_vec).collect();
+    Tapscript {
+        secp,
+        tx,
+        index,
+        prevouts,
+        leaf_hash,
+        budget: VALIDATION_WEIGHT_OFFSET + input.witness.size() as i64,
+    }
+    .run(script, stack)
+}
+
+/// Executes the tapscript subset this tool writes: pushes, conditionals and
+/// signature checks. Anything else is refused rather than guessed at.
+struct Tapscript<'a> {
+    secp: &'a Secp256k1<secp256k1::All>,
+    tx: &'a Transaction,
+    index: usize,
+    prevouts: &'a [TxOut],
+    leaf_hash: TapLeafHash,
+    /// Signature operation budget left.
+    budget: i64,
+}
+
+impl Tapscript<'_> {
+    fn run(mut self, script: &Script, mut stack: Vec<Vec<u8>>) -> Result<(), String> {
+        if let Some(item) = stack
+            .iter()
+            .find(|item| item.len() > MAX_SCRIPT_ELEMENT_SIZE)
+        {
+            return Err(format!("witness item of {} bytes", item.len()));
+        }
+        let mut conditions: Vec<bool> = vec![];
+        for instruction in script.instructions() {
+            let instruction = instruction.map_err(|err| err.to_string())?;
+            let executing = conditions.iter().all(|taken| *taken);
+            let op = match instruction {
+                Instruction::PushBytes(push) => {
+                    if push.len() > MAX_SCRIPT_ELEMENT_SIZE {
+                        return Err(format!("push of {} bytes", push.len()));
+                    }
+                    if executing {
+                        stack.push(push.as_bytes().to_vec());
+                    }
+                    continue;
+                }
+                Instruction::Op(op) => op,
+            };
+            match op {
+                OP_IF | OP_NOTIF => {
+                    let taken = executing && {
+                        // Tapscript requires a minimal condition.
+                        let condition = pop(&mut stack)?;
+                        match condition.as_slice() {
+                            [] => op == OP_NOTIF,
+                            [1] => op == OP_IF,
+                            _ => return Err(format!("{} condition is not minimal", op)),
+                        }
+                    };
+                    conditions.push(taken);
+                }
+                OP_ELSE => {
+                    let taken = conditions.last_mut().ok_or("OP_ELSE without OP_IF")?;
+                    *taken = !*taken;
+                }
+                OP_ENDIF => {
+                    conditions.pop().ok_or("OP_ENDIF without OP_IF")?;
+                }
+                _ if !executing => {}
+                OP_CHECKSIG | OP_CHECKSIGVERIFY => {
+                    let pubkey = pop(&mut stack)?;
+                    let signature = pop(&mut stack)?;
+                    let valid = self.check_signature(&signature, &pubkey)?;
+                    if op == OP_CHECKSIGVERIFY {
+                        if !valid {
+                            return Err("OP_CHECKSIGVERIFY with an empty signature".to_string());
+                        }
+                    } else {
+                        stack.push(if valid { vec![1] } else { vec![] });
+                    }
+                }
+                OP_VERIFY => {
+                    if !cast_to_bool(&pop(&mut stack)?) {
+                        return Err("OP_VERIFY failed".to_string());
+                    }
+                }
+                OP_DROP => {
+                    pop(&mut stack)?;
+                }
+                OP_CSV => {
+                    let top = stack.last().ok_or("stack underflow")?;
+                    self.check_sequence(top)?;
+                }
+                _ => match op.classify(ClassifyContext::TapScript) {
+                    Class::PushNum(-1) => stack.push(vec![0x81]),
+                    Class::PushNum(n) => stack.push(vec![n as u8]),
+                    _ => return Err(format!("unsupported opcode {}", op)),
+                },
+            }
+        }
+        if !conditions.is_empty() {
+            return Err("unbalanced conditional".to_string());
+        }
+        match stack.as_slice() {
+            [top] if cast_to_bool(top) => Ok(()),
+            [_] => Err("script ended with a false value".to_string()),
+            _ => Err(format!("script ended with {} stack items", stack.len())),
+        }
+    }
+
+    /// BIP-112: the input's `nSequence` must be a relative lock of the same
+    /// kind and at least as long as `required`.
+    fn check_sequence(&self, required: &[u8]) -> Result<(), String> {
+        let required = script_num(required)?;
+        if required < 0 {
+            return Err("negative OP_CSV lock".to_string());
+        }
+        if required & SEQUENCE_LOCK_TIME_DISABLE_FLAG as i64 != 0 {
+            return Ok(());
+        }
+        let sequence = self.tx.input[self.index].sequence.0;
+        if self.tx.version.0 < 2 {
+            return Err("OP_CSV needs transaction version 2".to_string());
+        }
+        if sequence & SEQUENCE_LOCK_TIME_DISABLE_FLAG != 0 {
+            return Err("OP_CSV with relative lock time disabled".to_string());
+        }
+        let mask = (SEQUENCE_LOCK_TIME_TYPE_FLAG | SEQUENCE_LOCK_TIME_MASK) as i64;
+        let (required, sequence) = (required & mask, sequence as i64 & mask);
+        let kind = SEQUENCE_LOCK_TIME_TYPE_FLAG as i64;
+        if required & kind != sequence & kind {
+            return Err("OP_CSV lock is not of the input's kind".to_string());
+        }
+        if sequence < required {
+            return Err(format!(
+                "input is locked for {} but OP_CSV needs {}",
+                sequence & SEQUENCE_LOCK_TIME_MASK as i64,
+                required & SEQUENCE_LOCK_TIME_MASK as i64
+            ));
+        }
+        Ok(())
+    }
+
+    /// An empty signature is a failed check; any other must be valid and
+    /// is charged to the signature operation budget.
+    fn check_signature(&mut self, signature: &[u8], pubkey: &[u8]) -> Result<bool, String> {
+        if pubkey.is_empty() {
+            return Err("empty public key".to_string());
+        }
+        if signature.is_empty() {
+            return Ok(false);
+        }
+        self.budget -= VALIDATION_WEIGHT_PER_SIGOP_PASSED;
+        if self.budget < 0 {
+            return Err("signature operation budget exceeded".to_string());
+        }
+        if signature.len() == 65 && signature[64] == 0 {
+            return Err("explicit SIGHASH_DEFAULT byte".to_string());
+        }
+        // Other key lengths are reserved for upgrades and always pass.
+        if pubkey.len() != 32 {
+            return Ok(true);
+        }
+        let pubkey = XOnlyPublicKey::from_slice(pubkey).map_err(|err| err.to_string())?;
+        let signature = taproot::Signature::from_slice(signature).map_err(|err| err.to_string())?;
+        let hash = SighashCache::new(self.tx)
+            .taproot_script_spend_signature_hash(
+                self.index,
+                &Prevouts::All(self.prevouts),
+                self.leaf_hash,
+                signature.hash_ty,
+            )
+            .map_err(|err| err.to_string())?;
+        let msg = Message::from_digest(hash.to_byte_array());
+        self.secp
+            .verify_schnorr(&signature.sig, &msg, &pubkey)
+            .map_err(|err| format!("invalid signature for {}: {}", pubkey, err))?;
+        Ok(true)
+    }
+}
+
+/// Decodes a script number of up to five bytes, as OP_CSV reads one.
+fn script_num(item: &[u8]) -> Result<i64, String> {
+    if item.len() > 5 {
+        return Err(format!("script number of {} bytes", item.len()));
+    }
+    let Some((last, _)) = item.split_last() else {
+        return Ok(0);
+    };
+    let mut value = item
+        .iter()
+        .enumerate()
+        .fold(0i64, |value, (i, byte)| value | (*byte as i64) << (8 * i));
+    if last & 0x80 != 0 {
+        value &= !(0x80i64 << (8 * (item.len() - 1)));
+        value = -value;
+    }
+    Ok(value)
+}
+
+fn pop(stack: &mut Vec<Vec<u8>>) -> Result<Vec<u8>, String> {
+    stack.pop().ok_or_else(|| "stack underflow".to_string())
+}
+
+/// Script truth: any non-zero byte, except a lone sign bit at the end.
+fn cast_to_bool(item: &[u8]) -> bool {
+    match item.split_last() {
+        None => false,
+        Some((last, rest)) => rest.iter().any(|b| *b != 0) || (*last != 0 && *last != 0x80),
+    }
+}
@@ -0,0 +1,28 @@
+pub mod audit;
+pub mod backend;
+pub mod batch;
+pub mod bitcoind;
+pub mod cancel;
+pub mod cluster;
+pub mod descriptor;
+pub mod electrum;
+pub mod esplora;
+pub mod hash;
+pub mod indexer;
+pub mod input;
+pub mod interpreter;
+pub mod lifecycle;
+pub mod miner;
+pub mod policy;
+pub mod rbf;
+pub mod recover;
+pub mod reveal;
+pub mod scheduler;
+pub mod search;
+#[cfg(test)]
+mod test;
+pub mod tweak;
+pub mod types;
+pub mod utils;
+pub mod verify;
+pub mod worker;
@@ -0,0 +1,311 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use bitcoin::{
+    consensus::{deserialize, encode::serialize_hex},
+    Transaction,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::backend::ChainBackend;
+
+/// Where a commit/reveal pair is in its life.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Stage {
+    Mined,
+    CommitBroadcast,
+    CommitConfirmed,
+    RevealBroadcast,
+    RevealConfirmed,
+    /// Gave up after too many attempts; `Record::failed_stage` says where.
+    Failed,
+}
+
+/// One tracked mint. The id is the commit txid.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Record {
+    pub id: String,
+    pub funding_outpoint: String,
+    pub commit_tx: String,
+    pub reveal_tx: String,
+    pub stage: Stage,
+    pub failed_stage: Option<Stage>,
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    pub updated_at: u64,
+    /// The failed commit was found on neither the mempool nor the chain, so
+    /// its funding output may be spent again.
+    #[serde(default)]
+    pub released: bool,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Policy {
+    /// Confirmations required before a stage counts as confirmed.
+    pub confirmations: u32,
+    /// Failed broadcasts and lookups tolerated per stage before the record
+    /// is parked.
+    pub max_attempts: u32,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Policy {
+            confirmations: 1,
+            max_attempts: 5,
+        }
+    }
+}
+
+impl Record {
+    pub fn new(commit: &Transaction, reveal: &Transaction) -> Self {
+        Record {
+            id: commit.txid().to_string(),
+            funding_outpoint: commit.input[0].previous_output.to_string(),
+            commit_tx: serialize_hex(commit),
+            reveal_tx: serialize_hex(reveal),
+            stage: Stage::Mined,
+            failed_stage: None,
+            attempts: 0,
+            last_error: None,
+            updated_at: now(),
+            released: false,
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        matches!(self.stage, Stage::RevealConfirmed | Stage::Failed)
+    }
+
+    /// Whether the record claims its funding output. A failed broadcast may
+    /// still have reached the network, so only a released record gives it up.
+    pub fn holds_funding_output(&self) -> bool {
+        !self.released
+    }
+
+    /// Moves a failed record back to the stage it failed in.
+    fn retry(&mut self) -> Result<()> {
+        let stage = self
+            .failed_stage
+            .take()
+            .ok_or_else(|| anyhow!("record {} has not failed", self.id))?;
+        self.stage = stage;
+        self.attempts = 0;
+        self.released = false;
+        self.updated_at = now();
+        Ok(())
+    }
+
+    /// Advances at most one stage. Only the exact transactions stored in the
+    /// record are ever (re)broadcast, so retrying cannot double-spend.
+    pub fn step(&mut self, backend: &dyn ChainBackend, policy: &Policy) -> Result<()> {
+        let commit: Transaction = deserialize(&hex::decode(&self.commit_tx)?)?;
+        let reveal: Transaction = deserialize(&hex::decode(&self.reveal_tx)?)?;
+        match self.stage {
+            Stage::Mined => self.broadcast(backend, &commit, Stage::CommitBroadcast, policy),
+            Stage::CommitBroadcast => match backend.tx_confirmations(&commit) {
+                Ok(Some(n)) if n >= policy.confirmations => self.advance(Stage::CommitConfirmed),
+                Ok(Some(_)) => {}
+                // Dropped from the mempool: send the same commit again.
+                Ok(None) => self.broadcast(backend, &commit, Stage::CommitBroadcast, policy),
+                Err(err) => self.fail(err, policy),
+            },
+            Stage::CommitConfirmed => {
+                self.broadcast(backend, &reveal, Stage::RevealBroadcast, policy)
+            }
+            Stage::RevealBroadcast => match backend.tx_confirmations(&reveal) {
+                Ok(Some(n)) if n >= policy.confirmations => self.advance(Stage::RevealConfirmed),
+                Ok(Some(_)) => {}
+                Ok(None) => self.broadcast(backend, &reveal, Stage::RevealBroadcast, policy),
+                Err(err) => self.fail(err, policy),
+            },
+            Stage::RevealConfirmed | Stage::Failed => {}
+        }
+        Ok(())
+    }
+
+    fn broadcast(
+        &mut self,
+        backend: &dyn ChainBackend,
+        tx: &Transaction,
+        next: Stage,
+        policy: &Policy,
+    ) {
+        let result = backend.broadcast(tx).or_else(|err| {
+            // A rejected rebroadcast is fine if the network already has it.
+            match backend.tx_confirmations(tx) {
+                Ok(Some(_)) => Ok(tx.txid()),
+                _ => Err(err),
+            }
+        });
+        match result {
+            Ok(_) => self.advance(next),
+            Err(err) => self.fail(err, policy),
+        }
+    }
+
+    /// Counts a failed broadcast or lookup against `policy.max_attempts`.
+    fn fail(&mut self, err: anyhow::Error, policy: &Policy) {
+        self.attempts += 1;
+        self.last_error = Some(format!("{:#}", err));
+        self.updated_at = now();
+        if self.attempts >= policy.max_attempts {
+            self.failed_stage = Some(self.stage);
+            self.stage = Stage::Failed;
+        }
+    }
+
+    fn advance(&mut self, stage: Stage) {
+        if self.stage != stage {
+            self.attempts = 0;
+            self.last_error = None;
+        }
+        self.stage = stage;
+        self.updated_at = now();
+    }
+}
+
+/// Append-only JSON-lines log of records; the last line per id wins.
+#[derive(Debug)]
+pub struct Journal {
+    path: PathBuf,
+    records: BTreeMap<String, Record>,
+}
+
+impl Journal {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut records = BTreeMap::new();
+        if path.exists() {
+            let file = File::open(&path)
+                .with_context(|| format!("failed to open journal {}", path.display()))?;
+            for (number, line) in BufReader::new(file).lines().enumerate() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let record: Record = serde_json::from_str(&line).with_context(|| {
+                    format!("corrupt journal {} line {}", path.display(), number + 1)
+                })?;
+                records.insert(record.id.clone(), record);
+            }
+        } else if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        Ok(Journal { path, records })
+    }
+
+    pub fn records(&self) -> impl Iterator<Item = &Record> {
+        self.records.values()
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Record> {
+        self.records.get(id)
+    }
+
+    /// Starts tracking a new record, refusing one whose funding output is
+    /// claimed by another record.
+    pub fn insert(&mut self, record: Record) -> Result<()> {
+        if let Some(existing) = self.records.get(&record.id) {
+            bail!(
+                "{} is already tracked at stage {:?}",
+                existing.id,
+                existing.stage
+            );
+        }
+        self.check_funding_output(&record)?;
+        self.update(record)
+    }
+
+    /// Sends a failed record back to the stage it failed in.
+    pub fn retry(&mut self, id: &str) -> Result<()> {
+        let mut record = self
+            .get(id)
+            .cloned()
+            .ok_or_else(|| anyhow!("{} is not tracked", id))?;
+        record.retry()?;
+        self.check_funding_output(&record)?;
+        self.update(record)
+    }
+
+    /// Gives up the funding output of a failed record once `backend` knows
+    /// its commit neither in the mempool nor on chain.
+    pub fn release(&mut self, id: &str, backend: &dyn ChainBackend) -> Result<()> {
+        let mut record = self
+            .get(id)
+            .cloned()
+            .ok_or_else(|| anyhow!("{} is not tracked", id))?;
+        if record.stage != Stage::Failed {
+            bail!("{} is still tracked at stage {:?}", id, record.stage);
+        }
+        let commit: Transaction = deserialize(&hex::decode(&record.commit_tx)?)?;
+        if let Some(confirmations) = backend.tx_confirmations(&commit)? {
+            bail!(
+                "commit {} reached the network ({} confirmations); its funding output stays claimed",
+                id,
+                confirmations
+            );
+        }
+        record.released = true;
+        record.updated_at = now();
+        self.update(record)
+    }
+
+    fn check_funding_output(&self, record: &Record) -> Result<()> {
+        if let Some(conflict) = self.records.values().find(|existing| {
+            existing.id != record.id
+                && existing.funding_outpoint == record.funding_outpoint
+                && existing.holds_funding_output()
+        }) {
+            bail!(
+                "funding output {} is already claimed by tracked commit {}",
+                record.funding_outpoint,
+                conflict.id
+            );
+        }
+        Ok(())
+    }
+
+    /// Persists a new state for a record.
+    pub fn update(&mut self, record: Record) -> Result<()> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("failed to open journal {}", self.path.display()))?;
+        writeln!(file, "{}", serde_json::to_string(&record)?)?;
+        file.sync_data()?;
+        self.records.insert(record.id.clone(), record);
+        Ok(())
+    }
+
+    /// Steps every unfinished record once, persisting each change.
+    pub fn step_all(&mut self, backend: &dyn ChainBackend, policy: &Policy) -> Result<usize> {
+        let pending: Vec<Record> = self.records().filter(|r| !r.is_done()).cloned().collect();
+        for mut record in pending.iter().cloned() {
+            let before = record.clone();
+            if let Err(err) = record.step(backend, policy) {
+                record.last_error = Some(format!("{:#}", err));
+            }
+            if record != before {
+                self.update(record)?;
+            }
+        }
+        Ok(self.records().filter(|r| !r.is_done()).count())
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
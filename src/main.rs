@@ -1,168 +1,848 @@
-use std::{
-    cmp::min,
-    env,
-    sync::{
-        atomic::{AtomicBool, AtomicU64, Ordering},
-        Arc,
-    },
-    time::SystemTime,
-};
+use std::{net::TcpListener, path::PathBuf, str::FromStr, thread, time::Duration};
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 use bitcoin::{
-    key::{rand, rand::rngs::OsRng, Keypair},
-    secp256k1, Address, Amount, PrivateKey, XOnlyPublicKey,
+    consensus::{deserialize, encode::serialize_hex},
+    Address, PrivateKey, Script, Transaction,
 };
-use rand::Rng;
-use rayon::prelude::{IntoParallelIterator, ParallelIterator};
-use serde::{Deserialize, Serialize};
-
-use crate::{
-    types::{Payload, Root},
-    utils::get_output_value_for_commit,
-    worker::predicate,
+use clap::{Args, Parser, Subcommand};
+use psbt::{
+    backend::{self, BackendKind, ChainBackend},
+    batch,
+    cancel::CancellationToken,
+    cluster, descriptor,
+    electrum::ElectrumClient,
+    indexer::{validate_mint, MintRules},
+    input::{self, InputFormat, InputSource, DEFAULT_JOB_ENV},
+    interpreter,
+    lifecycle::{Journal, Policy, Record},
+    miner::{self, MineOutcome},
+    policy,
+    rbf::{self, BumpOptions},
+    recover::{self, RecoveryPath},
+    reveal,
+    scheduler::Scheduler,
+    search::{self, SearchBackend},
+    types::{CommitOutput, RbfPolicy, Root, Variant},
+    utils,
+    verify::{verify_claim, Claim},
+    worker,
 };
+use serde_json::json;
+
+#[derive(Debug, Parser)]
+#[command(version, about = "Atomicals commit bitwork miner")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Search for a commit transaction that satisfies the commit bitwork.
+    Mine {
+        #[command(flatten)]
+        job: JobArgs,
+        #[command(flatten)]
+        search: SearchArgs,
+        /// Count txids meeting prefixes up to this many nibbles shorter than
+        /// the bitwork, stopping early if the counts are implausible.
+        #[arg(long)]
+        near_miss_levels: Option<u8>,
+        /// Append each near miss to this JSON-lines file.
+        #[arg(long)]
+        near_miss_journal: Option<String>,
+    },
+    /// Mine several mints in a row, each commit funded by the previous
+    /// commit's change, and print the commit/reveal pairs in order.
+    Batch {
+        #[command(flatten)]
+        job: JobArgs,
+        #[command(flatten)]
+        search: SearchArgs,
+        /// Mints to chain, at most 12 to stay within mempool limits.
+        #[arg(long)]
+        count: usize,
+    },
+    /// Hand the job out to `work` processes over TCP and sign the winning
+    /// commit; the funding key never leaves this process.
+    Coordinate {
+        #[command(flatten)]
+        job: JobArgs,
+        /// Address to accept workers on.
+        #[arg(long, default_value = "0.0.0.0:7070")]
+        listen: String,
+        /// Run as a pool, crediting workers for txids meeting this easier
+        /// bitwork. Overrides the job's `shareBitwork`.
+        #[arg(long)]
+        share_bitwork: Option<String>,
+    },
+    /// Search commits assigned by a coordinator.
+    Work {
+        /// Coordinator address.
+        #[arg(long)]
+        connect: String,
+        #[command(flatten)]
+        search: SearchArgs,
+        /// Search threads; defaults to the core count.
+        #[arg(long)]
+        threads: Option<usize>,
+        /// Name to credit shares to in pool mode.
+        #[arg(long)]
+        name: Option<String>,
+    },
+    /// Print the commit address and the funding it requires.
+    Plan {
+        #[command(flatten)]
+        job: JobArgs,
+        #[arg(long)]
+        nonce: Option<u64>,
+        #[arg(long)]
+        time: Option<u64>,
+    },
+    /// Check a claimed mining result against the job.
+    Verify {
+        #[command(flatten)]
+        job: JobArgs,
+        #[command(flatten)]
+        result: ResultArgs,
+        /// Txid the miner claims to have found.
+        #[arg(long)]
+        txid: Option<String>,
+        /// Raw commit transaction (hex) the miner claims to have signed.
+        #[arg(long)]
+        tx: Option<String>,
+    },
+    /// Inspect an envelope script or a reveal transaction (hex).
+    Decode { hex: String },
+    /// Predict whether the indexer credits a dmt mint, from its raw commit
+    /// and reveal transactions (hex).
+    Validate {
+        #[arg(long)]
+        commit: String,
+        #[arg(long)]
+        reveal: String,
+        /// Ticker the mint must be for.
+        #[arg(long)]
+        ticker: Option<String>,
+        /// Mint amount of the deployed ticker.
+        #[arg(long)]
+        mint_amount: Option<u64>,
+        /// Commit bitwork required by the deployed ticker.
+        #[arg(long)]
+        mint_bitworkc: Option<String>,
+        /// Reveal bitwork required by the deployed ticker.
+        #[arg(long)]
+        mint_bitworkr: Option<String>,
+    },
+    /// Replace a stuck commit with one paying a higher fee from its change,
+    /// re-mining the bitwork over sequences that signal replaceability.
+    Bump {
+        #[command(flatten)]
+        job: JobArgs,
+        #[command(flatten)]
+        result: ResultArgs,
+        #[command(flatten)]
+        search: SearchArgs,
+        /// Fee rate the replacement pays at least.
+        #[arg(long)]
+        fee_rate: u64,
+        /// Fees of the commit's descendants in the mempool, such as its
+        /// broadcast reveal, which the replacement must also outbid.
+        #[arg(long, default_value_t = 0)]
+        descendant_fees: u64,
+    },
+    /// Assemble the signed commit and reveal transactions for a result.
+    BuildReveal {
+        #[command(flatten)]
+        job: JobArgs,
+        #[command(flatten)]
+        result: ResultArgs,
+    },
+    /// Sweep an unrevealed commit output back to the wallet.
+    Recover {
+        #[command(flatten)]
+        job: JobArgs,
+        #[command(flatten)]
+        result: ResultArgs,
+        /// Commit output to sweep.
+        #[arg(long, default_value_t = 0)]
+        output: usize,
+        /// Spend the refund leaf with this recovery key (WIF) once its
+        /// timelock has passed; the key path with the funding key otherwise.
+        #[arg(long)]
+        recovery_wif: Option<String>,
+        /// Where the sweep pays to; the funding address when omitted.
+        #[arg(long)]
+        address: Option<String>,
+        /// Fee rate of the sweep; the job's `satsbyte` when omitted.
+        #[arg(long)]
+        fee_rate: Option<u64>,
+    },
+    /// Report the difficulty of the commit bitwork and the expected run time.
+    Estimate {
+        #[command(flatten)]
+        job: JobArgs,
+        #[command(flatten)]
+        search: SearchArgs,
+        /// Candidates to time on one thread when measuring the hash rate.
+        #[arg(long, default_value_t = 100_000)]
+        sample: u32,
+    },
+    /// Measure how the search scales with the number of threads.
+    Bench {
+        #[command(flatten)]
+        job: JobArgs,
+        #[command(flatten)]
+        search: SearchArgs,
+        /// Thread counts to run; defaults to powers of two up to the core
+        /// count.
+        #[arg(long = "threads", value_delimiter = ',')]
+        threads: Vec<usize>,
+        /// Candidates searched per thread in each run.
+        #[arg(long, default_value_t = 500_000)]
+        per_thread: u32,
+    },
+    /// List the unspent outputs of the funding address.
+    Utxos(JobArgs),
+    /// Look up a ticker on the electrum server (defaults to the job's ticker).
+    Ticker {
+        #[command(flatten)]
+        job: JobArgs,
+        #[arg(long)]
+        ticker: Option<String>,
+    },
+    /// Broadcast raw transactions in order, e.g. the commit then the reveal.
+    Broadcast {
+        #[command(flatten)]
+        job: JobArgs,
+        #[arg(long = "tx", required = true)]
+        txs: Vec<String>,
+        /// Only test mempool acceptance instead of broadcasting.
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Track commit/reveal pairs through broadcast and confirmation.
+    Track {
+        /// Journal file holding the tracked records.
+        #[arg(long, default_value = "psbt-journal.jsonl")]
+        journal: PathBuf,
+        #[command(subcommand)]
+        action: TrackAction,
+    },
+}
 
-#[cfg(test)]
-mod test;
-mod types;
-mod utils;
-mod worker;
+#[derive(Debug, Subcommand)]
+enum TrackAction {
+    /// Start tracking the commit and reveal of a mining result.
+    Add {
+        #[command(flatten)]
+        job: JobArgs,
+        #[command(flatten)]
+        result: ResultArgs,
+    },
+    /// Drive unfinished records until they are confirmed or parked as failed.
+    Run {
+        #[command(flatten)]
+        job: JobArgs,
+        /// Step each record once instead of polling until done.
+        #[arg(long)]
+        once: bool,
+        /// Seconds between polls.
+        #[arg(long, default_value_t = 30)]
+        poll: u64,
+        #[arg(long, default_value_t = 1)]
+        confirmations: u32,
+        #[arg(long, default_value_t = 5)]
+        max_attempts: u32,
+    },
+    /// Print every tracked record.
+    List,
+    /// Send a failed record back to the stage it failed in.
+    Retry { id: String },
+    /// Give up the funding output of a failed record whose commit the
+    /// backend finds neither in the mempool nor on chain.
+    Release {
+        #[command(flatten)]
+        job: JobArgs,
+        id: String,
+    },
+}
 
-const OUTPUT_BYTES_BASE: u64 = 43;
-const DUST_AMOUNT: u64 = 546;
+#[derive(Debug, Args)]
+struct JobArgs {
+    /// Job file (JSON or TOML). Use `-` for stdin.
+    #[arg(conflicts_with = "env")]
+    input: Option<PathBuf>,
+    /// Read the job from this environment variable. Without an input path,
+    /// `PSBT_JOB` is used if set, otherwise stdin.
+    #[arg(long)]
+    env: Option<String>,
+    /// Job format; detected from the extension or content when omitted.
+    #[arg(long)]
+    format: Option<InputFormat>,
+    /// `electrum`, `bitcoind` or `esplora`; defaults to the first configured
+    /// of bitcoind, esplora and electrum.
+    #[arg(long)]
+    backend: Option<BackendKind>,
+    /// Sequences to search: `any`, `signal` (BIP-125 replaceable) or
+    /// `final`; overrides the job's `sequencePolicy.rbf`. `final` leaves only
+    /// 0xfffffffe and 0xffffffff, two candidates per grind variant.
+    #[arg(long)]
+    rbf: Option<RbfPolicy>,
+    /// Only search sequences that cannot mean a relative lock time.
+    #[arg(long)]
+    no_relative_lock_time: bool,
+    /// Emit transactions that default relay policy rejects.
+    #[arg(long)]
+    allow_non_standard: bool,
+    /// Current block height, against which lock times are checked; overrides
+    /// the job's `blockHeight`.
+    #[arg(long)]
+    block_height: Option<u32>,
+}
 
-#[derive(Debug, Serialize, Deserialize)]
-struct Success {
-    sequence: u64,
+#[derive(Debug, Args)]
+struct ResultArgs {
+    #[arg(long)]
+    sequence: u32,
+    #[arg(long)]
     nonce: u64,
+    #[arg(long)]
     time: u64,
-    magic: String,
+    /// Fee rate the result was mined with; required when the job sets
+    /// `feeTargetBlocks`.
+    #[arg(long)]
+    satsbyte: Option<u64>,
+    /// Commit version the result was mined with.
+    #[arg(long, default_value_t = 1)]
+    tx_version: i32,
+    /// Commit lock time the result was mined with.
+    #[arg(long, default_value_t = 0)]
+    lock_time: u32,
+    /// Sats taken off the change output when the result was mined.
+    #[arg(long, default_value_t = 0)]
+    change_reduction: u64,
+}
+
+#[derive(Debug, Args)]
+struct SearchArgs {
+    /// Search backend (`auto`, `avx512`, `sha-ni`, `armv8-sha`, `avx2`,
+    /// `sse2` or `scalar`); overrides the job's `searchBackend`.
+    #[arg(long)]
+    search_backend: Option<String>,
+}
+
+impl SearchArgs {
+    fn backend(&self, msg: &mut Root) -> Result<Box<dyn SearchBackend>> {
+        if let Some(name) = &self.search_backend {
+            msg.search_backend = Some(name.clone());
+        }
+        search::by_name(msg.search_backend.as_deref())
+    }
+}
+
+impl ResultArgs {
+    /// Pins the fee rate so the commit is rebuilt exactly as it was mined.
+    fn apply(&self, msg: &mut Root) -> Result<()> {
+        match (self.satsbyte, msg.worker_options.fee_target_blocks) {
+            (Some(satsbyte), _) => msg.worker_options.satsbyte = satsbyte,
+            (None, Some(_)) => bail!("the job uses feeTargetBlocks; pass the mined --satsbyte"),
+            (None, None) => {}
+        }
+        msg.worker_options.fee_target_blocks = None;
+        Ok(())
+    }
+
+    fn claim(&self) -> Claim {
+        Claim {
+            sequence: self.sequence as u64,
+            nonce: self.nonce,
+            time: self.time,
+            variant: self.variant(),
+            txid: None,
+            tx: None,
+        }
+    }
+
+    fn variant(&self) -> Variant {
+        Variant {
+            version: self.tx_version,
+            lock_time: self.lock_time,
+            change_reduction: self.change_reduction,
+        }
+    }
+}
+
+impl JobArgs {
+    fn source(&self) -> InputSource {
+        match (&self.input, &self.env) {
+            (Some(path), _) if path.as_os_str() == "-" => InputSource::Stdin,
+            (Some(path), _) => InputSource::File(path.clone()),
+            (None, Some(name)) => InputSource::Env(name.clone()),
+            (None, None) if std::env::var_os(DEFAULT_JOB_ENV).is_some() => {
+                InputSource::Env(DEFAULT_JOB_ENV.to_string())
+            }
+            (None, None) => InputSource::Stdin,
+        }
+    }
+
+    fn load(&self) -> Result<Root> {
+        let mut msg: Root = input::load(&self.source(), self.format)?;
+        if let Some(rbf) = self.rbf {
+            msg.sequence_policy.rbf = rbf;
+        }
+        if self.no_relative_lock_time {
+            msg.sequence_policy.no_relative_lock_time = true;
+        }
+        if self.allow_non_standard {
+            msg.allow_non_standard = true;
+        }
+        if let Some(height) = self.block_height {
+            msg.block_height = Some(height);
+        }
+        Ok(msg)
+    }
+
+    fn backend(&self, msg: &Root) -> Result<Box<dyn ChainBackend>> {
+        backend::from_options(
+            &msg.worker_options,
+            msg.network.clone().into(),
+            self.backend,
+        )
+    }
+
+    /// Loads the job and resolves `feeTargetBlocks` into `satsbyte`.
+    fn load_with_fees(&self) -> Result<Root> {
+        let mut msg = self.load()?;
+        if msg.worker_options.fee_target_blocks.is_some() {
+            let backend = self.backend(&msg)?;
+            backend::apply_fee_target(&mut msg.worker_options, backend.as_ref())?;
+        }
+        Ok(msg)
+    }
 }
 
 fn main() -> Result<()> {
-    let arg = env::args().nth(1).unwrap();
-    let msg = serde_json::from_str::<Root>(&arg).unwrap();
-
-    let found = Arc::new(AtomicBool::new(false));
-    let result_sequence = Arc::new(AtomicU64::new(0));
-    let result_nonce = Arc::new(AtomicU64::new(0));
-    let result_time = Arc::new(AtomicU64::new(0));
-    rayon::scope(|ctx| {
-        let seq_range_per_worker = worker::MAX_SEQUENCE / msg.concurrency;
-        for i in 0..msg.concurrency {
-            let seq_start = i * seq_range_per_worker;
-            let mut seq_end = seq_start + seq_range_per_worker - 1;
-            if i == msg.concurrency - 1 {
-                seq_end = worker::MAX_SEQUENCE - 1;
+    match Cli::parse().command {
+        Command::Mine {
+            job,
+            search,
+            near_miss_levels,
+            near_miss_journal,
+        } => {
+            let mut msg = job.load_with_fees()?;
+            if near_miss_levels.is_some() || near_miss_journal.is_some() {
+                let options = msg.near_misses.get_or_insert_with(Default::default);
+                if let Some(levels) = near_miss_levels {
+                    options.levels = levels;
+                }
+                if near_miss_journal.is_some() {
+                    options.journal = near_miss_journal;
+                }
             }
-            let found_clone = Arc::clone(&found);
-            let result_sequence_clone = Arc::clone(&result_sequence);
-            let result_nonce_clone = Arc::clone(&result_nonce);
-            let result_time_clone = Arc::clone(&result_time);
-
-            let payload = get_payload(msg.clone(), None, None).unwrap();
-
-            ctx.spawn(move |_s| {
-                (seq_start..=seq_end).into_par_iter().find_any(|seq| {
-                    if found_clone.load(Ordering::SeqCst) {
-                        return true;
-                    }
-                    if seq % 10000 == 0 {
-                        println!(
-                            "Started mining for sequence: {} - {}",
-                            seq,
-                            min(seq + 10000, seq_end)
-                        );
-                    }
-                    let res = predicate(*seq, &payload);
-                    match res {
-                        Ok(have) => {
-                            if have {
-                                found_clone.store(true, Ordering::SeqCst);
-                                result_sequence_clone.store(*seq as u64, Ordering::SeqCst);
-                                result_nonce_clone
-                                    .store(payload.copied_data.args.nonce, Ordering::SeqCst);
-                                result_time_clone
-                                    .store(payload.copied_data.args.time, Ordering::SeqCst);
-                                return true;
-                            }
-                            have
-                        }
-                        Err(err) => {
-                            println!("Error: {:#?}", err);
-                            false
-                        }
-                    }
-                });
-            })
+            let backend = search.backend(&mut msg)?;
+            mine(msg, backend.as_ref())
         }
-    });
+        Command::Batch { job, search, count } => {
+            let mut msg = job.load_with_fees()?;
+            let backend = search.backend(&mut msg)?;
+            let token = CancellationToken::new();
+            let handler_token = token.clone();
+            ctrlc::set_handler(move || handler_token.cancel())?;
+            let report = batch::mine_batch(&msg, count, &token, backend.as_ref())?;
+            println!("{}", serde_json::to_string(&report)?);
+            Ok(())
+        }
+        Command::Coordinate {
+            job,
+            listen,
+            share_bitwork,
+        } => {
+            let mut msg = job.load_with_fees()?;
+            if share_bitwork.is_some() {
+                msg.share_bitwork = share_bitwork;
+            }
+            coordinate(msg, &listen)
+        }
+        Command::Work {
+            connect,
+            search,
+            threads,
+            name,
+        } => {
+            let hasher = search::hasher_by_name(search.search_backend.as_deref())?;
+            let threads = threads.unwrap_or(Scheduler::default().threads);
+            cluster::work(connect, hasher, threads, name)
+        }
+        Command::Plan { job, nonce, time } => {
+            let plan = miner::plan(&job.load_with_fees()?, nonce, time)?;
+            println!("{}", serde_json::to_string(&plan)?);
+            Ok(())
+        }
+        Command::Verify {
+            job,
+            result,
+            txid,
+            tx,
+        } => verify(job.load()?, result, txid, tx),
+        Command::Decode { hex } => decode(&hex),
+        Command::Validate {
+            commit,
+            reveal,
+            ticker,
+            mint_amount,
+            mint_bitworkc,
+            mint_bitworkr,
+        } => {
+            let rules = MintRules {
+                ticker,
+                mint_amount,
+                mint_bitworkc,
+                mint_bitworkr,
+            };
+            let validation = validate_mint(&parse_tx(&commit)?, &parse_tx(&reveal)?, &rules);
+            println!("{}", serde_json::to_string(&validation)?);
+            if !validation.credited {
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        Command::BuildReveal { job, result } => build_reveal(job.load()?, result),
+        Command::Recover {
+            job,
+            result,
+            output,
+            recovery_wif,
+            address,
+            fee_rate,
+        } => {
+            let mut msg = job.load()?;
+            result.apply(&mut msg)?;
+            let mut payload =
+                miner::get_payload(msg.clone(), Some(result.time), Some(result.nonce))?;
+            payload.variant = result.variant();
+            let commit = worker::build_commit_tx(result.sequence, &payload)?;
+            let path = match recovery_wif {
+                Some(wif) => RecoveryPath::Refund(PrivateKey::from_wif(&wif)?),
+                None => RecoveryPath::Key,
+            };
+            let destination = match address {
+                Some(address) => Address::from_str(&address)?
+                    .require_network(msg.network.clone().into())?
+                    .script_pubkey(),
+                None => payload.funding_private_script_pubkey.clone(),
+            };
+            let satsbyte = fee_rate.unwrap_or(msg.worker_options.satsbyte);
+            let tx = recover::recover(
+                &msg,
+                &payload,
+                &commit,
+                output,
+                &path,
+                destination,
+                satsbyte,
+            )?;
+            println!(
+                "{}",
+                json!({
+                    "commitTxid": commit.txid().to_string(),
+                    "recoverTxid": tx.txid().to_string(),
+                    "recoverTx": serialize_hex(&tx),
+                })
+            );
+            Ok(())
+        }
+        Command::Bump {
+            job,
+            result,
+            search,
+            fee_rate,
+            descendant_fees,
+        } => {
+            let mut msg = job.load()?;
+            result.apply(&mut msg)?;
+            let hasher = search::hasher_by_name(search.search_backend.as_deref())?;
+            let options = BumpOptions {
+                satsbyte: fee_rate,
+                descendant_fees,
+            };
+            let token = CancellationToken::new();
+            let handler_token = token.clone();
+            ctrlc::set_handler(move || handler_token.cancel())?;
+            let bumped = rbf::bump(&msg, &result.claim(), &options, &token, hasher)?;
+            println!("{}", serde_json::to_string(&bumped)?);
+            Ok(())
+        }
+        Command::Estimate {
+            job,
+            search,
+            sample,
+        } => {
+            let mut msg = job.load()?;
+            let backend = search.backend(&mut msg)?;
+            estimate(msg, backend.as_ref(), sample)
+        }
+        Command::Bench {
+            job,
+            search,
+            mut threads,
+            per_thread,
+        } => {
+            if threads.is_empty() {
+                let cores = Scheduler::default().threads;
+                threads = (0..)
+                    .map(|i| 1 << i)
+                    .take_while(|n| *n < cores)
+                    .chain([cores])
+                    .collect();
+            }
+            let mut msg = job.load()?;
+            let backend = search.backend(&mut msg)?;
+            let runs = miner::benchmark(&msg, backend.as_ref(), &threads, per_thread)?;
+            println!("{}", serde_json::to_string(&runs)?);
+            Ok(())
+        }
+        Command::Utxos(job) => {
+            let msg = job.load()?;
+            let backend = job.backend(&msg)?;
+            let utxos = backend.list_unspent(&miner::funding_address(&msg)?.script_pubkey())?;
+            println!("{}", serde_json::to_string(&utxos)?);
+            Ok(())
+        }
+        Command::Ticker { job, ticker } => {
+            let msg = job.load()?;
+            let ticker = ticker.unwrap_or(msg.copied_data.args.mint_ticker);
+            let client = ElectrumClient::new(&msg.worker_options.electrum_api)?;
+            println!("{}", client.get_by_ticker(&ticker)?);
+            Ok(())
+        }
+        Command::Broadcast { job, txs, dry_run } => {
+            let msg = job.load()?;
+            let backend = job.backend(&msg)?;
+            let txs = txs
+                .iter()
+                .map(|hex| parse_tx(hex))
+                .collect::<Result<Vec<_>>>()?;
+            if dry_run {
+                let results = backend.test_mempool_accept(&txs)?;
+                println!("{}", serde_json::to_string(&results)?);
+                return Ok(());
+            }
+            for tx in &txs {
+                println!("{}", backend.broadcast(tx)?);
+            }
+            Ok(())
+        }
+        Command::Track { journal, action } => track(journal, action),
+    }
+}
 
-    let success = Success {
-        sequence: result_sequence.load(Ordering::SeqCst),
-        nonce: result_nonce.load(Ordering::SeqCst),
-        time: result_time.load(Ordering::SeqCst),
-        magic: "a87c1c7c-02a2-4d7d-ae59-81b176127c81".to_string(),
-    };
-    println!("{}", serde_json::to_string(&success).unwrap());
+fn mine(msg: Root, backend: &dyn SearchBackend) -> Result<()> {
+    let token = CancellationToken::new();
+    let handler_token = token.clone();
+    ctrlc::set_handler(move || handler_token.cancel())?;
+
+    match miner::mine_with(&msg, &token, backend)? {
+        MineOutcome::Found(success) => println!("{}", serde_json::to_string(&success)?),
+        MineOutcome::Stopped(report) => println!("{}", serde_json::to_string(&report)?),
+    }
     Ok(())
 }
 
-fn get_payload(mut msg: Root, test_time: Option<u64>, test_nonce: Option<u64>) -> Result<Payload> {
-    msg.copied_data.args.nonce = OsRng.gen_range(0..10000000);
-    msg.copied_data.args.time = SystemTime::now()
-        .duration_since(SystemTime::UNIX_EPOCH)?
-        .as_secs();
-    if let Some(time) = test_time {
-        msg.copied_data.args.time = time;
+fn coordinate(msg: Root, listen: &str) -> Result<()> {
+    let token = CancellationToken::new();
+    let handler_token = token.clone();
+    ctrlc::set_handler(move || handler_token.cancel())?;
+
+    let coordinated = cluster::coordinate(&msg, TcpListener::bind(listen)?, &token)?;
+    match coordinated.outcome {
+        MineOutcome::Found(success) => println!("{}", serde_json::to_string(&success)?),
+        MineOutcome::Stopped(report) => println!("{}", serde_json::to_string(&report)?),
     }
-    if let Some(nonce) = test_nonce {
-        msg.copied_data.args.nonce = nonce;
+    if !coordinated.contributions.is_empty() {
+        let contributions = serde_json::json!({ "contributions": coordinated.contributions });
+        println!("{}", contributions);
     }
-    let private_key = PrivateKey::from_wif(&msg.funding_wif)?;
-    let secp = secp256k1::Secp256k1::new();
-    let (xonly_pubkey, parity) =
-        XOnlyPublicKey::from_keypair(&Keypair::from_secret_key(&secp, &private_key.inner));
-    // get public key
-    xonly_pubkey.public_key(parity);
-    let (_address, fixed_output_script_pubkey) = utils::get_address_by_copied_data(
-        &secp,
-        &xonly_pubkey,
-        &msg.copied_data,
-        &msg.worker_options.op_type,
+    Ok(())
+}
+
+fn verify(
+    mut msg: Root,
+    result: ResultArgs,
+    txid: Option<String>,
+    tx: Option<String>,
+) -> Result<()> {
+    result.apply(&mut msg)?;
+    let claim = Claim {
+        txid,
+        tx: tx.as_deref().map(parse_tx).transpose()?,
+        ..result.claim()
+    };
+    let verification = verify_claim(&msg, &claim)?;
+    println!("{}", serde_json::to_string(&verification)?);
+    if !verification.valid {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+fn parse_tx(hex: &str) -> Result<Transaction> {
+    Ok(deserialize(&hex::decode(hex.trim())?)?)
+}
+
+fn decode(hex: &str) -> Result<()> {
+    let bytes = hex::decode(hex.trim())?;
+    let script = match deserialize::<Transaction>(&bytes) {
+        Ok(tx) => {
+            let Some(input) = tx.input.first() else {
+                bail!("transaction has no inputs");
+            };
+            let Some(script) = input.witness.tapscript() else {
+                bail!("first input is not a taproot script spend");
+            };
+            script.to_bytes()
+        }
+        Err(_) => bytes,
+    };
+    let envelope = utils::decode_envelope(Script::from_bytes(&script))?;
+    println!(
+        "{}",
+        json!({
+            "publicKey": envelope.xonly_public_key.to_string(),
+            "opType": envelope.op_type,
+            "payload": envelope.copied_data,
+        })
     );
+    Ok(())
+}
 
-    let private_address = Address::p2tr(&secp, xonly_pubkey, None, msg.network.into());
-
-    let total_inputs_value = msg.funding_utxo.value;
-    let total_outputs_value = get_output_value_for_commit(msg.fees);
-    let calculated_fee = total_inputs_value - total_outputs_value;
-    let mut need_change_fee_output = false;
-    let expected_fee = msg.fees.commit_fee_only + msg.worker_options.satsbyte * OUTPUT_BYTES_BASE;
-    let difference_between_calculated_and_expected_fee = calculated_fee - expected_fee;
-    if calculated_fee > 0
-        && difference_between_calculated_and_expected_fee > 0
-        && difference_between_calculated_and_expected_fee >= DUST_AMOUNT
-    {
-        need_change_fee_output = true;
+/// The commit, one reveal per commit output, and how to watch each output.
+fn commit_and_reveals(
+    mut msg: Root,
+    result: &ResultArgs,
+) -> Result<(Transaction, Vec<Transaction>, Vec<CommitOutput>)> {
+    result.apply(&mut msg)?;
+    let mut payload = miner::get_payload(msg.clone(), Some(result.time), Some(result.nonce))?;
+    payload.variant = result.variant();
+    let commit = worker::build_commit_tx(result.sequence, &payload)?;
+    let hasher = search::hasher_by_name(msg.search_backend.as_deref())?;
+    let token = CancellationToken::new();
+    let handler_token = token.clone();
+    ctrlc::set_handler(move || handler_token.cancel())?;
+    let reveals = reveal::reveal_templates(&msg, &payload, commit.txid())?
+        .iter()
+        .map(|template| {
+            reveal::mine_reveal_tx(
+                template,
+                &payload,
+                payload.copied_data.args.bitworkr.as_deref(),
+                miner::sequence_range(&msg.sequence_policy),
+                &token,
+                hasher,
+            )
+        })
+        .collect::<Result<Vec<_>>>()?;
+    interpreter::verify_mint(&payload, &commit, &reveals)?;
+    policy::ensure_mint_standard(&msg, &payload, &commit, &reveals)?;
+    let outputs = descriptor::commit_outputs(&msg, &payload)?;
+    Ok((commit, reveals, outputs))
+}
+
+fn build_reveal(msg: Root, result: ResultArgs) -> Result<()> {
+    let (commit, reveals, outputs) = commit_and_reveals(msg, &result)?;
+    let mut built = json!({
+        "commitTxid": commit.txid().to_string(),
+        "commitTx": serialize_hex(&commit),
+        "revealTxid": reveals[0].txid().to_string(),
+        "revealTx": serialize_hex(&reveals[0]),
+        "commitOutputs": outputs,
+    });
+    if reveals.len() > 1 {
+        built["reveals"] = reveals
+            .iter()
+            .map(|reveal| {
+                json!({
+                    "revealTxid": reveal.txid().to_string(),
+                    "revealTx": serialize_hex(reveal),
+                })
+            })
+            .collect();
     }
+    println!("{}", built);
+    Ok(())
+}
 
-    Ok(Payload {
-        secp,
-        copied_data: msg.copied_data,
-        funding_utxo_id: msg.funding_utxo.txid.parse()?,
-        funding_utxo_index: msg.funding_utxo.index,
-        funding_utxo_vout: msg.funding_utxo.vout,
-        funding_utxo_value: Amount::from_sat(msg.funding_utxo.value),
-        xonly_pub_key: xonly_pubkey,
-        funding_private_key: private_key,
-        funding_private_script_pubkey: private_address.script_pubkey(),
-        funding_value: Amount::from_sat(difference_between_calculated_and_expected_fee),
-        fixed_output_script_pubkey,
-        fixed_output_value: Amount::from_sat(get_output_value_for_commit(msg.fees)),
-        need_change_fee_output,
-        valid_prefix: msg.worker_bitwork_info_commit.prefix,
-        valid_ext: msg.worker_bitwork_info_commit.ext,
-    })
+fn track(journal: PathBuf, action: TrackAction) -> Result<()> {
+    let mut journal = Journal::open(journal)?;
+    match action {
+        TrackAction::Add { job, result } => {
+            let (commit, reveals, _) = commit_and_reveals(job.load()?, &result)?;
+            let [reveal] = &reveals[..] else {
+                bail!("only single-output commits can be tracked");
+            };
+            let record = Record::new(&commit, reveal);
+            println!("{}", serde_json::to_string(&record)?);
+            journal.insert(record)?;
+        }
+        TrackAction::Run {
+            job,
+            once,
+            poll,
+            confirmations,
+            max_attempts,
+        } => {
+            let msg = job.load()?;
+            let backend = job.backend(&msg)?;
+            let policy = Policy {
+                confirmations,
+                max_attempts,
+            };
+            loop {
+                let pending = journal.step_all(backend.as_ref(), &policy)?;
+                if once || pending == 0 {
+                    break;
+                }
+                thread::sleep(Duration::from_secs(poll));
+            }
+            for record in journal.records() {
+                println!("{}", serde_json::to_string(record)?);
+            }
+        }
+        TrackAction::List => {
+            for record in journal.records() {
+                println!("{}", serde_json::to_string(record)?);
+            }
+        }
+        TrackAction::Retry { id } => journal.retry(&id)?,
+        TrackAction::Release { job, id } => {
+            let msg = job.load()?;
+            let backend = job.backend(&msg)?;
+            journal.release(&id, backend.as_ref())?;
+        }
+    }
+    Ok(())
+}
+
+fn estimate(msg: Root, backend: &dyn SearchBackend, sample: u32) -> Result<()> {
+    let Some(prefix) = &msg.worker_bitwork_info_commit.prefix else {
+        bail!("job has no commit bitwork");
+    };
+    let ext = msg.worker_bitwork_info_commit.ext;
+    let expected_attempts = worker::bitwork_difficulty(prefix, ext);
+
+    let per_thread = miner::benchmark(&msg, backend, &[1], sample.max(1))?[0].hashes_per_second;
+    let threads = Scheduler::default().threads as f64;
+    println!(
+        "{}",
+        json!({
+            "searchBackend": backend.name(),
+            "prefix": prefix,
+            "ext": ext,
+            "expectedAttempts": expected_attempts,
+            "hashesPerSecond": per_thread * threads,
+            "expectedSeconds": expected_attempts / (per_thread * threads),
+        })
+    );
+    Ok(())
 }
@@ -0,0 +1,675 @@
+use std::{
+    cmp::min,
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Instant, SystemTime},
+};
+
+use anyhow::{anyhow, bail, Result};
+use bitcoin::{
+    absolute::LOCK_TIME_THRESHOLD,
+    key::{rand, rand::rngs::OsRng, Keypair},
+    secp256k1, Address, Amount, PrivateKey, ScriptBuf, Sequence, XOnlyPublicKey,
+};
+use rand::Rng;
+
+use crate::{
+    audit::NearMisses,
+    cancel::CancellationToken,
+    descriptor, policy,
+    scheduler::{Scheduler, Task, BATCH_SIZE},
+    search::{self, Candidate, SearchBackend},
+    tweak,
+    types::{
+        BenchmarkRun, Checkpoint, GrindOptions, Payload, Plan, RbfPolicy, Root, SequencePolicy,
+        SequenceRange, StopReason, StopReport, Success, Variant, WorkerCheckpoint,
+    },
+    utils::{self, get_output_value_for_commit},
+    worker,
+};
+
+pub const OUTPUT_BYTES_BASE: u64 = 43;
+pub const DUST_AMOUNT: u64 = 546;
+pub const MAGIC: &str = "a87c1c7c-02a2-4d7d-ae59-81b176127c81";
+
+/// Sequences are mined and checkpointed in chunks of this size.
+pub const CHUNK_SIZE: u32 = 10000;
+
+/// Most commit variants a job may grind. Each one is a template held by
+/// every worker and hashed at every sequence.
+pub const MAX_VARIANTS: usize = 1 << 16;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MineOutcome {
+    Found(Success),
+    Stopped(StopReport),
+}
+
+/// Runs the search described by `msg` until a solution is found, the range is
+/// exhausted or `token` is cancelled.
+pub fn mine(msg: &Root, token: &CancellationToken) -> Result<MineOutcome> {
+    let backend = search::by_name(msg.search_backend.as_deref())?;
+    mine_with(msg, token, backend.as_ref())
+}
+
+/// [`mine`] with an explicit search backend.
+pub fn mine_with(
+    msg: &Root,
+    token: &CancellationToken,
+    backend: &dyn SearchBackend,
+) -> Result<MineOutcome> {
+    let workers = job_workers(msg)?;
+    let pairs: Vec<(u64, u64)> = workers.iter().map(|w| (w.time, w.nonce)).collect();
+    let payloads = worker_payloads(msg, &pairs)?;
+    if let Some(payload) = payloads.first() {
+        search_space(msg, payload)?;
+    }
+
+    let tasks = pending_chunks(&workers);
+    let searches = payloads
+        .iter()
+        .map(|payload| backend.prepare(payload))
+        .collect::<Result<Vec<_>>>()?;
+    // Every owner shares the grind options, so the variant count too. Batches
+    // shrink with it to keep the stop flag checked as often.
+    let variants = searches.first().map_or(1, |search| search.variants());
+    let scheduler = Scheduler {
+        batch_size: (BATCH_SIZE / variants as u32).max(1),
+        ..Scheduler::default()
+    };
+    let near_misses = near_misses(msg)?;
+    if let Some(audit) = &near_misses {
+        let probe = SequenceRange { start: 0, end: 0 };
+        if searches
+            .iter()
+            .any(|search| search.search_near(probe, audit.matcher()).is_none())
+        {
+            bail!(
+                "search backend `{}` cannot count near misses",
+                backend.name()
+            );
+        }
+    }
+    let run = scheduler.run(tasks, workers.len(), token.found_flag(), |owner, batch| {
+        let Some(audit) = &near_misses else {
+            return searches[owner].search(batch).first().copied();
+        };
+        let (hits, near) = searches[owner].search_near(batch, audit.matcher())?;
+        let searched = (batch.end - batch.start) as u64 + 1;
+        audit.record(&payloads[owner], searched * variants as u64, &near);
+        if audit.is_implausible() {
+            token.found_flag().store(true, Ordering::Relaxed);
+        }
+        hits.first().copied()
+    });
+    let attempts = run.attempts * variants as u64;
+
+    if let Some(hit) = run.hit {
+        return Ok(MineOutcome::Found(success(
+            msg,
+            &payloads[hit.owner],
+            hit.found,
+        )));
+    }
+    let implausible = near_misses.as_ref().is_some_and(NearMisses::is_implausible);
+    let status = if token.is_cancelled() {
+        StopReason::Cancelled
+    } else if implausible {
+        StopReason::Implausible
+    } else {
+        StopReason::Exhausted
+    };
+    Ok(MineOutcome::Stopped(StopReport {
+        near_misses: near_misses.as_ref().map(NearMisses::report),
+        ..stop_report(status, attempts, workers, run.completed)
+    }))
+}
+
+/// The near-miss counter the job asks for, if any.
+fn near_misses(msg: &Root) -> Result<Option<NearMisses>> {
+    let Some(options) = &msg.near_misses else {
+        return Ok(None);
+    };
+    let prefix = msg
+        .worker_bitwork_info_commit
+        .prefix
+        .as_deref()
+        .ok_or_else(|| anyhow!("near misses need a commit bitwork prefix"))?;
+    Ok(Some(NearMisses::new(prefix, options)?))
+}
+
+/// The job's workers: resumed from its checkpoint, or one per sequence
+/// partition. Fresh workers roll consecutive nonces from one random start,
+/// stepping over each other's fan-out outputs, so [`worker_payloads`]
+/// derives them all in one batch.
+pub fn job_workers(msg: &Root) -> Result<Vec<WorkerCheckpoint>> {
+    let allowed = sequence_range(&commit_sequence_policy(msg));
+    if allowed.start > allowed.end {
+        bail!("the sequence policy leaves no sequence to search");
+    }
+    Ok(match &msg.resume {
+        Some(checkpoint) => {
+            if let Some(worker) = checkpoint.workers.iter().find(|worker| {
+                worker.start > worker.end
+                    || worker.start < allowed.start
+                    || worker.end > allowed.end
+            }) {
+                bail!(
+                    "checkpoint range {}..={} lies outside the sequence policy's {}..={}",
+                    worker.start,
+                    worker.end,
+                    allowed.start,
+                    allowed.end
+                );
+            }
+            checkpoint.workers.clone()
+        }
+        None => {
+            let time = now()?;
+            let first = random_nonce();
+            let stride = fan_out(msg) as u64;
+            partition(msg.concurrency, allowed)
+                .into_iter()
+                .enumerate()
+                .map(|(worker, (start, end))| WorkerCheckpoint {
+                    nonce: first + worker as u64 * stride,
+                    time,
+                    start,
+                    end,
+                    completed: vec![],
+                })
+                .collect()
+        }
+    })
+}
+
+/// The chunks of each worker's range not yet searched, in sequence order.
+pub fn pending_chunks(workers: &[WorkerCheckpoint]) -> Vec<Task> {
+    workers
+        .iter()
+        .enumerate()
+        .flat_map(|(owner, worker)| {
+            chunks(worker.start, worker.end)
+                .filter(|chunk| !is_covered(&worker.completed, chunk))
+                .map(move |range| Task { owner, range })
+        })
+        .collect()
+}
+
+/// The result for a confirmed hit in `payload`.
+pub fn success(msg: &Root, payload: &Payload, hit: Candidate) -> Success {
+    Success {
+        sequence: hit.sequence as u64,
+        nonce: payload.copied_data.args.nonce,
+        time: payload.copied_data.args.time,
+        magic: MAGIC.to_string(),
+        satsbyte: msg
+            .worker_options
+            .fee_target_blocks
+            .map(|_| msg.worker_options.satsbyte),
+        variant: Some(hit.variant).filter(|variant| *variant != Variant::default()),
+    }
+}
+
+/// Reports a search that ended without a hit, with the ranges `completed`
+/// per worker added to its checkpoint.
+pub fn stop_report(
+    status: StopReason,
+    attempts: u64,
+    workers: Vec<WorkerCheckpoint>,
+    completed: Vec<Vec<SequenceRange>>,
+) -> StopReport {
+    let workers: Vec<WorkerCheckpoint> = workers
+        .into_iter()
+        .zip(completed)
+        .map(|(worker, done)| WorkerCheckpoint {
+            completed: merge_ranges([worker.completed.clone(), done].concat()),
+            ..worker
+        })
+        .collect();
+    StopReport {
+        status,
+        attempts,
+        ranges: merge_ranges(workers.iter().flat_map(|w| w.completed.clone()).collect()),
+        checkpoint: Checkpoint { workers },
+        magic: MAGIC.to_string(),
+        near_misses: None,
+    }
+}
+
+/// Times the scheduler at each thread count over `per_thread` candidates per
+/// thread, so perfect scaling keeps the run time constant.
+pub fn benchmark(
+    msg: &Root,
+    backend: &dyn SearchBackend,
+    threads: &[usize],
+    per_thread: u32,
+) -> Result<Vec<BenchmarkRun>> {
+    // A bitwork no txid meets, so every candidate is searched.
+    let mut msg = msg.clone();
+    msg.worker_bitwork_info_commit.prefix = Some("0".repeat(64));
+    msg.worker_bitwork_info_commit.ext = None;
+    let payload = get_payload(msg, None, None)?;
+    let search = backend.prepare(&payload)?;
+    let mut runs: Vec<BenchmarkRun> = vec![];
+    for &count in threads {
+        // Small tasks leave the threads room to steal from one another.
+        let task_size = (per_thread / 8).max(1);
+        let tasks = (0..per_thread.saturating_mul(count as u32))
+            .step_by(task_size as usize)
+            .map(|start| Task {
+                owner: 0,
+                range: SequenceRange {
+                    start,
+                    end: start + task_size - 1,
+                },
+            })
+            .collect();
+        let scheduler = Scheduler {
+            threads: count,
+            batch_size: (BATCH_SIZE / search.variants() as u32).max(1),
+            ..Scheduler::default()
+        };
+        let started = Instant::now();
+        let run = scheduler.run(tasks, 1, &AtomicBool::new(false), |_, batch| {
+            search.search(batch).first().copied()
+        });
+        let seconds = started.elapsed().as_secs_f64();
+        let attempts = run.attempts * search.variants() as u64;
+        let hashes_per_second = attempts as f64 / seconds;
+        let base = runs
+            .first()
+            .map_or(hashes_per_second / count as f64, |run| {
+                run.hashes_per_second / run.threads as f64
+            });
+        let speedup = hashes_per_second / base;
+        runs.push(BenchmarkRun {
+            threads: count,
+            candidates: attempts,
+            seconds,
+            hashes_per_second,
+            speedup,
+            efficiency: speedup / count as f64,
+        });
+    }
+    Ok(runs)
+}
+
+/// Every variant of the commit the job's grind options allow, the plain
+/// commit first.
+pub fn variants(payload: &Payload) -> Result<Vec<Variant>> {
+    let grind = &payload.grind;
+    if grind.max_lock_time >= LOCK_TIME_THRESHOLD {
+        bail!("maxLockTime {} is not a block height", grind.max_lock_time);
+    }
+    let versions = if grind.versions.is_empty() {
+        vec![1]
+    } else {
+        grind.versions.clone()
+    };
+    if let Some(version) = versions
+        .iter()
+        .find(|version| !(1..=policy::MAX_STANDARD_VERSION).contains(*version))
+    {
+        bail!(
+            "cannot grind transaction version {}; only 1 and 2 relay",
+            version
+        );
+    }
+    // The change output must stay above dust.
+    let max_reduction = if payload.need_change_fee_output {
+        grind
+            .change_tolerance
+            .min(payload.funding_value.to_sat().saturating_sub(DUST_AMOUNT))
+    } else {
+        0
+    };
+    let count = (max_reduction + 1)
+        .checked_mul(grind.max_lock_time as u64 + 1)
+        .and_then(|count| count.checked_mul(versions.len() as u64))
+        .filter(|count| *count <= MAX_VARIANTS as u64)
+        .ok_or_else(|| {
+            anyhow!(
+                "grinding {} change reductions, {} lock times and {} versions exceeds {} variants; \
+                 lower changeTolerance or maxLockTime",
+                max_reduction + 1,
+                grind.max_lock_time as u64 + 1,
+                versions.len(),
+                MAX_VARIANTS
+            )
+        })?;
+    let mut variants = Vec::with_capacity(count as usize);
+    for change_reduction in 0..=max_reduction {
+        for lock_time in 0..=grind.max_lock_time {
+            for &version in &versions {
+                variants.push(Variant {
+                    version,
+                    lock_time,
+                    change_reduction,
+                });
+            }
+        }
+    }
+    Ok(variants)
+}
+
+/// Payloads for each `(time, nonce)` pair, with the commit outputs derived in
+/// batches rather than one taproot tweak at a time.
+pub fn worker_payloads(msg: &Root, pairs: &[(u64, u64)]) -> Result<Vec<Payload>> {
+    let Some(&(time, nonce)) = pairs.first() else {
+        return Ok(vec![]);
+    };
+    let base = get_payload(msg.clone(), Some(time), Some(nonce))?;
+    let outputs = fan_out(msg);
+    let output_pairs: Vec<(u64, u64)> = pairs
+        .iter()
+        .flat_map(|&(time, nonce)| (0..outputs).map(move |o| (time, fan_out_nonce(nonce, o))))
+        .collect();
+    let scripts = tweak::commit_scripts(
+        &base.secp,
+        &base.xonly_pub_key,
+        &base.copied_data,
+        &msg.worker_options.op_type,
+        base.refund_leaf.as_ref(),
+        &output_pairs,
+    )?;
+    // The first pair is also derived the usual way, which guards the batch.
+    if scripts[0] != base.fixed_output_script_pubkey
+        || scripts[1..outputs] != base.fan_out_script_pubkeys[..]
+    {
+        bail!("batched taproot tweak disagrees with the commit address");
+    }
+    Ok(pairs
+        .iter()
+        .zip(scripts.chunks(outputs))
+        .map(|(&(time, nonce), scripts)| {
+            let mut payload = base.clone();
+            payload.copied_data.args.time = time;
+            payload.copied_data.args.nonce = nonce;
+            payload.fixed_output_script_pubkey = scripts[0].clone();
+            payload.fan_out_script_pubkeys = scripts[1..].to_vec();
+            payload
+        })
+        .collect())
+}
+
+/// Commit outputs the job asks for.
+pub fn fan_out(msg: &Root) -> usize {
+    msg.fan_out.max(1) as usize
+}
+
+/// Nonce of the envelope in commit output `output`; the first output keeps
+/// the mined nonce.
+pub fn fan_out_nonce(nonce: u64, output: usize) -> u64 {
+    nonce + output as u64
+}
+
+/// Splits the sequence space into one inclusive range per worker.
+pub fn partition(concurrency: u32, range: SequenceRange) -> Vec<(u32, u32)> {
+    let size = (range.end - range.start) as u64 + 1;
+    let workers = (concurrency as u64).clamp(1, size);
+    let seq_range_per_worker = size / workers;
+    (0..workers)
+        .map(|i| {
+            let seq_start = range.start as u64 + i * seq_range_per_worker;
+            let mut seq_end = seq_start + seq_range_per_worker - 1;
+            if i == workers - 1 {
+                seq_end = range.end as u64;
+            }
+            (seq_start as u32, seq_end as u32)
+        })
+        .collect()
+}
+
+/// The sequence policy commits of `msg` are mined under. Grinding version 2
+/// rules out relative lock times unless `allowNonStandard` is set, since
+/// under BIP-68 a version 2 hit with the disable flag clear would lock the
+/// funding input for that many blocks.
+pub fn commit_sequence_policy(msg: &Root) -> SequencePolicy {
+    SequencePolicy {
+        no_relative_lock_time: msg.sequence_policy.no_relative_lock_time
+            || (!msg.allow_non_standard && msg.grind.versions.iter().any(|version| *version >= 2)),
+        ..msg.sequence_policy
+    }
+}
+
+/// The sequences `policy` allows. Without restrictions that is every value
+/// but `0xffffffff`, as mining always searched.
+pub fn sequence_range(policy: &SequencePolicy) -> SequenceRange {
+    let mut range = match policy.rbf {
+        RbfPolicy::Any => SequenceRange {
+            start: 0,
+            end: worker::MAX_SEQUENCE - 1,
+        },
+        RbfPolicy::Signal => SequenceRange {
+            start: 0,
+            end: Sequence::ENABLE_RBF_NO_LOCKTIME.0,
+        },
+        RbfPolicy::Final => SequenceRange {
+            start: Sequence::ENABLE_LOCKTIME_NO_RBF.0,
+            end: worker::MAX_SEQUENCE,
+        },
+    };
+    if policy.no_relative_lock_time {
+        range.start = range.start.max(worker::SEQUENCE_LOCK_TIME_DISABLE_FLAG);
+    }
+    range
+}
+
+/// Candidates one nonce offers: each sequence the commit policy allows, for
+/// every variant of `payload`. The final policy's two sequences are refused
+/// unless a grind widens them.
+pub fn search_space(msg: &Root, payload: &Payload) -> Result<u64> {
+    let range = sequence_range(&commit_sequence_policy(msg));
+    let variants = variants(payload)?.len() as u64;
+    if msg.sequence_policy.rbf == RbfPolicy::Final && variants == 1 {
+        bail!(
+            "the final rbf policy leaves two sequences per nonce; grind maxLockTime, versions \
+             or changeTolerance to widen the search"
+        );
+    }
+    Ok(((range.end - range.start) as u64 + 1) * variants)
+}
+
+/// Splits `start..=end` into ranges of at most [`CHUNK_SIZE`].
+pub fn chunks(start: u32, end: u32) -> impl Iterator<Item = SequenceRange> {
+    (start..=end)
+        .step_by(CHUNK_SIZE as usize)
+        .map(move |s| SequenceRange {
+            start: s,
+            end: min(s.saturating_add(CHUNK_SIZE - 1), end),
+        })
+}
+
+fn is_covered(completed: &[SequenceRange], range: &SequenceRange) -> bool {
+    completed
+        .iter()
+        .any(|c| c.start <= range.start && range.end <= c.end)
+}
+
+/// Sorts and coalesces overlapping or adjacent ranges.
+pub fn merge_ranges(mut ranges: Vec<SequenceRange>) -> Vec<SequenceRange> {
+    ranges.sort();
+    let mut merged: Vec<SequenceRange> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end.saturating_add(1) => {
+                last.end = last.end.max(range.end);
+            }
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+/// The p2tr address controlled by the funding key.
+pub fn funding_address(msg: &Root) -> Result<Address> {
+    let private_key = PrivateKey::from_wif(&msg.funding_wif)?;
+    let secp = secp256k1::Secp256k1::new();
+    let (xonly_pubkey, _parity) =
+        XOnlyPublicKey::from_keypair(&Keypair::from_secret_key(&secp, &private_key.inner));
+    Ok(Address::p2tr(
+        &secp,
+        xonly_pubkey,
+        None,
+        msg.network.clone().into(),
+    ))
+}
+
+/// Derives the commit address for a job and the funding it needs.
+pub fn plan(msg: &Root, nonce: Option<u64>, time: Option<u64>) -> Result<Plan> {
+    let payload = get_payload(msg.clone(), time, nonce)?;
+    let (commit_address, _) = utils::get_address_by_copied_data(
+        &payload.secp,
+        &payload.xonly_pub_key,
+        &payload.copied_data,
+        &msg.worker_options.op_type,
+        payload.refund_leaf.as_ref(),
+        msg.network.clone().into(),
+    );
+    let commit_output_value = get_output_value_for_commit(msg.fees);
+    let further_outputs = payload.fan_out_script_pubkeys.len() as u64;
+    let required_funding = commit_output_value * (further_outputs + 1)
+        + msg.fees.commit_fee_only
+        + msg.worker_options.satsbyte * OUTPUT_BYTES_BASE * further_outputs;
+    let fan_out_addresses = payload
+        .fan_out_script_pubkeys
+        .iter()
+        .map(|script| Ok(Address::from_script(script, msg.network.clone().into())?.to_string()))
+        .collect::<Result<_>>()?;
+    Ok(Plan {
+        commit_address,
+        nonce: payload.copied_data.args.nonce,
+        time: payload.copied_data.args.time,
+        commit_output_value,
+        required_funding,
+        required_funding_with_change: required_funding
+            + msg.worker_options.satsbyte * OUTPUT_BYTES_BASE
+            + DUST_AMOUNT,
+        funding_value: msg.funding_utxo.value,
+        change_output: payload.need_change_fee_output,
+        fan_out_addresses,
+        commit_outputs: descriptor::commit_outputs(msg, &payload)?,
+        search_space: search_space(msg, &payload)?,
+    })
+}
+
+fn random_nonce() -> u64 {
+    OsRng.gen_range(0..10000000)
+}
+
+fn now() -> Result<u64> {
+    Ok(SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)?
+        .as_secs())
+}
+
+pub fn get_payload(
+    mut msg: Root,
+    test_time: Option<u64>,
+    test_nonce: Option<u64>,
+) -> Result<Payload> {
+    msg.copied_data.args.nonce = random_nonce();
+    msg.copied_data.args.time = now()?;
+    if let Some(time) = test_time {
+        msg.copied_data.args.time = time;
+    }
+    if let Some(nonce) = test_nonce {
+        msg.copied_data.args.nonce = nonce;
+    }
+    let private_key = PrivateKey::from_wif(&msg.funding_wif)?;
+    let secp = secp256k1::Secp256k1::new();
+    let (xonly_pubkey, parity) =
+        XOnlyPublicKey::from_keypair(&Keypair::from_secret_key(&secp, &private_key.inner));
+    // get public key
+    xonly_pubkey.public_key(parity);
+    let refund_leaf = msg.refund.as_ref().map(utils::refund_leaf).transpose()?;
+    let (_address, fixed_output_script_pubkey) = utils::get_address_by_copied_data(
+        &secp,
+        &xonly_pubkey,
+        &msg.copied_data,
+        &msg.worker_options.op_type,
+        refund_leaf.as_ref(),
+        msg.network.clone().into(),
+    );
+
+    // Each further output of a fan-out commit carries the next nonce.
+    let fan_out_script_pubkeys: Vec<ScriptBuf> = (1..fan_out(&msg))
+        .map(|output| {
+            let mut copied_data = msg.copied_data.clone();
+            copied_data.args.nonce = fan_out_nonce(copied_data.args.nonce, output);
+            let (_address, script_pubkey) = utils::get_address_by_copied_data(
+                &secp,
+                &xonly_pubkey,
+                &copied_data,
+                &msg.worker_options.op_type,
+                refund_leaf.as_ref(),
+                msg.network.clone().into(),
+            );
+            script_pubkey
+        })
+        .collect();
+
+    let private_address = Address::p2tr(&secp, xonly_pubkey, None, msg.network.into());
+
+    let further_outputs = fan_out_script_pubkeys.len() as u64;
+    let total_inputs_value = msg.funding_utxo.value;
+    let total_outputs_value = get_output_value_for_commit(msg.fees) * (further_outputs + 1);
+    let calculated_fee = total_inputs_value
+        .checked_sub(total_outputs_value)
+        .ok_or_else(|| {
+            anyhow!(
+                "funding utxo of {} sats cannot cover the {} sat commit output",
+                total_inputs_value,
+                total_outputs_value
+            )
+        })?;
+    let fee_without_change = msg.fees.commit_fee_only
+        + msg.worker_options.satsbyte * OUTPUT_BYTES_BASE * further_outputs;
+    if calculated_fee < fee_without_change {
+        bail!(
+            "funding utxo of {} sats leaves a {} sat commit fee, below the expected {} sats",
+            total_inputs_value,
+            calculated_fee,
+            fee_without_change
+        );
+    }
+    let mut need_change_fee_output = false;
+    let expected_fee = fee_without_change + msg.worker_options.satsbyte * OUTPUT_BYTES_BASE;
+    // Whatever cannot also pay for a change output goes to the fee.
+    let difference_between_calculated_and_expected_fee =
+        calculated_fee.saturating_sub(expected_fee);
+    if calculated_fee > 0
+        && difference_between_calculated_and_expected_fee > 0
+        && difference_between_calculated_and_expected_fee >= DUST_AMOUNT
+    {
+        need_change_fee_output = true;
+    }
+
+    Ok(Payload {
+        secp,
+        copied_data: msg.copied_data,
+        funding_utxo_id: msg.funding_utxo.txid.parse()?,
+        funding_utxo_index: msg.funding_utxo.index,
+        funding_utxo_vout: msg.funding_utxo.vout,
+        funding_utxo_value: Amount::from_sat(msg.funding_utxo.value),
+        xonly_pub_key: xonly_pubkey,
+        funding_private_key: private_key,
+        funding_private_script_pubkey: private_address.script_pubkey(),
+        funding_value: Amount::from_sat(difference_between_calculated_and_expected_fee),
+        fixed_output_script_pubkey,
+        fixed_output_value: Amount::from_sat(get_output_value_for_commit(msg.fees)),
+        fan_out_script_pubkeys,
+        refund_leaf,
+        need_change_fee_output,
+        valid_prefix: msg.worker_bitwork_info_commit.prefix,
+        valid_ext: msg.worker_bitwork_info_commit.ext,
+        // A lock time past the known height would leave the commit non-final.
+        grind: GrindOptions {
+            max_lock_time: msg.block_height.map_or(msg.grind.max_lock_time, |height| {
+                msg.grind.max_lock_time.min(height)
+            }),
+            ..msg.grind
+        },
+        variant: Variant::default(),
+    })
+}
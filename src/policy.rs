@@ -0,0 +1,225 @@
+use anyhow::{anyhow, bail, Result};
+use bitcoin::{
+    absolute::LockTime, blockdata::constants::MAX_SCRIPT_ELEMENT_SIZE, script::Instruction, Amount,
+    Script, Transaction, TxOut,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    reveal,
+    types::{Payload, Root},
+    worker::{funding_prevout, SEQUENCE_LOCK_TIME_MASK, SEQUENCE_LOCK_TIME_TYPE_FLAG},
+};
+
+/// Default minimum relay fee rate, in sat/vB.
+pub const MIN_RELAY_FEE: u64 = 1;
+
+/// Highest version relayed without TRUC rules. Version 3 opts into them,
+/// and a version 1 reveal could not then spend an unconfirmed commit.
+pub const MAX_STANDARD_VERSION: i32 = 2;
+
+/// Largest tapscript witness item relayed, besides the script and control
+/// block.
+pub const MAX_STANDARD_TAPSCRIPT_STACK_ITEM_SIZE: usize = 80;
+
+/// First byte of a taproot annex, which is not relayed.
+const ANNEX_TAG: u8 = 0x50;
+
+/// One way a transaction falls outside default relay policy.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum Nonstandard {
+    Version {
+        version: i32,
+    },
+    Weight {
+        weight: u64,
+    },
+    Dust {
+        output: usize,
+        value: u64,
+        threshold: u64,
+    },
+    FeeBelowRelay {
+        fee: u64,
+        required: u64,
+    },
+    PushTooLarge {
+        input: usize,
+        size: usize,
+    },
+    WitnessItemTooLarge {
+        input: usize,
+        item: usize,
+        size: usize,
+    },
+    Annex {
+        input: usize,
+    },
+    /// Locked to a block height above the current one.
+    NonFinal {
+        lock_time: u32,
+        height: u32,
+    },
+    /// A BIP-68 relative lock, which holds the input back until its prevout
+    /// is buried deep enough.
+    RelativeLock {
+        input: usize,
+        sequence: u32,
+    },
+}
+
+/// Checks `tx`, spending `prevouts` in input order, against default relay
+/// policy. Every problem found is reported rather than stopping at the first.
+/// Lock times are only checked against a known block `height`.
+pub fn check(
+    tx: &Transaction,
+    prevouts: &[TxOut],
+    height: Option<u32>,
+) -> Result<Vec<Nonstandard>> {
+    if prevouts.len() != tx.input.len() {
+        bail!(
+            "{} prevouts given for {} inputs",
+            prevouts.len(),
+            tx.input.len()
+        );
+    }
+    let mut nonstandard = vec![];
+    if !(1..=MAX_STANDARD_VERSION).contains(&tx.version.0) {
+        nonstandard.push(Nonstandard::Version {
+            version: tx.version.0,
+        });
+    }
+    if tx.weight() > Transaction::MAX_STANDARD_WEIGHT {
+        nonstandard.push(Nonstandard::Weight {
+            weight: tx.weight().to_wu(),
+        });
+    }
+    for (output, txout) in tx.output.iter().enumerate() {
+        let threshold = txout.script_pubkey.dust_value();
+        if !txout.script_pubkey.is_op_return() && txout.value < threshold {
+            nonstandard.push(Nonstandard::Dust {
+                output,
+                value: txout.value.to_sat(),
+                threshold: threshold.to_sat(),
+            });
+        }
+    }
+
+    if let (LockTime::Blocks(lock_time), Some(height)) = (tx.lock_time, height) {
+        // A block at `height + 1` takes lock times up to `height`.
+        if tx.is_lock_time_enabled() && lock_time.to_consensus_u32() > height {
+            nonstandard.push(Nonstandard::NonFinal {
+                lock_time: lock_time.to_consensus_u32(),
+                height,
+            });
+        }
+    }
+    if tx.version.0 >= 2 {
+        // A zero relative lock is met by any prevout, confirmed or not.
+        let lock_mask = SEQUENCE_LOCK_TIME_TYPE_FLAG | SEQUENCE_LOCK_TIME_MASK;
+        for (input, txin) in tx.input.iter().enumerate() {
+            if txin.sequence.is_relative_lock_time() && txin.sequence.0 & lock_mask != 0 {
+                nonstandard.push(Nonstandard::RelativeLock {
+                    input,
+                    sequence: txin.sequence.0,
+                });
+            }
+        }
+    }
+
+    let spent: Amount = prevouts.iter().map(|prevout| prevout.value).sum();
+    let paid: Amount = tx.output.iter().map(|output| output.value).sum();
+    let fee = spent
+        .checked_sub(paid)
+        .ok_or_else(|| anyhow!("transaction {} spends more than its inputs", tx.txid()))?
+        .to_sat();
+    let required = MIN_RELAY_FEE * tx.vsize() as u64;
+    if fee < required {
+        nonstandard.push(Nonstandard::FeeBelowRelay { fee, required });
+    }
+
+    for (input, (txin, prevout)) in tx.input.iter().zip(prevouts).enumerate() {
+        if !prevout.script_pubkey.is_p2tr() {
+            continue;
+        }
+        let mut stack: Vec<&[u8]> = txin.witness.iter().collect();
+        if stack.len() >= 2 && stack.last().and_then(|item| item.first()) == Some(&ANNEX_TAG) {
+            nonstandard.push(Nonstandard::Annex { input });
+            stack.pop();
+        }
+        // A single item is a key path signature, which consensus bounds.
+        if stack.len() < 2 {
+            continue;
+        }
+        let script = Script::from_bytes(stack[stack.len() - 2]);
+        for instruction in script.instructions().flatten() {
+            if let Instruction::PushBytes(push) = instruction {
+                if push.len() > MAX_SCRIPT_ELEMENT_SIZE {
+                    nonstandard.push(Nonstandard::PushTooLarge {
+                        input,
+                        size: push.len(),
+                    });
+                }
+            }
+        }
+        for (item, bytes) in stack[..stack.len() - 2].iter().enumerate() {
+            if bytes.len() > MAX_STANDARD_TAPSCRIPT_STACK_ITEM_SIZE {
+                nonstandard.push(Nonstandard::WitnessItemTooLarge {
+                    input,
+                    item,
+                    size: bytes.len(),
+                });
+            }
+        }
+    }
+    Ok(nonstandard)
+}
+
+/// Fails on a transaction that default relay policy rejects, unless
+/// `allow_non_standard` is set.
+pub fn ensure_standard(
+    tx: &Transaction,
+    prevouts: &[TxOut],
+    height: Option<u32>,
+    allow_non_standard: bool,
+) -> Result<()> {
+    reject(tx, check(tx, prevouts, height)?, allow_non_standard)
+}
+
+/// Fails on any of the `nonstandard` findings of [`check`] for `tx`, unless
+/// `allow_non_standard` is set.
+pub fn reject(
+    tx: &Transaction,
+    nonstandard: Vec<Nonstandard>,
+    allow_non_standard: bool,
+) -> Result<()> {
+    if nonstandard.is_empty() || allow_non_standard {
+        return Ok(());
+    }
+    bail!(
+        "transaction {} would not be relayed: {}; set allowNonStandard to emit it anyway",
+        tx.txid(),
+        serde_json::to_string(&nonstandard)?
+    )
+}
+
+/// Checks a commit built for `payload` and the reveals spending it.
+pub fn ensure_mint_standard(
+    msg: &Root,
+    payload: &Payload,
+    commit: &Transaction,
+    reveals: &[Transaction],
+) -> Result<()> {
+    ensure_standard(
+        commit,
+        &[funding_prevout(payload)],
+        msg.block_height,
+        msg.allow_non_standard,
+    )?;
+    for reveal in reveals {
+        let prevouts = reveal::spent_outputs(commit, reveal)?;
+        ensure_standard(reveal, &prevouts, msg.block_height, msg.allow_non_standard)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,120 @@
+use anyhow::{anyhow, bail, Result};
+use bitcoin::{consensus::encode::serialize_hex, Amount, Sequence, Transaction};
+
+use crate::{
+    cancel::CancellationToken,
+    hash::{HashBackend, TxidTemplate},
+    interpreter,
+    miner::{self, DUST_AMOUNT},
+    policy,
+    scheduler::{Scheduler, Task},
+    search::{Candidate, Search, TemplateSearch},
+    types::{Bump, Payload, RbfPolicy, Root, SequencePolicy},
+    verify::Claim,
+    worker::{build_commit_tx, predicate, unsigned_commit_tx, BitworkMatcher},
+};
+
+/// Fee rate, in sat/vB, a replacement must add on top of the fees it evicts.
+pub const INCREMENTAL_RELAY_FEE: u64 = 1;
+
+/// Highest `nSequence` that signals replaceability under BIP-125.
+pub const MAX_RBF_SEQUENCE: u32 = Sequence::ENABLE_RBF_NO_LOCKTIME.0;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct BumpOptions {
+    /// Fee rate the replacement must pay at least.
+    pub satsbyte: u64,
+    /// Fees of the commit's descendants in the mempool, such as a broadcast
+    /// reveal, which the replacement evicts and so has to outbid as well.
+    pub descendant_fees: u64,
+}
+
+/// Replaces the commit of `original` with one that pays more from its change
+/// and still meets the bitwork, searching only sequences that signal
+/// replaceability and otherwise follow the job's sequence policy. `msg` must
+/// carry the fee rate the original was mined with.
+pub fn bump(
+    msg: &Root,
+    original: &Claim,
+    options: &BumpOptions,
+    token: &CancellationToken,
+    hasher: HashBackend,
+) -> Result<Bump> {
+    let mut payload = miner::get_payload(msg.clone(), Some(original.time), Some(original.nonce))?;
+    payload.variant = original.variant;
+    if !payload.need_change_fee_output {
+        bail!("the commit has no change output to pay a higher fee from");
+    }
+    let sequence =
+        u32::try_from(original.sequence).map_err(|_| anyhow!("sequence is out of range"))?;
+    let replaced = build_commit_tx(sequence, &payload)?;
+    if !replaced.input[0].sequence.is_rbf() {
+        bail!(
+            "commit {} does not signal replaceability (nSequence {:#x})",
+            replaced.txid(),
+            sequence
+        );
+    }
+    let replaced_fee = fee(&replaced, &payload)?;
+
+    // The replacement keeps every output but the change, so its size too.
+    let vsize = replaced.vsize() as u64;
+    let required = (options.satsbyte * vsize)
+        .max(replaced_fee + options.descendant_fees + INCREMENTAL_RELAY_FEE * vsize);
+    payload.variant.change_reduction += required - replaced_fee;
+    let change = payload
+        .funding_value
+        .to_sat()
+        .saturating_sub(payload.variant.change_reduction);
+    if change < DUST_AMOUNT {
+        bail!("the change output cannot cover a {} sat fee", required);
+    }
+
+    let matcher = BitworkMatcher::new(&payload.valid_prefix, &payload.valid_ext)
+        .ok_or_else(|| anyhow!("job has no commit bitwork to re-mine"))?;
+    let template = TxidTemplate::new(&unsigned_commit_tx(0, &payload), 0)?;
+    let search = TemplateSearch::new(vec![(payload.variant, template)], Some(matcher), hasher);
+    let policy = SequencePolicy {
+        rbf: RbfPolicy::Signal,
+        no_relative_lock_time: miner::commit_sequence_policy(msg).no_relative_lock_time
+            || (!msg.allow_non_standard && payload.variant.version >= 2),
+    };
+    let range = miner::sequence_range(&policy);
+    let tasks = miner::chunks(range.start, range.end)
+        .map(|range| Task { owner: 0, range })
+        .collect();
+    token.rearm();
+    let run = Scheduler::default().run(tasks, 1, token.found_flag(), |_, batch| {
+        search
+            .search(batch)
+            .into_iter()
+            .find(|hit| predicate(hit.sequence, &payload).unwrap_or(false))
+    });
+    let hit: Candidate = run
+        .hit
+        .ok_or_else(|| anyhow!("no replacement found before the search stopped"))?
+        .found;
+
+    let commit = build_commit_tx(hit.sequence, &payload)?;
+    interpreter::verify_mint(&payload, &commit, &[])?;
+    policy::ensure_mint_standard(msg, &payload, &commit, &[])?;
+    let fee = fee(&commit, &payload)?;
+    Ok(Bump {
+        result: miner::success(msg, &payload, hit),
+        commit_txid: commit.txid().to_string(),
+        commit_tx: serialize_hex(&commit),
+        fee,
+        satsbyte: fee as f64 / commit.vsize() as f64,
+        replaced_txid: replaced.txid().to_string(),
+        replaced_fee,
+    })
+}
+
+fn fee(tx: &Transaction, payload: &Payload) -> Result<u64> {
+    let spent: Amount = tx.output.iter().map(|output| output.value).sum();
+    Ok(payload
+        .funding_utxo_value
+        .checked_sub(spent)
+        .ok_or_else(|| anyhow!("commit {} spends more than its input", tx.txid()))?
+        .to_sat())
+}
@@ -0,0 +1,189 @@
+use anyhow::{anyhow, bail, Result};
+use bitcoin::{
+    absolute::LockTime,
+    key::Keypair,
+    psbt::Input,
+    sighash::{Prevouts, SighashCache},
+    taproot::LeafVersion,
+    transaction::Version,
+    Amount, OutPoint, PrivateKey, ScriptBuf, Sequence, TapLeafHash, TapSighashType, Transaction,
+    TxIn, TxOut, Witness, XOnlyPublicKey,
+};
+
+use crate::{
+    interpreter,
+    miner::fan_out_nonce,
+    policy::{self, Nonstandard},
+    types::{Payload, Root},
+    utils,
+};
+
+/// How to spend a commit output whose reveal was never sent.
+#[derive(Debug, Clone)]
+pub enum RecoveryPath {
+    /// The funding key, tweaked by the commit's merkle root.
+    Key,
+    /// The refund leaf, signed by the recovery key once its timelock passed.
+    Refund(PrivateKey),
+}
+
+/// Sweeps envelope output `output` of `commit` to `destination` at
+/// `satsbyte`, verified and policy-checked like any other emitted
+/// transaction.
+pub fn recover(
+    msg: &Root,
+    payload: &Payload,
+    commit: &Transaction,
+    output: usize,
+    path: &RecoveryPath,
+    destination: ScriptBuf,
+    satsbyte: u64,
+) -> Result<Transaction> {
+    let prevout = commit
+        .output
+        .get(output)
+        .cloned()
+        .ok_or_else(|| anyhow!("commit {} has no output {}", commit.txid(), output))?;
+    let mut copied_data = payload.copied_data.clone();
+    copied_data.args.nonce = fan_out_nonce(copied_data.args.nonce, output);
+    let (spend_info, _) = utils::get_spend_info_by_copied_data(
+        &payload.secp,
+        &payload.xonly_pub_key,
+        &copied_data,
+        &msg.worker_options.op_type,
+        payload.refund_leaf.as_ref(),
+    );
+    if ScriptBuf::new_p2tr_tweaked(spend_info.output_key()) != prevout.script_pubkey {
+        bail!(
+            "commit output {} is not an envelope output of this job",
+            output
+        );
+    }
+
+    let sequence = match path {
+        RecoveryPath::Key => Sequence::ENABLE_RBF_NO_LOCKTIME,
+        RecoveryPath::Refund(_) => {
+            let refund = msg
+                .refund
+                .as_ref()
+                .ok_or_else(|| anyhow!("the job's commits have no refund leaf"))?;
+            Sequence::from_height(refund.blocks)
+        }
+    };
+    let mut tx = Transaction {
+        version: Version::TWO,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint {
+                txid: commit.txid(),
+                vout: output as u32,
+            },
+            script_sig: ScriptBuf::new(),
+            sequence,
+            witness: Witness::default(),
+        }],
+        output: vec![TxOut {
+            value: prevout.value,
+            script_pubkey: destination,
+        }],
+    };
+    let prevouts = [prevout];
+
+    // Signatures are a fixed size, so signing once sizes the fee exactly.
+    let sign = |tx: &mut Transaction| -> Result<()> {
+        let witness = match path {
+            RecoveryPath::Key => {
+                let hash = SighashCache::new(&*tx).taproot_key_spend_signature_hash(
+                    0,
+                    &Prevouts::All(&prevouts),
+                    TapSighashType::Default,
+                )?;
+                let mut input = Input {
+                    tap_merkle_root: spend_info.merkle_root(),
+                    ..Default::default()
+                };
+                utils::sign_psbt_taproot(
+                    &payload.funding_private_key.inner,
+                    payload.xonly_pub_key,
+                    None,
+                    &mut input,
+                    hash,
+                    TapSighashType::Default,
+                    &payload.secp,
+                );
+                let signature = input.tap_key_sig.expect("key path signature");
+                Witness::from_slice(&[signature.to_vec()])
+            }
+            RecoveryPath::Refund(key) => {
+                let leaf = payload
+                    .refund_leaf
+                    .as_ref()
+                    .ok_or_else(|| anyhow!("the job's commits have no refund leaf"))?;
+                let (recovery_key, _) = XOnlyPublicKey::from_keypair(&Keypair::from_secret_key(
+                    &payload.secp,
+                    &key.inner,
+                ));
+                let expected = msg
+                    .refund
+                    .as_ref()
+                    .and_then(|refund| refund.key.parse().ok());
+                if expected != Some(recovery_key) {
+                    bail!("{} is not the refund leaf's recovery key", recovery_key);
+                }
+                let control_block = spend_info
+                    .control_block(&(leaf.clone(), LeafVersion::TapScript))
+                    .ok_or_else(|| anyhow!("refund leaf missing from the taproot tree"))?;
+                let leaf_hash = TapLeafHash::from_script(leaf, LeafVersion::TapScript);
+                let hash = SighashCache::new(&*tx).taproot_script_spend_signature_hash(
+                    0,
+                    &Prevouts::All(&prevouts),
+                    leaf_hash,
+                    TapSighashType::Default,
+                )?;
+                let mut input = Input::default();
+                utils::sign_psbt_taproot(
+                    &key.inner,
+                    recovery_key,
+                    Some(leaf_hash),
+                    &mut input,
+                    hash,
+                    TapSighashType::Default,
+                    &payload.secp,
+                );
+                let signature = input.tap_script_sigs[&(recovery_key, leaf_hash)];
+                let mut witness = Witness::new();
+                witness.push(signature.to_vec());
+                witness.push(leaf.as_bytes());
+                witness.push(control_block.serialize());
+                witness
+            }
+        };
+        tx.input[0].witness = witness;
+        Ok(())
+    };
+    sign(&mut tx)?;
+    let fee = satsbyte * tx.vsize() as u64;
+    let swept = prevouts[0]
+        .value
+        .checked_sub(Amount::from_sat(fee))
+        .filter(|value| *value >= tx.output[0].script_pubkey.dust_value())
+        .ok_or_else(|| {
+            anyhow!(
+                "commit output of {} sats cannot pay a {} sat sweep",
+                prevouts[0].value.to_sat(),
+                fee
+            )
+        })?;
+    tx.output[0].value = swept;
+    sign(&mut tx)?;
+
+    interpreter::verify_tx(&payload.secp, &tx, &prevouts)?;
+    let mut nonstandard = policy::check(&tx, &prevouts, msg.block_height)?;
+    if let RecoveryPath::Refund(_) = path {
+        // The refund leaf's lock is the point of the path; nodes relay the
+        // sweep once the commit is `refund.blocks` deep.
+        nonstandard.retain(|finding| !matches!(finding, Nonstandard::RelativeLock { .. }));
+    }
+    policy::reject(&tx, nonstandard, msg.allow_non_standard)?;
+    Ok(tx)
+}
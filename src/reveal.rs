@@ -0,0 +1,219 @@
+use std::str::FromStr;
+
+use crate::{
+    cancel::CancellationToken,
+    hash::{HashBackend, TxidTemplate},
+    miner::{self, fan_out_nonce},
+    scheduler::{Scheduler, Task},
+    search::{Search, TemplateSearch},
+    types::{Payload, Root, SequenceRange, Variant},
+    utils,
+    worker::{has_valid_bitwork, BitworkMatcher},
+};
+use anyhow::{anyhow, bail, Result};
+use bitcoin::{
+    absolute::LockTime,
+    psbt::Input,
+    sighash::{Prevouts, SighashCache},
+    taproot::{ControlBlock, LeafVersion},
+    transaction::Version,
+    Address, Amount, OutPoint, ScriptBuf, Sequence, TapLeafHash, TapSighashType, Transaction, TxIn,
+    TxOut, Txid, Witness,
+};
+
+/// Everything needed to spend the commit output through its envelope leaf.
+#[derive(Debug, Clone)]
+pub struct RevealTemplate {
+    pub commit_outpoint: OutPoint,
+    pub commit_output: TxOut,
+    pub outputs: Vec<TxOut>,
+    pub script: ScriptBuf,
+    pub control_block: ControlBlock,
+    pub leaf_hash: TapLeafHash,
+}
+
+/// Prepares the reveal of the commit output at `commit_txid:0`, paying the dmt
+/// mint amount to `worker_options.address`.
+pub fn reveal_template(msg: &Root, payload: &Payload, commit_txid: Txid) -> Result<RevealTemplate> {
+    output_reveal_template(msg, payload, commit_txid, 0)
+}
+
+/// [`reveal_template`] for every output of a fan-out commit, in output order.
+pub fn reveal_templates(
+    msg: &Root,
+    payload: &Payload,
+    commit_txid: Txid,
+) -> Result<Vec<RevealTemplate>> {
+    (0..=payload.fan_out_script_pubkeys.len())
+        .map(|output| output_reveal_template(msg, payload, commit_txid, output))
+        .collect()
+}
+
+/// The reveal of commit output `output`, whose envelope carries the nonce
+/// [`fan_out_nonce`] gives it.
+fn output_reveal_template(
+    msg: &Root,
+    payload: &Payload,
+    commit_txid: Txid,
+    output: usize,
+) -> Result<RevealTemplate> {
+    let script_pubkey = match output {
+        0 => &payload.fixed_output_script_pubkey,
+        _ => payload
+            .fan_out_script_pubkeys
+            .get(output - 1)
+            .ok_or_else(|| anyhow!("commit has no output {}", output))?,
+    };
+    let mut copied_data = payload.copied_data.clone();
+    copied_data.args.nonce = fan_out_nonce(copied_data.args.nonce, output);
+    let (spend_info, script) = utils::get_spend_info_by_copied_data(
+        &payload.secp,
+        &payload.xonly_pub_key,
+        &copied_data,
+        &msg.worker_options.op_type,
+        payload.refund_leaf.as_ref(),
+    );
+    if ScriptBuf::new_p2tr_tweaked(spend_info.output_key()) != *script_pubkey {
+        bail!(
+            "envelope of commit output {} does not match its script",
+            output
+        );
+    }
+    let control_block = spend_info
+        .control_block(&(script.clone(), LeafVersion::TapScript))
+        .ok_or_else(|| anyhow!("envelope leaf missing from the taproot tree"))?;
+    let receive_address = Address::from_str(&msg.worker_options.address)?
+        .require_network(msg.network.clone().into())?;
+    let mint_amount = u64::try_from(msg.worker_options.dmt_options.mint_amount)
+        .map_err(|_| anyhow!("mint amount must not be negative"))?;
+    if mint_amount >= payload.fixed_output_value.to_sat() {
+        bail!(
+            "commit output of {} sats cannot fund a {} sat mint",
+            payload.fixed_output_value.to_sat(),
+            mint_amount
+        );
+    }
+    Ok(RevealTemplate {
+        commit_outpoint: OutPoint {
+            txid: commit_txid,
+            vout: output as u32,
+        },
+        commit_output: TxOut {
+            value: payload.fixed_output_value,
+            script_pubkey: script_pubkey.clone(),
+        },
+        outputs: vec![TxOut {
+            value: Amount::from_sat(mint_amount),
+            script_pubkey: receive_address.script_pubkey(),
+        }],
+        leaf_hash: TapLeafHash::from_script(&script, LeafVersion::TapScript),
+        script,
+        control_block,
+    })
+}
+
+fn unsigned_reveal_tx(template: &RevealTemplate, seq: u32) -> Transaction {
+    Transaction {
+        version: Version::ONE,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: template.commit_outpoint,
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence(seq),
+            witness: Witness::default(),
+        }],
+        output: template.outputs.clone(),
+    }
+}
+
+/// The commit outputs `reveal` spends, in input order.
+pub fn spent_outputs(commit: &Transaction, reveal: &Transaction) -> Result<Vec<TxOut>> {
+    reveal
+        .input
+        .iter()
+        .map(|input| {
+            commit
+                .output
+                .get(input.previous_output.vout as usize)
+                .cloned()
+                .ok_or_else(|| anyhow!("reveal {} does not spend the commit", reveal.txid()))
+        })
+        .collect()
+}
+
+/// Builds and signs the reveal transaction for the given `nSequence`.
+pub fn build_reveal_tx(
+    template: &RevealTemplate,
+    seq: u32,
+    payload: &Payload,
+) -> Result<Transaction> {
+    let mut tx = unsigned_reveal_tx(template, seq);
+    let hash_ty = TapSighashType::Default;
+    let hash = SighashCache::new(&tx).taproot_script_spend_signature_hash(
+        0,
+        &Prevouts::All(std::slice::from_ref(&template.commit_output)),
+        template.leaf_hash,
+        hash_ty,
+    )?;
+    let mut input = Input::default();
+    utils::sign_psbt_taproot(
+        &payload.funding_private_key.inner,
+        payload.xonly_pub_key,
+        Some(template.leaf_hash),
+        &mut input,
+        hash,
+        hash_ty,
+        &payload.secp,
+    );
+    let signature = input.tap_script_sigs[&(payload.xonly_pub_key, template.leaf_hash)];
+
+    let mut witness = Witness::new();
+    witness.push(signature.to_vec());
+    witness.push(template.script.as_bytes());
+    witness.push(template.control_block.serialize());
+    tx.input[0].witness = witness;
+    Ok(tx)
+}
+
+/// Builds the reveal, searching `nSequence` within `range` for a txid
+/// matching `bitworkr` when the mint asks for one, until `token` is
+/// cancelled. The txid does not commit to the witness, so only the winning
+/// candidate is signed.
+pub fn mine_reveal_tx(
+    template: &RevealTemplate,
+    payload: &Payload,
+    bitworkr: Option<&str>,
+    range: SequenceRange,
+    token: &CancellationToken,
+    hasher: HashBackend,
+) -> Result<Transaction> {
+    let Some(bitworkr) = bitworkr else {
+        return build_reveal_tx(template, range.start, payload);
+    };
+    let (prefix, ext) = utils::parse_bitwork(bitworkr)?;
+    let (prefix, ext) = (Some(prefix), ext);
+    let matcher = BitworkMatcher::new(&prefix, &ext)
+        .ok_or_else(|| anyhow!("bitworkr {} cannot be searched", bitworkr))?;
+    let txid_template = TxidTemplate::new(&unsigned_reveal_tx(template, 0), 0)?;
+    let search = TemplateSearch::new(
+        vec![(Variant::default(), txid_template)],
+        Some(matcher),
+        hasher,
+    );
+    let tasks = miner::chunks(range.start, range.end)
+        .map(|range| Task { owner: 0, range })
+        .collect();
+    token.rearm();
+    let run = Scheduler::default().run(tasks, 1, token.found_flag(), |_, batch| {
+        search.search(batch).into_iter().find(|hit| {
+            let txid = unsigned_reveal_tx(template, hit.sequence).txid();
+            has_valid_bitwork(&txid.to_string(), &prefix, &ext)
+        })
+    });
+    let hit = match run.hit {
+        Some(hit) => hit.found,
+        None if token.is_cancelled() => bail!("reveal search cancelled"),
+        None => bail!("no sequence satisfies bitworkr {}", bitworkr),
+    };
+    build_reveal_tx(template, hit.sequence, payload)
+}
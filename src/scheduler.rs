@@ -0,0 +1,189 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Mutex,
+    },
+    thread,
+};
+
+use crossbeam_deque::{Steal, Stealer, Worker};
+
+use crate::types::SequenceRange;
+
+/// Candidates searched between two looks at the stop flag.
+pub const BATCH_SIZE: u32 = 1024;
+
+/// A contiguous range of candidates belonging to one of the job's workers
+/// (one nonce/time pair each).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Task {
+    pub owner: usize,
+    pub range: SequenceRange,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Hit<H> {
+    pub owner: usize,
+    pub found: H,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Run<H> {
+    pub hit: Option<Hit<H>>,
+    /// Tasks searched to the end, per owner.
+    pub completed: Vec<Vec<SequenceRange>>,
+    /// Sequences searched; a batch with a hit counts in full.
+    pub attempts: u64,
+}
+
+/// Runs tasks on a fixed set of threads, each with its own deque. A thread
+/// works through its own tasks in sequence order and, once out of work,
+/// steals from the far end of the others.
+#[derive(Debug, Copy, Clone)]
+pub struct Scheduler {
+    pub threads: usize,
+    /// Pin each thread to one of the cores the process may run on, where the
+    /// platform supports it.
+    pub pin: bool,
+    pub batch_size: u32,
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Scheduler {
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+            pin: true,
+            batch_size: BATCH_SIZE,
+        }
+    }
+}
+
+impl Scheduler {
+    /// Searches `tasks` until one batch reports a hit, `stop` is raised or the
+    /// tasks run out. `search` returns the first hit in a batch, if any.
+    pub fn run<H, F>(&self, tasks: Vec<Task>, owners: usize, stop: &AtomicBool, search: F) -> Run<H>
+    where
+        H: Send,
+        F: Fn(usize, SequenceRange) -> Option<H> + Sync,
+    {
+        let threads = self.threads.max(1);
+        let queues: Vec<Worker<Task>> = (0..threads).map(|_| Worker::new_lifo()).collect();
+        // Contiguous slices keep each thread on neighbouring candidates. They
+        // are pushed in reverse so the owner pops the lowest sequence first
+        // while thieves take the highest.
+        let per_thread = tasks.len().div_ceil(threads).max(1);
+        for (i, slice) in tasks.chunks(per_thread).enumerate() {
+            for task in slice.iter().rev() {
+                queues[i].push(*task);
+            }
+        }
+        let stealers: Vec<Stealer<Task>> = queues.iter().map(Worker::stealer).collect();
+
+        let hit = Mutex::new(None);
+        let completed: Vec<Mutex<Vec<SequenceRange>>> =
+            (0..owners).map(|_| Mutex::new(vec![])).collect();
+        let attempts = AtomicU64::new(0);
+        let cores = if self.pin { allowed_cores() } else { vec![] };
+        thread::scope(|scope| {
+            for (index, queue) in queues.into_iter().enumerate() {
+                let (stealers, hit, completed, attempts, search, cores) =
+                    (&stealers, &hit, &completed, &attempts, &search, &cores);
+                scope.spawn(move || {
+                    if !cores.is_empty() {
+                        pin_to_core(cores[index % cores.len()]);
+                    }
+                    let mut tried = 0;
+                    'tasks: while let Some(task) = next_task(&queue, stealers, index) {
+                        let mut start = task.range.start;
+                        loop {
+                            if stop.load(Ordering::Relaxed) {
+                                break 'tasks;
+                            }
+                            let end = start
+                                .saturating_add(self.batch_size - 1)
+                                .min(task.range.end);
+                            let batch = SequenceRange { start, end };
+                            let found = search(task.owner, batch);
+                            tried += (end - start) as u64 + 1;
+                            if let Some(found) = found {
+                                stop.store(true, Ordering::Relaxed);
+                                hit.lock().unwrap().get_or_insert(Hit {
+                                    owner: task.owner,
+                                    found,
+                                });
+                                break 'tasks;
+                            }
+                            if end == task.range.end {
+                                break;
+                            }
+                            start = end + 1;
+                        }
+                        completed[task.owner].lock().unwrap().push(task.range);
+                    }
+                    attempts.fetch_add(tried, Ordering::Relaxed);
+                });
+            }
+        });
+
+        Run {
+            hit: hit.into_inner().unwrap(),
+            completed: completed
+                .into_iter()
+                .map(|done| done.into_inner().unwrap())
+                .collect(),
+            attempts: attempts.into_inner(),
+        }
+    }
+}
+
+fn next_task(queue: &Worker<Task>, stealers: &[Stealer<Task>], index: usize) -> Option<Task> {
+    if let Some(task) = queue.pop() {
+        return Some(task);
+    }
+    loop {
+        let mut retry = false;
+        // Start with the neighbour so idle threads spread out over victims.
+        for offset in 1..stealers.len() {
+            match stealers[(index + offset) % stealers.len()].steal() {
+                Steal::Success(task) => return Some(task),
+                Steal::Retry => retry = true,
+                Steal::Empty => {}
+            }
+        }
+        if !retry {
+            return None;
+        }
+    }
+}
+
+/// The cores this process may run on.
+#[cfg(target_os = "linux")]
+fn allowed_cores() -> Vec<usize> {
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        if libc::sched_getaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &mut set) != 0 {
+            return vec![];
+        }
+        (0..libc::CPU_SETSIZE as usize)
+            .filter(|core| libc::CPU_ISSET(*core, &set))
+            .collect()
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn pin_to_core(core: usize) {
+    // Best effort: a failure just leaves the thread to the OS scheduler.
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_SET(core, &mut set);
+        libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set);
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn allowed_cores() -> Vec<usize> {
+    vec![]
+}
+
+#[cfg(not(target_os = "linux"))]
+fn pin_to_core(_core: usize) {}
@@ -0,0 +1,262 @@
+use anyhow::{anyhow, Result};
+use bitcoin::{hashes::Hash, Txid};
+
+use crate::{
+    hash::{HashBackend, TxidTemplate},
+    miner,
+    types::{Payload, SequenceRange, Variant},
+    worker::{self, predicate, BitworkMatcher},
+};
+
+/// Name that picks the fastest hash backend passing its self-test.
+pub const AUTO: &str = "auto";
+
+/// A way of searching commit candidates, such as CPU hashing or an offload
+/// device.
+pub trait SearchBackend: Send + Sync {
+    fn name(&self) -> String;
+
+    /// Prepares the search of one worker's candidates.
+    fn prepare<'a>(&self, payload: &'a Payload) -> Result<Box<dyn Search + 'a>>;
+}
+
+/// A commit that meets the bitwork.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Candidate {
+    pub sequence: u32,
+    pub variant: Variant,
+}
+
+/// A txid that meets a bitwork weaker than the target, unconfirmed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct NearMiss {
+    pub candidate: Candidate,
+    pub txid: Txid,
+}
+
+/// A search prepared for one payload.
+pub trait Search: Send + Sync {
+    /// Commits with a sequence in `range` that meet the bitwork, in
+    /// ascending sequence order. A search may stop at its first hit.
+    fn search(&self, range: SequenceRange) -> Vec<Candidate>;
+
+    /// Commits searched per sequence.
+    fn variants(&self) -> usize {
+        1
+    }
+
+    /// [`Search::search`] that also returns, from the same hashes, every
+    /// commit meeting `near`. `None` when the search cannot report them.
+    fn search_near(
+        &self,
+        _range: SequenceRange,
+        _near: &BitworkMatcher,
+    ) -> Option<(Vec<Candidate>, Vec<NearMiss>)> {
+        None
+    }
+}
+
+/// Resolves a backend by name, or auto-detects one for `None` or `"auto"`.
+/// Hash backends must pass their self-test to be used.
+pub fn by_name(name: Option<&str>) -> Result<Box<dyn SearchBackend>> {
+    Ok(Box::new(HashSearchBackend {
+        hasher: hasher_by_name(name)?,
+    }))
+}
+
+/// The hash backend [`by_name`] would search with.
+pub fn hasher_by_name(name: Option<&str>) -> Result<HashBackend> {
+    let Some(name) = name.filter(|name| *name != AUTO) else {
+        return Ok(HashBackend::detect());
+    };
+    let hasher: HashBackend = name.parse().map_err(|_| {
+        anyhow!(
+            "unknown search backend `{}`, expected one of {}",
+            name,
+            names().join(", ")
+        )
+    })?;
+    hasher
+        .self_test()
+        .map_err(|err| anyhow!("search backend `{}` is unusable: {}", name, err))?;
+    Ok(hasher)
+}
+
+/// Names accepted by [`by_name`].
+pub fn names() -> Vec<&'static str> {
+    let mut names = vec![AUTO];
+    names.extend(HashBackend::ALL.iter().map(|hasher| hasher.name()));
+    names
+}
+
+/// Hashes candidate txids from a template on the CPU.
+#[derive(Debug, Copy, Clone)]
+pub struct HashSearchBackend {
+    pub hasher: HashBackend,
+}
+
+impl SearchBackend for HashSearchBackend {
+    fn name(&self) -> String {
+        self.hasher.name().to_string()
+    }
+
+    fn prepare<'a>(&self, payload: &'a Payload) -> Result<Box<dyn Search + 'a>> {
+        Ok(Box::new(HashSearch {
+            templates: TemplateSearch::new(
+                variant_templates(payload)?,
+                BitworkMatcher::new(&payload.valid_prefix, &payload.valid_ext),
+                self.hasher,
+            ),
+            payload,
+        }))
+    }
+}
+
+/// The txid template of every variant of `payload`'s commit. One unsigned
+/// commit is serialized and each variant patched into a copy of it.
+pub fn variant_templates(payload: &Payload) -> Result<Vec<(Variant, TxidTemplate)>> {
+    let base = TxidTemplate::new(&worker::unsigned_commit_tx(0, payload), 0)?;
+    let change = worker::change_vout(payload);
+    miner::variants(payload)?
+        .into_iter()
+        .map(|variant| {
+            let value = change.map(|vout| (vout, worker::change_value(payload, variant)));
+            Ok((
+                variant,
+                base.with_variant(variant.version, variant.lock_time, value)?,
+            ))
+        })
+        .collect()
+}
+
+/// Hashes the txids of commit templates, one per variant. Hits are not
+/// confirmed, which would take the signing key.
+pub struct TemplateSearch {
+    templates: Vec<(Variant, TxidTemplate)>,
+    matcher: Option<BitworkMatcher>,
+    hasher: HashBackend,
+}
+
+impl TemplateSearch {
+    pub fn new(
+        templates: Vec<(Variant, TxidTemplate)>,
+        matcher: Option<BitworkMatcher>,
+        hasher: HashBackend,
+    ) -> Self {
+        TemplateSearch {
+            templates,
+            matcher,
+            hasher,
+        }
+    }
+
+    /// The txid of a candidate, or `None` for a variant not searched.
+    pub fn txid(&self, candidate: &Candidate) -> Option<Txid> {
+        self.templates
+            .iter()
+            .find(|(variant, _)| *variant == candidate.variant)
+            .map(|(_, template)| template.txid(self.hasher, candidate.sequence))
+    }
+
+    /// Hashes every candidate in `range` once, for the target and for `near`.
+    fn scan(
+        &self,
+        range: SequenceRange,
+        near: Option<&BitworkMatcher>,
+    ) -> (Vec<Candidate>, Vec<NearMiss>) {
+        let mut txids = vec![[0; 32]; (range.end - range.start) as usize + 1];
+        let (mut hits, mut near_misses) = (vec![], vec![]);
+        for (variant, template) in &self.templates {
+            template.txids(self.hasher, range.start, &mut txids);
+            for (i, txid) in txids.iter().enumerate() {
+                let candidate = Candidate {
+                    sequence: range.start + i as u32,
+                    variant: *variant,
+                };
+                if self.matcher.as_ref().is_some_and(|m| m.matches(txid)) {
+                    hits.push(candidate);
+                }
+                if near.is_some_and(|near| near.matches(txid)) {
+                    near_misses.push(NearMiss {
+                        candidate,
+                        txid: Txid::from_byte_array(*txid),
+                    });
+                }
+            }
+        }
+        hits.sort_by_key(|hit| hit.sequence);
+        (hits, near_misses)
+    }
+}
+
+impl Search for TemplateSearch {
+    fn search(&self, range: SequenceRange) -> Vec<Candidate> {
+        if self.matcher.is_none() {
+            return vec![];
+        }
+        self.scan(range, None).0
+    }
+
+    fn variants(&self) -> usize {
+        self.templates.len()
+    }
+
+    fn search_near(
+        &self,
+        range: SequenceRange,
+        near: &BitworkMatcher,
+    ) -> Option<(Vec<Candidate>, Vec<NearMiss>)> {
+        Some(self.scan(range, Some(near)))
+    }
+}
+
+/// Only a candidate whose hashed txid matches is built and signed, which
+/// also confirms the hit.
+struct HashSearch<'a> {
+    templates: TemplateSearch,
+    payload: &'a Payload,
+}
+
+impl HashSearch<'_> {
+    /// Confirms at most the first hit of each variant.
+    fn confirm(&self, hits: Vec<Candidate>) -> Vec<Candidate> {
+        let mut confirmed: Vec<Candidate> = vec![];
+        for hit in hits {
+            if confirmed.iter().any(|c| c.variant == hit.variant) {
+                continue;
+            }
+            let payload = Payload {
+                variant: hit.variant,
+                ..self.payload.clone()
+            };
+            match predicate(hit.sequence, &payload) {
+                Ok(true) => confirmed.push(hit),
+                Ok(false) => eprintln!(
+                    "Error: {} hash backend reported a false match at sequence {}",
+                    self.templates.hasher, hit.sequence
+                ),
+                Err(err) => eprintln!("Error: {:#?}", err),
+            }
+        }
+        confirmed
+    }
+}
+
+impl Search for HashSearch<'_> {
+    fn search(&self, range: SequenceRange) -> Vec<Candidate> {
+        self.confirm(self.templates.search(range))
+    }
+
+    fn variants(&self) -> usize {
+        self.templates.variants()
+    }
+
+    fn search_near(
+        &self,
+        range: SequenceRange,
+        near: &BitworkMatcher,
+    ) -> Option<(Vec<Candidate>, Vec<NearMiss>)> {
+        let (hits, near_misses) = self.templates.search_near(range, near)?;
+        Some((self.confirm(hits), near_misses))
+    }
+}
@@ -1,6 +1,60 @@
-use std::str::FromStr;
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    str::FromStr,
+    sync::{atomic::AtomicBool, mpsc},
+    thread,
+    time::Duration,
+};
 
-use crate::types::{Args, CopiedData};
+use bitcoin::{
+    consensus::encode::serialize_hex, hashes::Hash, Amount, Network, PrivateKey, Script, ScriptBuf,
+    Sequence, TapSighashType, Transaction, TxOut, Txid, Witness,
+};
+
+use crate::{
+    backend::{self, ChainBackend},
+    batch::{mine_batch, MAX_BATCH},
+    cancel::CancellationToken,
+    cluster::{self, Assignment, CommitTemplate, Message, OwnerSetup},
+    descriptor::{commit_outputs, descriptor_checksum},
+    electrum::{script_hash, ElectrumClient},
+    hash::{HashBackend, TxidTemplate},
+    indexer::{is_valid_ticker, validate_mint, MintRules, Violation},
+    input::{parse, InputFormat},
+    interpreter::{verify_input, verify_mint, verify_tx},
+    lifecycle::{Journal, Policy, Record, Stage},
+    miner::{
+        commit_sequence_policy, funding_address, get_payload, job_workers, merge_ranges, mine,
+        mine_with, partition, plan, sequence_range, variants, worker_payloads, MineOutcome,
+        CHUNK_SIZE, MAX_VARIANTS,
+    },
+    policy::{check, ensure_mint_standard, ensure_standard, Nonstandard},
+    rbf::{bump, BumpOptions, MAX_RBF_SEQUENCE},
+    recover::{recover, RecoveryPath},
+    reveal::{build_reveal_tx, mine_reveal_tx, reveal_template, reveal_templates},
+    scheduler::{Hit, Scheduler, Task},
+    search::{
+        self, variant_templates, Candidate, HashSearchBackend, NearMiss, Search, SearchBackend,
+        TemplateSearch,
+    },
+    tweak::NonceRoller,
+    types::{
+        Args, BitcoindRpc, Checkpoint, CopiedData, ElectrumApi, Fees, FundingUtxo, GrindOptions,
+        NearMissOptions, NearMissRecord, Payload, RbfPolicy, RefundOptions, Root, SequencePolicy,
+        SequenceRange, StopReason, Variant, WorkerBitworkInfoCommit, WorkerCheckpoint,
+        WorkerOptions,
+    },
+    utils::{decode_envelope, get_address_by_copied_data, parse_bitwork},
+    verify::{verify_claim, Claim, Mismatch},
+    worker::{
+        bitwork_difficulty, build_commit_tx, funding_prevout, grade_bitwork, has_valid_bitwork,
+        is_share_bitwork, predicate, BitworkGrade, BitworkMatcher, MAX_SEQUENCE,
+        SEQUENCE_LOCK_TIME_DISABLE_FLAG,
+    },
+};
 
 #[test]
 fn test_copied_data_encoded_ttts() {
@@ -29,3 +83,2096 @@ fn test_copied_data_encoded_voids() {
     };
     assert_eq!(hex::encode(copied_data.encode()).as_str(), "a16461726773a56474696d651a659b86d9656e6f6e63651a0045584568626974776f726b63673030303030303068626974776f726b7267303030303030306b6d696e745f7469636b657265766f696473");
 }
+
+fn sample_root() -> Root {
+    let secret_key = bitcoin::secp256k1::SecretKey::from_slice(&[1u8; 32]).unwrap();
+    Root {
+        copied_data: CopiedData {
+            args: Args {
+                bitworkc: Some("0000".to_string()),
+                mint_ticker: "ttts".to_string(),
+                ..Default::default()
+            },
+        },
+        worker_options: WorkerOptions {
+            satsbyte: 10,
+            op_type: "dmt".to_string(),
+            ..Default::default()
+        },
+        funding_wif: PrivateKey::new(secret_key, bitcoin::Network::Bitcoin).to_wif(),
+        funding_utxo: FundingUtxo {
+            txid: "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b".to_string(),
+            vout: 0,
+            value: 100000,
+            ..Default::default()
+        },
+        fees: Fees {
+            commit_fee_only: 300,
+            reveal_fee_plus_outputs: 10000,
+            ..Default::default()
+        },
+        perform_bitwork_for_commit_tx: true,
+        worker_bitwork_info_commit: WorkerBitworkInfoCommit {
+            prefix: Some("0000".to_string()),
+            ..Default::default()
+        },
+        concurrency: 2,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_mine_cancelled_before_start_reports_checkpoint() {
+    let token = CancellationToken::new();
+    token.cancel();
+    let MineOutcome::Stopped(report) = mine(&sample_root(), &token).unwrap() else {
+        panic!("expected a stop report");
+    };
+    assert_eq!(report.status, StopReason::Cancelled);
+    assert_eq!(report.attempts, 0);
+    assert!(report.ranges.is_empty());
+    assert_eq!(report.checkpoint.workers.len(), 2);
+    assert_eq!(report.checkpoint.workers[1].end, MAX_SEQUENCE - 1);
+}
+
+#[test]
+fn test_mine_resumes_from_checkpoint() {
+    let mut root = sample_root();
+    root.worker_bitwork_info_commit.prefix = Some(String::new());
+    root.resume = Some(Checkpoint {
+        workers: vec![WorkerCheckpoint {
+            nonce: 42,
+            time: 1704688101,
+            start: 0,
+            end: 3 * CHUNK_SIZE - 1,
+            completed: vec![SequenceRange {
+                start: 0,
+                end: 2 * CHUNK_SIZE - 1,
+            }],
+        }],
+    });
+    let MineOutcome::Found(success) = mine(&root, &CancellationToken::new()).unwrap() else {
+        panic!("expected a solution");
+    };
+    assert_eq!(success.nonce, 42);
+    assert_eq!(success.time, 1704688101);
+    assert!(success.sequence >= 2 * CHUNK_SIZE as u64);
+}
+
+#[test]
+fn test_merge_ranges() {
+    let ranges = vec![
+        SequenceRange { start: 20, end: 29 },
+        SequenceRange { start: 0, end: 9 },
+        SequenceRange { start: 10, end: 15 },
+    ];
+    assert_eq!(
+        merge_ranges(ranges),
+        vec![
+            SequenceRange { start: 0, end: 15 },
+            SequenceRange { start: 20, end: 29 },
+        ]
+    );
+}
+
+#[test]
+fn test_parse_reports_field_path() {
+    let mut value = serde_json::to_value(sample_root()).unwrap();
+    value["fees"]["commitFeeOnly"] = serde_json::json!("lots");
+    let err = parse::<Root>(&value.to_string(), InputFormat::Json).unwrap_err();
+    assert!(err.to_string().contains("fees.commitFeeOnly"), "{}", err);
+
+    let err = parse::<Root>("concurrency = 1\n", InputFormat::Toml).unwrap_err();
+    assert!(err.to_string().contains("invalid input"), "{}", err);
+}
+
+#[test]
+fn test_parse_toml_matches_json() {
+    let root = sample_root();
+    let text = toml::to_string(&root).unwrap();
+    assert_eq!(InputFormat::detect(None, &text), InputFormat::Toml);
+    assert_eq!(parse::<Root>(&text, InputFormat::Toml).unwrap(), root);
+}
+
+#[test]
+fn test_copied_data_decode_round_trip() {
+    let copied_data = CopiedData {
+        args: Args {
+            time: 1704691417,
+            nonce: 4544581,
+            bitworkc: Some("0000000".to_string()),
+            bitworkr: Some("0000000".to_string()),
+            mint_ticker: "voids".to_string(),
+        },
+    };
+    assert_eq!(
+        CopiedData::decode(&copied_data.encode()).unwrap(),
+        copied_data
+    );
+}
+
+#[test]
+fn test_decode_envelope_from_reveal() {
+    let mut root = sample_root();
+    root.worker_options.address =
+        "bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr".to_string();
+    root.worker_options.dmt_options.mint_amount = 1000;
+    let payload = get_payload(root.clone(), Some(1704688101), Some(7588557)).unwrap();
+    let commit = build_commit_tx(0, &payload).unwrap();
+    let template = reveal_template(&root, &payload, commit.txid()).unwrap();
+    let reveal = build_reveal_tx(&template, 0, &payload).unwrap();
+    assert_eq!(reveal.input[0].previous_output.txid, commit.txid());
+    assert_eq!(reveal.output[0].value.to_sat(), 1000);
+
+    let script = reveal.input[0].witness.tapscript().unwrap();
+    let envelope = decode_envelope(script).unwrap();
+    assert_eq!(envelope.op_type, "dmt");
+    assert_eq!(envelope.xonly_public_key, payload.xonly_pub_key);
+    assert_eq!(envelope.copied_data, payload.copied_data);
+}
+
+#[test]
+fn test_mine_reveal_tx_searches_until_cancelled() {
+    let mut root = sample_root();
+    root.worker_options.address =
+        "bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr".to_string();
+    root.worker_options.dmt_options.mint_amount = 1000;
+    let payload = get_payload(root.clone(), Some(1704688101), Some(7588557)).unwrap();
+    let commit = build_commit_tx(0, &payload).unwrap();
+    let template = reveal_template(&root, &payload, commit.txid()).unwrap();
+    let range = SequenceRange {
+        start: 0,
+        end: u32::MAX,
+    };
+    let token = CancellationToken::new();
+    let reveal = mine_reveal_tx(
+        &template,
+        &payload,
+        Some("ab"),
+        range,
+        &token,
+        HashBackend::Scalar,
+    )
+    .unwrap();
+    assert!(reveal.txid().to_string().starts_with("ab"));
+    assert!(!reveal.input[0].witness.is_empty());
+
+    token.cancel();
+    let err = mine_reveal_tx(
+        &template,
+        &payload,
+        Some(&"0".repeat(16)),
+        range,
+        &token,
+        HashBackend::Scalar,
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("cancelled"));
+}
+
+#[test]
+fn test_parse_bitwork() {
+    assert_eq!(parse_bitwork("0000").unwrap(), ("0000".to_string(), None));
+    assert_eq!(
+        parse_bitwork("ab12.5").unwrap(),
+        ("ab12".to_string(), Some(5))
+    );
+    assert!(parse_bitwork("00.16").is_err());
+    assert!(parse_bitwork("AB").is_err());
+    assert_eq!(bitwork_difficulty("00", None), 256.0);
+    assert_eq!(bitwork_difficulty("00", Some(8)), 512.0);
+}
+
+#[test]
+fn test_plan_reports_funding() {
+    let planned = plan(&sample_root(), Some(7588557), Some(1704688101)).unwrap();
+    assert!(planned.commit_address.starts_with("bc1p"));
+    assert_eq!(planned.commit_output_value, 10000);
+    assert_eq!(planned.required_funding, 10300);
+    assert_eq!(planned.required_funding_with_change, 10300 + 430 + 546);
+    assert!(planned.change_output);
+    assert_eq!(planned.search_space, 0xffffffff);
+
+    let mut root = sample_root();
+    root.funding_utxo.value = 5000;
+    assert!(plan(&root, None, None).is_err());
+
+    // Exactly the required funding pays the commit fee without change...
+    root.funding_utxo.value = planned.required_funding;
+    let payload = get_payload(root.clone(), Some(1704688101), Some(7588557)).unwrap();
+    assert!(!payload.need_change_fee_output);
+    // ...and less underpays it.
+    root.funding_utxo.value -= 1;
+    let err = get_payload(root, Some(1704688101), Some(7588557)).unwrap_err();
+    assert!(
+        err.to_string().contains("below the expected 300 sats"),
+        "{}",
+        err
+    );
+}
+
+struct HttpRequest {
+    method: String,
+    target: String,
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+impl HttpRequest {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Serves HTTP on localhost, answering each request with `handler`.
+fn serve_http(handler: impl Fn(&HttpRequest) -> (u16, String) + Send + 'static) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut headers = vec![];
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                match line.trim().split_once(':') {
+                    Some((key, value)) => headers.push((key.to_string(), value.trim().to_string())),
+                    None => break,
+                }
+            }
+            let mut request = HttpRequest {
+                method: request_line.split_whitespace().next().unwrap().to_string(),
+                target: request_line.split_whitespace().nth(1).unwrap().to_string(),
+                headers,
+                body: String::new(),
+            };
+            let content_length = request
+                .header("content-length")
+                .map_or(0, |value| value.parse().unwrap());
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).unwrap();
+            request.body = String::from_utf8(body).unwrap();
+            let (status, response) = handler(&request);
+            write!(
+                stream,
+                "HTTP/1.1 {} OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                response.len(),
+                response
+            )
+            .unwrap();
+        }
+    });
+    url
+}
+
+#[test]
+fn test_electrum_client_against_mock_server() {
+    let script_pubkey = funding_address(&sample_root()).unwrap().script_pubkey();
+    let hash = script_hash(&script_pubkey);
+    let url = serve_http(move |request| {
+        let target = request.target.as_str();
+        if request.method == "GET"
+            && target.starts_with("/blockchain.scripthash.listunspent?params=")
+        {
+            assert!(target.contains(&hash));
+            let utxos = r#"[{"tx_hash":"4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b","tx_pos":1,"height":0,"value":5000}]"#;
+            return (200, format!(r#"{{"success":true,"response":{}}}"#, utxos));
+        }
+        if request.method == "POST" && target == "/blockchain.transaction.broadcast" {
+            assert!(request.body.contains("params"));
+            let response = r#"{"success":true,"response":"4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b"}"#;
+            return (200, response.to_string());
+        }
+        (
+            500,
+            r#"{"success":false,"message":"unknown method"}"#.to_string(),
+        )
+    });
+
+    let mut api = ElectrumApi {
+        base_url: url,
+        ..Default::default()
+    };
+    let client = ElectrumClient::new(&api).unwrap();
+    let utxos = client.list_unspent(&script_pubkey).unwrap();
+    assert_eq!(utxos.len(), 1);
+    assert_eq!((utxos[0].vout, utxos[0].value), (1, 5000));
+    assert!(client.get_by_ticker("ttts").is_err());
+
+    api.use_post = true;
+    let client = ElectrumClient::new(&api).unwrap();
+    let payload = get_payload(sample_root(), Some(1704688101), Some(7588557)).unwrap();
+    let txid = client
+        .broadcast(&build_commit_tx(0, &payload).unwrap())
+        .unwrap();
+    assert_eq!(txid.to_string(), utxos[0].txid);
+
+    // atomicals-js job files still carry the socket state flag; others may not.
+    let api: ElectrumApi =
+        serde_json::from_str(r#"{"baseUrl":"http://x","usePost":true,"isOpenFlag":true}"#).unwrap();
+    assert!(api.use_post);
+    #[allow(deprecated)]
+    let flag = api.is_open_flag;
+    assert!(flag);
+    let api: ElectrumApi =
+        serde_json::from_str(r#"{"baseUrl":"http://x","usePost":true}"#).unwrap();
+    assert!(api.use_post);
+}
+
+#[test]
+fn test_bitcoind_backend_against_stub_rpc() {
+    let cookie = std::env::temp_dir().join(format!("psbt-test-cookie-{}", std::process::id()));
+    std::fs::write(&cookie, "__cookie__:secret\n").unwrap();
+    let url = serve_http(|request| {
+        assert_eq!(
+            request.header("authorization"),
+            Some("Basic X19jb29raWVfXzpzZWNyZXQ=")
+        );
+        let call: serde_json::Value = serde_json::from_str(&request.body).unwrap();
+        let result = match call["method"].as_str().unwrap() {
+            "scantxoutset" => serde_json::json!({ "unspents": [{
+                "txid": "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b",
+                "vout": 2,
+                "amount": 0.001,
+            }]}),
+            "testmempoolaccept" => serde_json::json!([{
+                "txid": "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b",
+                "allowed": false,
+                "reject-reason": "missing-inputs",
+            }]),
+            _ => {
+                let error =
+                    r#"{"result":null,"error":{"code":-26,"message":"bad-txns"},"id":"psbt"}"#;
+                return (500, error.to_string());
+            }
+        };
+        (
+            200,
+            serde_json::json!({ "result": result, "error": null }).to_string(),
+        )
+    });
+
+    let mut root = sample_root();
+    root.worker_options.bitcoind_rpc = Some(BitcoindRpc {
+        url,
+        cookie_file: Some(cookie.to_string_lossy().into_owned()),
+        ..Default::default()
+    });
+    let backend = backend::from_options(&root.worker_options, Network::Bitcoin, None).unwrap();
+    let utxos = backend.list_unspent(&ScriptBuf::new()).unwrap();
+    assert_eq!((utxos[0].vout, utxos[0].value), (2, 100000));
+
+    let payload = get_payload(root, Some(1704688101), Some(7588557)).unwrap();
+    let commit = build_commit_tx(0, &payload).unwrap();
+    let accepted = backend
+        .test_mempool_accept(std::slice::from_ref(&commit))
+        .unwrap();
+    assert_eq!(accepted[0].reject_reason.as_deref(), Some("missing-inputs"));
+    let err = backend.broadcast(&commit).unwrap_err();
+    assert!(err.to_string().contains("bad-txns"), "{}", err);
+    std::fs::remove_file(cookie).unwrap();
+}
+
+#[test]
+fn test_esplora_backend_sets_fee_rate() {
+    let address = funding_address(&sample_root()).unwrap();
+    let utxo_path = format!("/address/{}/utxo", address);
+    let url = serve_http(move |request| {
+        match (request.method.as_str(), request.target.as_str()) {
+        ("GET", "/fee-estimates") => (200, r#"{"1":30.2,"3":12.5,"6":8.1,"144":1.0}"#.to_string()),
+        ("GET", path) if path == utxo_path => (
+            200,
+            r#"[{"txid":"4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b","vout":0,"status":{"confirmed":true},"value":7000}]"#.to_string(),
+        ),
+        ("POST", "/tx") => (400, "sendrawtransaction RPC error".to_string()),
+        _ => (404, String::new()),
+    }
+    });
+
+    let mut root = sample_root();
+    root.worker_options.esplora_url = Some(url);
+    root.worker_options.fee_target_blocks = Some(4);
+    let backend = backend::from_options(&root.worker_options, Network::Bitcoin, None).unwrap();
+    backend::apply_fee_target(&mut root.worker_options, backend.as_ref()).unwrap();
+    assert_eq!(root.worker_options.satsbyte, 13);
+    assert_eq!(backend.fee_rate(200).unwrap(), 1);
+
+    let utxos = backend.list_unspent(&address.script_pubkey()).unwrap();
+    assert_eq!(utxos[0].value, 7000);
+    let payload = get_payload(root, Some(1704688101), Some(7588557)).unwrap();
+    assert!(backend
+        .broadcast(&build_commit_tx(0, &payload).unwrap())
+        .is_err());
+}
+
+/// In-memory chain that accepts broadcasts unless told to fail them.
+#[derive(Default)]
+struct FakeChain {
+    confirmations: RefCell<HashMap<Txid, u32>>,
+    failures: Cell<u32>,
+}
+
+impl FakeChain {
+    fn confirm_all(&self) {
+        for confirmations in self.confirmations.borrow_mut().values_mut() {
+            *confirmations += 1;
+        }
+    }
+}
+
+impl ChainBackend for FakeChain {
+    fn list_unspent(&self, _script_pubkey: &Script) -> anyhow::Result<Vec<FundingUtxo>> {
+        Ok(vec![])
+    }
+
+    fn confirmations(&self, txid: &Txid) -> anyhow::Result<Option<u32>> {
+        Ok(self.confirmations.borrow().get(txid).copied())
+    }
+
+    fn broadcast(&self, tx: &Transaction) -> anyhow::Result<Txid> {
+        if self.failures.get() > 0 {
+            self.failures.set(self.failures.get() - 1);
+            anyhow::bail!("min relay fee not met");
+        }
+        self.confirmations
+            .borrow_mut()
+            .entry(tx.txid())
+            .or_insert(0);
+        Ok(tx.txid())
+    }
+}
+
+fn sample_pair(sequence: u32) -> (Transaction, Transaction) {
+    let mut root = sample_root();
+    root.worker_options.address =
+        "bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr".to_string();
+    root.worker_options.dmt_options.mint_amount = 1000;
+    let payload = get_payload(root.clone(), Some(1704688101), Some(7588557)).unwrap();
+    let commit = build_commit_tx(sequence, &payload).unwrap();
+    let template = reveal_template(&root, &payload, commit.txid()).unwrap();
+    let reveal = build_reveal_tx(&template, 0, &payload).unwrap();
+    (commit, reveal)
+}
+
+#[test]
+fn test_lifecycle_survives_restart() {
+    let path = std::env::temp_dir().join(format!("psbt-journal-{}.jsonl", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let (commit, reveal) = sample_pair(0);
+    let id = commit.txid().to_string();
+    let chain = FakeChain::default();
+    let policy = Policy::default();
+
+    let mut journal = Journal::open(&path).unwrap();
+    journal.insert(Record::new(&commit, &reveal)).unwrap();
+    journal.step_all(&chain, &policy).unwrap();
+    assert_eq!(journal.get(&id).unwrap().stage, Stage::CommitBroadcast);
+
+    let (other_commit, other_reveal) = sample_pair(1);
+    let err = journal
+        .insert(Record::new(&other_commit, &other_reveal))
+        .unwrap_err();
+    assert!(err.to_string().contains("already claimed"), "{}", err);
+
+    let mut journal = Journal::open(&path).unwrap();
+    assert_eq!(journal.get(&id).unwrap().stage, Stage::CommitBroadcast);
+    chain.confirm_all();
+    journal.step_all(&chain, &policy).unwrap();
+    assert_eq!(journal.get(&id).unwrap().stage, Stage::CommitConfirmed);
+
+    chain.failures.set(policy.max_attempts);
+    for _ in 0..policy.max_attempts {
+        journal.step_all(&chain, &policy).unwrap();
+    }
+    let record = journal.get(&id).unwrap();
+    assert_eq!(record.stage, Stage::Failed);
+    assert_eq!(record.failed_stage, Some(Stage::CommitConfirmed));
+
+    journal.retry(&id).unwrap();
+    journal.step_all(&chain, &policy).unwrap();
+    chain.confirm_all();
+    assert_eq!(journal.step_all(&chain, &policy).unwrap(), 0);
+    let journal = Journal::open(&path).unwrap();
+    assert_eq!(journal.get(&id).unwrap().stage, Stage::RevealConfirmed);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_failed_commit_keeps_its_funding_output_until_released() {
+    let path = std::env::temp_dir().join(format!("psbt-release-{}.jsonl", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let (commit, reveal) = sample_pair(0);
+    let id = commit.txid().to_string();
+    let chain = FakeChain::default();
+    let policy = Policy::default();
+
+    let mut journal = Journal::open(&path).unwrap();
+    journal.insert(Record::new(&commit, &reveal)).unwrap();
+    chain.failures.set(policy.max_attempts);
+    for _ in 0..policy.max_attempts {
+        journal.step_all(&chain, &policy).unwrap();
+    }
+    assert_eq!(journal.get(&id).unwrap().failed_stage, Some(Stage::Mined));
+
+    // The last failed broadcast may have reached the network anyway.
+    let (other_commit, other_reveal) = sample_pair(1);
+    let other = Record::new(&other_commit, &other_reveal);
+    assert!(journal.insert(other.clone()).is_err());
+    chain.confirmations.borrow_mut().insert(commit.txid(), 0);
+    assert!(journal.release(&id, &chain).is_err());
+    assert!(journal.insert(other.clone()).is_err());
+
+    chain.confirmations.borrow_mut().clear();
+    journal.release(&id, &chain).unwrap();
+    let mut journal = Journal::open(&path).unwrap();
+    assert!(journal.get(&id).unwrap().released);
+    journal.insert(other).unwrap();
+    assert!(journal.retry(&id).is_err());
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_lifecycle_confirms_commit_without_txindex() {
+    let cookie = std::env::temp_dir().join(format!("psbt-txindex-cookie-{}", std::process::id()));
+    std::fs::write(&cookie, "__cookie__:secret\n").unwrap();
+    let (commit, reveal) = sample_pair(0);
+    let commit_txid = commit.txid().to_string();
+    let lookups_fail = std::sync::Arc::new(AtomicBool::new(false));
+    let fail = lookups_fail.clone();
+    let url = serve_http(move |request| {
+        let call: serde_json::Value = serde_json::from_str(&request.body).unwrap();
+        let error = |code: i64, message: &str| {
+            let body = serde_json::json!({
+                "result": null,
+                "error": { "code": code, "message": message },
+            });
+            (500, body.to_string())
+        };
+        let result = match call["method"].as_str().unwrap() {
+            // Confirmed transactions are not indexed.
+            "getrawtransaction" => return error(-5, "No such mempool transaction"),
+            "sendrawtransaction" => return error(-27, "Transaction outputs already in utxo set"),
+            "gettxout" if fail.load(std::sync::atomic::Ordering::SeqCst) => {
+                return error(-1, "lookup failed")
+            }
+            "gettxout" if call["params"] == serde_json::json!([commit_txid, 0, true]) => {
+                serde_json::json!({ "confirmations": 2 })
+            }
+            _ => serde_json::Value::Null,
+        };
+        (
+            200,
+            serde_json::json!({ "result": result, "error": null }).to_string(),
+        )
+    });
+    let mut root = sample_root();
+    root.worker_options.bitcoind_rpc = Some(BitcoindRpc {
+        url,
+        cookie_file: Some(cookie.to_string_lossy().into_owned()),
+        ..Default::default()
+    });
+    let backend = backend::from_options(&root.worker_options, Network::Bitcoin, None).unwrap();
+    let policy = Policy::default();
+
+    let mut record = Record::new(&commit, &reveal);
+    record.step(backend.as_ref(), &policy).unwrap();
+    assert_eq!(record.stage, Stage::CommitBroadcast);
+    record.step(backend.as_ref(), &policy).unwrap();
+    assert_eq!(record.stage, Stage::CommitConfirmed);
+    assert_eq!(backend.tx_confirmations(&reveal).unwrap(), None);
+
+    let mut record = Record::new(&commit, &reveal);
+    record.stage = Stage::CommitBroadcast;
+    lookups_fail.store(true, std::sync::atomic::Ordering::SeqCst);
+    for _ in 0..policy.max_attempts {
+        record.step(backend.as_ref(), &policy).unwrap();
+    }
+    assert_eq!(record.stage, Stage::Failed);
+    assert_eq!(record.failed_stage, Some(Stage::CommitBroadcast));
+    assert!(record
+        .last_error
+        .as_deref()
+        .is_some_and(|err| err.contains("lookup failed")));
+    std::fs::remove_file(cookie).unwrap();
+}
+
+#[test]
+fn test_verify_claim_reports_each_mismatch() {
+    let mut root = sample_root();
+    root.copied_data.args.bitworkc = Some("0".to_string());
+    root.worker_bitwork_info_commit.prefix = Some("0".to_string());
+    let payload = get_payload(root.clone(), Some(1704688101), Some(7588557)).unwrap();
+    let sequence = (0..)
+        .find(|seq| predicate(*seq, &payload).unwrap())
+        .unwrap();
+    let tx = build_commit_tx(sequence, &payload).unwrap();
+    let claim = Claim {
+        sequence: sequence as u64,
+        nonce: 7588557,
+        time: 1704688101,
+        variant: Variant::default(),
+        txid: Some(tx.txid().to_string()),
+        tx: Some(tx.clone()),
+    };
+    let verification = verify_claim(&root, &claim).unwrap();
+    assert!(verification.valid, "{:?}", verification.mismatches);
+
+    let mut forged = tx.clone();
+    forged.output[0].value = Amount::from_sat(1);
+    let mut witness = forged.input[0].witness.to_vec();
+    witness[0][0] ^= 1;
+    forged.input[0].witness = Witness::from_slice(&witness);
+    let claim = Claim {
+        sequence: sequence as u64 + 1,
+        txid: Some(tx.txid().to_string()),
+        tx: Some(forged),
+        ..claim
+    };
+    let mismatches = verify_claim(&root, &claim).unwrap().mismatches;
+    let kinds: Vec<_> = mismatches
+        .iter()
+        .map(|m| serde_json::to_value(m).unwrap()["kind"].clone())
+        .collect();
+    assert!(kinds.contains(&"txidMismatch".into()), "{:?}", mismatches);
+    assert!(mismatches.contains(&Mismatch::TransactionDiffers {
+        field: "input[0].sequence".to_string()
+    }));
+    assert!(mismatches.contains(&Mismatch::TransactionDiffers {
+        field: "output[0].value".to_string()
+    }));
+    assert!(
+        kinds.contains(&"invalidSignature".into()),
+        "{:?}",
+        mismatches
+    );
+}
+
+#[test]
+fn test_validate_mint_like_the_indexer() {
+    let mut root = sample_root();
+    root.copied_data.args.bitworkc = Some("0".to_string());
+    root.worker_bitwork_info_commit.prefix = Some("0".to_string());
+    root.worker_options.address =
+        "bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr".to_string();
+    root.worker_options.dmt_options.mint_amount = 1000;
+    let payload = get_payload(root.clone(), Some(1704688101), Some(7588557)).unwrap();
+    let sequence = (0..)
+        .find(|seq| predicate(*seq, &payload).unwrap())
+        .unwrap();
+    let commit = build_commit_tx(sequence, &payload).unwrap();
+    let template = reveal_template(&root, &payload, commit.txid()).unwrap();
+    let reveal = build_reveal_tx(&template, 0, &payload).unwrap();
+
+    let rules = MintRules {
+        ticker: Some("ttts".to_string()),
+        mint_amount: Some(1000),
+        mint_bitworkc: Some("0".to_string()),
+        mint_bitworkr: None,
+    };
+    let validation = validate_mint(&commit, &reveal, &rules);
+    assert!(validation.credited, "{:?}", validation.violations);
+    assert_eq!(validation.ticker.as_deref(), Some("ttts"));
+
+    let rules = MintRules {
+        ticker: Some("other".to_string()),
+        mint_amount: Some(999),
+        mint_bitworkc: Some("00.1".to_string()),
+        mint_bitworkr: Some("f".to_string()),
+    };
+    let violations = validate_mint(&commit, &reveal, &rules).violations;
+    assert!(violations.contains(&Violation::TickerMismatch {
+        expected: "other".to_string(),
+        actual: "ttts".to_string(),
+    }));
+    assert!(violations.contains(&Violation::MintAmountMismatch {
+        expected: 999,
+        actual: Some(1000),
+    }));
+    assert!(violations.contains(&Violation::RequirementNotMet {
+        key: "bitworkc".to_string(),
+        required: "00.1".to_string(),
+        actual: Some("0".to_string()),
+    }));
+    assert!(violations.contains(&Violation::RequirementNotMet {
+        key: "bitworkr".to_string(),
+        required: "f".to_string(),
+        actual: None,
+    }));
+
+    let mut other = commit.clone();
+    other.input[0].sequence = bitcoin::Sequence(sequence + 1);
+    let violations = validate_mint(&other, &reveal, &MintRules::default()).violations;
+    assert!(matches!(
+        violations[..],
+        [Violation::NotSpendingCommit { input: 0, .. }]
+    ));
+
+    // An envelope missing a required arg is not credited.
+    for missing in ["time", "nonce", "mint_ticker"] {
+        let mut encoder = minicbor::Encoder::new(vec![]);
+        encoder.map(1).unwrap().str("args").unwrap().map(2).unwrap();
+        for key in ["time", "nonce", "mint_ticker"]
+            .into_iter()
+            .filter(|key| *key != missing)
+        {
+            match key {
+                "mint_ticker" => encoder.str(key).unwrap().str("ttts").unwrap(),
+                _ => encoder.str(key).unwrap().u64(1704688101).unwrap(),
+            };
+        }
+        let script = bitcoin::script::Builder::new()
+            .push_x_only_key(&payload.xonly_pub_key)
+            .push_opcode(bitcoin::opcodes::all::OP_CHECKSIG)
+            .push_opcode(bitcoin::opcodes::OP_FALSE)
+            .push_opcode(bitcoin::opcodes::all::OP_IF)
+            .push_slice(b"atom")
+            .push_slice(b"dmt")
+            .push_slice(bitcoin::script::PushBytesBuf::try_from(encoder.into_writer()).unwrap())
+            .push_opcode(bitcoin::opcodes::all::OP_ENDIF)
+            .into_script();
+        let mut stripped = reveal.clone();
+        let mut witness = reveal.input[0].witness.to_vec();
+        witness[1] = script.into_bytes();
+        stripped.input[0].witness = Witness::from_slice(&witness);
+        let validation = validate_mint(&commit, &stripped, &MintRules::default());
+        assert!(!validation.credited);
+        assert!(
+            validation.violations.contains(&Violation::MissingArg {
+                key: missing.to_string()
+            }),
+            "{:?}",
+            validation.violations
+        );
+    }
+
+    assert!(is_valid_ticker("ttts"));
+    assert!(!is_valid_ticker("TTTS"));
+    assert!(!is_valid_ticker(""));
+    assert!(!is_valid_ticker(&"a".repeat(22)));
+}
+
+#[test]
+fn test_scheduler_steals_and_stops() {
+    let tasks: Vec<Task> = (0..2)
+        .flat_map(|owner| {
+            (0..10_000).step_by(500).map(move |start| Task {
+                owner,
+                range: SequenceRange {
+                    start,
+                    end: start + 499,
+                },
+            })
+        })
+        .collect();
+    let scheduler = Scheduler {
+        threads: 4,
+        pin: false,
+        batch_size: 64,
+    };
+
+    let run = scheduler.run(
+        tasks.clone(),
+        2,
+        &AtomicBool::new(false),
+        |_, _| None::<u32>,
+    );
+    assert_eq!(run.hit, None);
+    assert_eq!(run.attempts, 20_000);
+    for completed in run.completed {
+        assert_eq!(
+            merge_ranges(completed),
+            vec![SequenceRange {
+                start: 0,
+                end: 9999
+            }]
+        );
+    }
+
+    let run = scheduler.run(tasks.clone(), 2, &AtomicBool::new(false), |owner, batch| {
+        (owner == 1 && batch.start <= 7777 && 7777 <= batch.end).then_some(7777)
+    });
+    assert_eq!(
+        run.hit,
+        Some(Hit {
+            owner: 1,
+            found: 7777
+        })
+    );
+    assert!(run.completed[1]
+        .iter()
+        .all(|range| !(range.start..=range.end).contains(&7777)));
+
+    let run = scheduler.run(tasks, 2, &AtomicBool::new(true), |_, _| None::<u32>);
+    assert_eq!(run.attempts, 0);
+}
+
+#[test]
+fn test_hash_backends_match_signed_commits() {
+    let payload = get_payload(sample_root(), Some(1704688101), Some(7588557)).unwrap();
+    let template = TxidTemplate::new(&build_commit_tx(0, &payload).unwrap(), 0).unwrap();
+    let expected: Vec<_> = (100..137)
+        .map(|seq| build_commit_tx(seq, &payload).unwrap().txid())
+        .collect();
+    for backend in HashBackend::ALL {
+        if !backend.is_supported() {
+            continue;
+        }
+        backend.self_test().unwrap();
+        let mut txids = vec![[0; 32]; expected.len()];
+        template.txids(backend, 100, &mut txids);
+        for (txid, expected) in txids.iter().zip(&expected) {
+            assert_eq!(Txid::from_byte_array(*txid), *expected, "{}", backend);
+        }
+    }
+    assert!(HashBackend::detect().self_test().is_ok());
+    assert!("avx3".parse::<HashBackend>().is_err());
+
+    for (prefix, ext) in [
+        ("0", None),
+        ("00", Some(8)),
+        ("ab", Some(0)),
+        ("", Some(15)),
+    ] {
+        let matcher = BitworkMatcher::new(&Some(prefix.to_string()), &ext).unwrap();
+        for txid in &expected {
+            assert_eq!(
+                matcher.matches(&txid.to_byte_array()),
+                has_valid_bitwork(&txid.to_string(), &Some(prefix.to_string()), &ext)
+            );
+        }
+    }
+    assert_eq!(BitworkMatcher::new(&Some("0G".to_string()), &None), None);
+}
+
+/// "Finds" a solution at a fixed sequence without hashing anything.
+struct FakeBackend {
+    sequence: u32,
+}
+
+struct FakeSearch(u32);
+
+impl SearchBackend for FakeBackend {
+    fn name(&self) -> String {
+        "fake".to_string()
+    }
+
+    fn prepare<'a>(&self, _payload: &'a Payload) -> anyhow::Result<Box<dyn Search + 'a>> {
+        Ok(Box::new(FakeSearch(self.sequence)))
+    }
+}
+
+impl Search for FakeSearch {
+    fn search(&self, range: SequenceRange) -> Vec<Candidate> {
+        if (range.start..=range.end).contains(&self.0) {
+            vec![Candidate {
+                sequence: self.0,
+                variant: Variant::default(),
+            }]
+        } else {
+            vec![]
+        }
+    }
+}
+
+#[test]
+fn test_mine_with_injected_backend() {
+    let root = sample_root();
+    let backend = FakeBackend { sequence: 123_456 };
+    match mine_with(&root, &CancellationToken::new(), &backend).unwrap() {
+        MineOutcome::Found(success) => assert_eq!(success.sequence, 123_456),
+        other => panic!("expected a hit, got {:?}", other),
+    }
+
+    assert_eq!(search::by_name(Some("scalar")).unwrap().name(), "scalar");
+    assert!(search::by_name(None).is_ok());
+    let err = search::by_name(Some("gpu")).err().unwrap();
+    assert!(err.to_string().contains("expected one of auto"), "{}", err);
+}
+
+#[test]
+fn test_grinding_variants_verify() {
+    let mut root = sample_root();
+    root.copied_data.args.bitworkc = Some("0".to_string());
+    root.worker_bitwork_info_commit.prefix = Some("0".to_string());
+    root.grind = GrindOptions {
+        max_lock_time: 2,
+        versions: vec![1, 2],
+        change_tolerance: 1,
+    };
+    let payload = get_payload(root.clone(), Some(1704688101), Some(7588557)).unwrap();
+    let all = variants(&payload).unwrap();
+    assert_eq!(all.len(), 12);
+    assert_eq!(all[0], Variant::default());
+
+    let search = HashSearchBackend {
+        hasher: HashBackend::Scalar,
+    }
+    .prepare(&payload)
+    .unwrap();
+    assert_eq!(search.variants(), 12);
+    let range = sequence_range(&commit_sequence_policy(&root));
+    assert_eq!(range.start, SEQUENCE_LOCK_TIME_DISABLE_FLAG);
+    let hits = search.search(SequenceRange {
+        start: range.start,
+        end: range.start + 255,
+    });
+    assert!(hits.iter().any(|hit| hit.variant != Variant::default()));
+    for hit in hits {
+        let claim = Claim {
+            sequence: hit.sequence as u64,
+            nonce: 7588557,
+            time: 1704688101,
+            variant: hit.variant,
+            txid: None,
+            tx: None,
+        };
+        let verification = verify_claim(&root, &claim).unwrap();
+        assert!(verification.valid, "{:?}", verification.mismatches);
+    }
+
+    root.grind.max_lock_time = 500_000_000;
+    let payload = get_payload(root, Some(1704688101), Some(7588557)).unwrap();
+    assert!(variants(&payload).is_err());
+}
+
+#[test]
+fn test_commits_stay_final_before_mining() {
+    let mut root = sample_root();
+    root.grind = GrindOptions {
+        max_lock_time: 10,
+        versions: vec![1, 2],
+        change_tolerance: 0,
+    };
+    root.block_height = Some(3);
+    let payload = get_payload(root.clone(), Some(1704688101), Some(7588557)).unwrap();
+    let all = variants(&payload).unwrap();
+    assert_eq!(all.len(), 8);
+    assert!(all.iter().all(|variant| variant.lock_time <= 3));
+
+    // Relative locks are searched only when non-standard commits are wanted.
+    let range = sequence_range(&commit_sequence_policy(&root));
+    assert_eq!(range.start, SEQUENCE_LOCK_TIME_DISABLE_FLAG);
+    root.allow_non_standard = true;
+    assert_eq!(sequence_range(&commit_sequence_policy(&root)).start, 0);
+
+    let mut backwards = small_job("00", CHUNK_SIZE - 1);
+    let worker = &mut backwards.resume.as_mut().unwrap().workers[0];
+    (worker.start, worker.end) = (5, 4);
+    assert!(job_workers(&backwards).is_err());
+}
+
+#[test]
+fn test_patched_variant_templates_match_built_commits() {
+    let mut root = sample_root();
+    root.grind = GrindOptions {
+        max_lock_time: 3,
+        versions: vec![1, 2],
+        change_tolerance: 2,
+    };
+    let payload = get_payload(root.clone(), Some(1704688101), Some(7588557)).unwrap();
+    assert!(payload.need_change_fee_output);
+    let templates = variant_templates(&payload).unwrap();
+    assert_eq!(templates.len(), 24);
+    for (variant, template) in templates {
+        let commit = build_commit_tx(
+            SEQUENCE_LOCK_TIME_DISABLE_FLAG,
+            &Payload {
+                variant,
+                ..payload.clone()
+            },
+        )
+        .unwrap();
+        assert_eq!(template, TxidTemplate::new(&commit, 0).unwrap());
+        assert_eq!(
+            template.txid(HashBackend::Scalar, SEQUENCE_LOCK_TIME_DISABLE_FLAG),
+            commit.txid()
+        );
+    }
+
+    root.grind.max_lock_time = MAX_VARIANTS as u32;
+    let payload = get_payload(root, Some(1704688101), Some(7588557)).unwrap();
+    let err = variants(&payload).unwrap_err().to_string();
+    assert!(
+        err.contains("lower changeTolerance or maxLockTime"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn test_version_two_commits_never_lock_the_funding_input() {
+    let mut root = sample_root();
+    root.copied_data.args.bitworkc = Some("0".to_string());
+    root.worker_bitwork_info_commit.prefix = Some("0".to_string());
+    root.grind.versions = vec![2];
+    root.concurrency = 4;
+    for worker in job_workers(&root).unwrap() {
+        assert!(worker.start >= SEQUENCE_LOCK_TIME_DISABLE_FLAG);
+    }
+    let found = match mine(&root, &CancellationToken::new()).unwrap() {
+        MineOutcome::Found(success) => success,
+        MineOutcome::Stopped(stop) => panic!("stopped: {:?}", stop),
+    };
+    assert_eq!(found.variant.unwrap().version, 2);
+    assert!(!Sequence(found.sequence as u32).is_relative_lock_time());
+
+    // A version 2 claim whose sequence is a relative lock is refused.
+    let payload = get_payload(root.clone(), Some(1704688101), Some(7588557)).unwrap();
+    let variant = Variant {
+        version: 2,
+        ..Variant::default()
+    };
+    let locked = (0..SEQUENCE_LOCK_TIME_DISABLE_FLAG)
+        .find(|seq| {
+            let payload = Payload {
+                variant,
+                ..payload.clone()
+            };
+            predicate(*seq, &payload).unwrap()
+        })
+        .unwrap();
+    let claim = Claim {
+        sequence: locked as u64,
+        nonce: 7588557,
+        time: 1704688101,
+        variant,
+        txid: None,
+        tx: None,
+    };
+    let verification = verify_claim(&root, &claim).unwrap();
+    assert!(verification
+        .mismatches
+        .contains(&Mismatch::SequenceOutOfRange {
+            sequence: locked as u64
+        }));
+
+    for version in [0, 3] {
+        root.grind.versions = vec![1, version];
+        let payload = get_payload(root.clone(), Some(1704688101), Some(7588557)).unwrap();
+        assert!(variants(&payload).is_err());
+    }
+}
+
+#[test]
+fn test_nonce_roller_matches_taproot_builder() {
+    let root = sample_root();
+    let payload = get_payload(root.clone(), Some(1704688101), Some(0)).unwrap();
+    let op_type = &root.worker_options.op_type;
+    let roller = NonceRoller::new(
+        &payload.secp,
+        &payload.xonly_pub_key,
+        &payload.copied_data,
+        op_type,
+        None,
+    )
+    .unwrap();
+    let nonces = [
+        0,
+        23,
+        24,
+        255,
+        256,
+        65_535,
+        65_536,
+        7_588_557,
+        u32::MAX as u64,
+        u32::MAX as u64 + 1,
+        u64::MAX,
+    ];
+    let scripts = roller.scripts(&nonces).unwrap();
+    for (nonce, script) in nonces.iter().zip(scripts) {
+        let mut copied_data = payload.copied_data.clone();
+        copied_data.args.nonce = *nonce;
+        let (_, expected) = get_address_by_copied_data(
+            &payload.secp,
+            &payload.xonly_pub_key,
+            &copied_data,
+            op_type,
+            None,
+            Network::Bitcoin,
+        );
+        assert_eq!(script, expected, "nonce {}", nonce);
+    }
+
+    let pairs = [(1704688101, 5), (1704688102, 9_999_999), (1704688101, 42)];
+    for (payload, (time, nonce)) in worker_payloads(&root, &pairs).unwrap().iter().zip(pairs) {
+        let expected = get_payload(root.clone(), Some(time), Some(nonce)).unwrap();
+        assert_eq!(payload.copied_data, expected.copied_data);
+        assert_eq!(
+            payload.fixed_output_script_pubkey,
+            expected.fixed_output_script_pubkey
+        );
+    }
+}
+
+#[test]
+fn test_rolled_worker_nonces_match_get_payload_output_keys() {
+    let mut root = sample_root();
+    root.concurrency = 24;
+    root.fan_out = 2;
+    root.refund = Some(RefundOptions {
+        key: "1b84c5567b126440995d3ed5aaba0565d71e1834604819ff9c17f5e9d5dd078f".to_string(),
+        blocks: 144,
+    });
+    let workers = job_workers(&root).unwrap();
+    for pair in workers.windows(2) {
+        assert_eq!(pair[1].nonce, pair[0].nonce + 2);
+    }
+    let pairs: Vec<(u64, u64)> = workers.iter().map(|w| (w.time, w.nonce)).collect();
+    let payloads = worker_payloads(&root, &pairs).unwrap();
+    for (payload, (time, nonce)) in payloads.iter().zip(pairs) {
+        let expected = get_payload(root.clone(), Some(time), Some(nonce)).unwrap();
+        assert_eq!(payload.copied_data, expected.copied_data);
+        assert_eq!(
+            payload.fixed_output_script_pubkey, expected.fixed_output_script_pubkey,
+            "nonce {}",
+            nonce
+        );
+        assert_eq!(
+            payload.fan_out_script_pubkeys, expected.fan_out_script_pubkeys,
+            "nonce {}",
+            nonce
+        );
+    }
+}
+
+/// A checkpoint with two nonces over `0..=end`, for jobs that end quickly.
+fn small_job(prefix: &str, end: u32) -> Root {
+    let mut root = sample_root();
+    root.copied_data.args.bitworkc = Some(prefix.to_string());
+    root.worker_bitwork_info_commit.prefix = Some(prefix.to_string());
+    root.resume = Some(Checkpoint {
+        workers: [11, 12]
+            .map(|nonce| WorkerCheckpoint {
+                nonce,
+                time: 1704688101,
+                start: 0,
+                end,
+                completed: vec![],
+            })
+            .to_vec(),
+    });
+    root
+}
+
+/// The first owner setup and assignment a coordinator sends on `stream`.
+fn read_setup_and_assignment(stream: &TcpStream) -> (OwnerSetup, Assignment) {
+    let mut lines = BufReader::new(stream.try_clone().unwrap()).lines();
+    let mut next = || serde_json::from_str(&lines.next().unwrap().unwrap()).unwrap();
+    let Message::Setup(setup) = next() else {
+        panic!("expected an owner setup");
+    };
+    let Message::Assign(assignment) = next() else {
+        panic!("expected an assignment");
+    };
+    assert_eq!(assignment.owner, setup.owner);
+    (setup, assignment)
+}
+
+#[test]
+fn test_cluster_finds_and_signs_on_coordinator() {
+    let root = small_job("00", 5 * CHUNK_SIZE - 1);
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let workers: Vec<_> = (0..2)
+        .map(|_| thread::spawn(move || cluster::work(addr, HashBackend::Scalar, 1, None)))
+        .collect();
+    let outcome = cluster::coordinate(&root, listener, &CancellationToken::new())
+        .unwrap()
+        .outcome;
+    let MineOutcome::Found(success) = outcome else {
+        panic!("expected a hit, got {:?}", outcome);
+    };
+    assert!([11, 12].contains(&success.nonce));
+    assert!(verify_claim(&root, &Claim::from(&success)).unwrap().valid);
+    for worker in workers {
+        worker.join().unwrap().unwrap();
+    }
+}
+
+#[test]
+fn test_cluster_requeues_work_of_dropped_workers() {
+    let root = small_job(&"0".repeat(16), 2 * CHUNK_SIZE - 1);
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let liar = thread::spawn(move || {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        writeln!(stream, r#"{{"type":"hello","threads":1}}"#).unwrap();
+        let (setup, assignment) = read_setup_and_assignment(&stream);
+        let template: Transaction =
+            bitcoin::consensus::deserialize(&hex::decode(&setup.templates[0].tx).unwrap()).unwrap();
+        assert!(template.input[0].witness.is_empty(), "commit is unsigned");
+        let honest = thread::spawn(move || cluster::work(addr, HashBackend::Scalar, 1, None));
+        let found = Message::Found {
+            id: assignment.id,
+            sequence: assignment.range.start,
+            variant: Default::default(),
+        };
+        writeln!(stream, "{}", serde_json::to_string(&found).unwrap()).unwrap();
+        honest.join().unwrap().unwrap();
+    });
+    let outcome = cluster::coordinate(&root, listener, &CancellationToken::new())
+        .unwrap()
+        .outcome;
+    let MineOutcome::Stopped(report) = outcome else {
+        panic!("expected exhaustion, got {:?}", outcome);
+    };
+    assert_eq!(report.status, StopReason::Exhausted);
+    assert_eq!(report.attempts, 4 * CHUNK_SIZE as u64);
+    for worker in report.checkpoint.workers {
+        assert_eq!(
+            worker.completed,
+            vec![SequenceRange {
+                start: 0,
+                end: 2 * CHUNK_SIZE - 1
+            }]
+        );
+    }
+    liar.join().unwrap();
+}
+
+#[test]
+fn test_cluster_clamps_claimed_threads() {
+    let root = small_job(&"0".repeat(16), CHUNK_SIZE - 1);
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let greedy = thread::spawn(move || {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let hello = Message::Hello {
+            threads: usize::MAX,
+            name: None,
+        };
+        writeln!(stream, "{}", serde_json::to_string(&hello).unwrap()).unwrap();
+        // One owner's chunks per assignment, each claimed but not searched.
+        for _ in 0..2 {
+            let (_, assignment) = read_setup_and_assignment(&stream);
+            let done = Message::Done { id: assignment.id };
+            writeln!(stream, "{}", serde_json::to_string(&done).unwrap()).unwrap();
+        }
+    });
+    let outcome = cluster::coordinate(&root, listener, &CancellationToken::new())
+        .unwrap()
+        .outcome;
+    assert!(matches!(outcome, MineOutcome::Stopped(_)));
+    greedy.join().unwrap();
+}
+
+#[test]
+fn test_worker_halts_on_stop() {
+    let root = small_job(&"0".repeat(16), u32::MAX);
+    let payload = worker_payloads(&root, &[(1704688101, 11)])
+        .unwrap()
+        .remove(0);
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (done, halted) = mpsc::channel();
+    thread::spawn(move || {
+        let _ = done.send(cluster::work(addr, HashBackend::Scalar, 1, None));
+    });
+    let (mut stream, _) = listener.accept().unwrap();
+    let mut hello = String::new();
+    BufReader::new(stream.try_clone().unwrap())
+        .read_line(&mut hello)
+        .unwrap();
+    let setup = Message::Setup(OwnerSetup {
+        owner: 0,
+        prefix: payload.valid_prefix.clone(),
+        ext: payload.valid_ext,
+        share_prefix: None,
+        share_ext: None,
+        templates: vec![CommitTemplate {
+            variant: payload.variant,
+            tx: serialize_hex(&build_commit_tx(0, &payload).unwrap()),
+        }],
+    });
+    let assign = Message::Assign(Assignment {
+        id: 0,
+        owner: 0,
+        range: SequenceRange {
+            start: 0,
+            end: u32::MAX,
+        },
+    });
+    for message in [setup, assign] {
+        writeln!(stream, "{}", serde_json::to_string(&message).unwrap()).unwrap();
+    }
+    thread::sleep(Duration::from_millis(200));
+    assert!(halted.try_recv().is_err(), "worker is searching");
+
+    // The connection stays open; only the message ends the search.
+    writeln!(stream, "{}", serde_json::to_string(&Message::Stop).unwrap()).unwrap();
+    halted
+        .recv_timeout(Duration::from_secs(10))
+        .expect("worker halts on stop")
+        .unwrap();
+    drop(stream);
+}
+
+#[test]
+fn test_share_bitwork_grades() {
+    assert!(is_share_bitwork(("0000", None), ("00", None)));
+    assert!(is_share_bitwork(("0000", Some(8)), ("0000", Some(3))));
+    assert!(is_share_bitwork(("00a", None), ("00", Some(9))));
+    assert!(!is_share_bitwork(("00a", None), ("00", Some(11))));
+    assert!(!is_share_bitwork(("0000", None), ("0000", None)));
+    assert!(!is_share_bitwork(("0000", None), ("01", None)));
+
+    let (target, share) = (Some("0000".to_string()), Some("00".to_string()));
+    let grade = |txid: &str| grade_bitwork(txid, (&target, &None), (&share, &None));
+    assert_eq!(grade("0000ab"), BitworkGrade::Solution);
+    assert_eq!(grade("00abcd"), BitworkGrade::Share);
+    assert_eq!(grade("0abcde"), BitworkGrade::Miss);
+}
+
+#[test]
+fn test_pool_credits_verified_shares() {
+    let end = CHUNK_SIZE - 1;
+    let mut root = small_job(&"0".repeat(16), end);
+    root.share_bitwork = Some("00".to_string());
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    // Shares over the whole job, counted locally.
+    let pairs = [(1704688101, 11), (1704688101, 12)];
+    let share = BitworkMatcher::new(&root.share_bitwork, &None);
+    let searches: Vec<TemplateSearch> = worker_payloads(&root, &pairs)
+        .unwrap()
+        .iter()
+        .map(|payload| {
+            let template = TxidTemplate::new(&build_commit_tx(0, payload).unwrap(), 0).unwrap();
+            TemplateSearch::new(
+                vec![(payload.variant, template)],
+                share.clone(),
+                HashBackend::Scalar,
+            )
+        })
+        .collect();
+    let expected: usize = searches
+        .iter()
+        .map(|search| search.search(SequenceRange { start: 0, end }).len())
+        .sum();
+    assert!(expected > 0);
+
+    // Submits one real share twice and a miss, then leaves its work to others.
+    let cheat = thread::spawn(move || {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        writeln!(stream, r#"{{"type":"hello","threads":1,"name":"cheat"}}"#).unwrap();
+        let (setup, assignment) = read_setup_and_assignment(&stream);
+        assert_eq!(setup.share_prefix.as_deref(), Some("00"));
+        let template: Transaction =
+            bitcoin::consensus::deserialize(&hex::decode(&setup.templates[0].tx).unwrap()).unwrap();
+        let search = TemplateSearch::new(
+            vec![(
+                setup.templates[0].variant,
+                TxidTemplate::new(&template, 0).unwrap(),
+            )],
+            share,
+            HashBackend::Scalar,
+        );
+        let hits = search.search(assignment.range);
+        let miss = (assignment.range.start..=assignment.range.end)
+            .find(|sequence| hits.iter().all(|hit| hit.sequence != *sequence))
+            .unwrap();
+        for sequence in [hits[0].sequence, hits[0].sequence, miss] {
+            let share = Message::Share {
+                id: assignment.id,
+                sequence,
+                variant: setup.templates[0].variant,
+            };
+            writeln!(stream, "{}", serde_json::to_string(&share).unwrap()).unwrap();
+        }
+        let honest: Vec<_> = ["alice", "bob"]
+            .map(|name| {
+                thread::spawn(move || {
+                    cluster::work(addr, HashBackend::Scalar, 1, Some(name.to_string()))
+                })
+            })
+            .into();
+        for worker in honest {
+            worker.join().unwrap().unwrap();
+        }
+    });
+    let coordinated = cluster::coordinate(&root, listener, &CancellationToken::new()).unwrap();
+    cheat.join().unwrap();
+    assert!(matches!(coordinated.outcome, MineOutcome::Stopped(_)));
+
+    let contributions: HashMap<_, _> = coordinated
+        .contributions
+        .iter()
+        .map(|contribution| (contribution.worker.as_str(), contribution))
+        .collect();
+    assert_eq!(contributions["cheat"].shares, 1);
+    assert_eq!(contributions["cheat"].rejected, 2);
+    assert_eq!(contributions["cheat"].work, 256.0);
+    let credited: u64 = coordinated
+        .contributions
+        .iter()
+        .map(|contribution| contribution.shares)
+        .sum();
+    assert_eq!(credited, expected as u64);
+
+    root.share_bitwork = Some("0".repeat(16));
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    assert!(cluster::coordinate(&root, listener, &CancellationToken::new()).is_err());
+}
+
+#[test]
+fn test_near_misses_are_counted_and_journaled() {
+    let end = 2 * CHUNK_SIZE - 1;
+    let mut root = small_job("000000", end);
+    let path = std::env::temp_dir().join(format!("psbt-near-misses-{}.jsonl", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    root.near_misses = Some(NearMissOptions {
+        levels: 4,
+        journal: Some(path.to_str().unwrap().to_string()),
+    });
+    let backend = HashSearchBackend {
+        hasher: HashBackend::Scalar,
+    };
+    let outcome = mine_with(&root, &CancellationToken::new(), &backend).unwrap();
+    let MineOutcome::Stopped(report) = outcome else {
+        panic!("expected exhaustion, got {:?}", outcome);
+    };
+    assert_eq!(report.status, StopReason::Exhausted);
+    let levels = report.near_misses.unwrap();
+    let prefixes: Vec<&str> = levels.iter().map(|level| level.prefix.as_str()).collect();
+    assert_eq!(prefixes, ["00000", "0000", "000", "00"]);
+
+    let payloads = worker_payloads(&root, &[(1704688101, 11), (1704688101, 12)]).unwrap();
+    for level in &levels {
+        let matcher = BitworkMatcher::new(&Some(level.prefix.clone()), &None);
+        let observed: usize = payloads
+            .iter()
+            .map(|payload| {
+                let template = TxidTemplate::new(&build_commit_tx(0, payload).unwrap(), 0).unwrap();
+                TemplateSearch::new(
+                    vec![(payload.variant, template)],
+                    matcher.clone(),
+                    HashBackend::Scalar,
+                )
+                .search(SequenceRange { start: 0, end })
+                .len()
+            })
+            .sum();
+        assert_eq!(level.observed, observed as u64, "prefix {}", level.prefix);
+        assert_eq!(
+            level.expected,
+            2.0 * CHUNK_SIZE as f64 * 2.0 / bitwork_difficulty(&level.prefix, None)
+        );
+        assert!(level.probability > 1e-9);
+    }
+
+    let journal = std::fs::read_to_string(&path).unwrap();
+    let records: Vec<NearMissRecord> = journal
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(records.len() as u64, levels[3].observed);
+    for record in &records {
+        assert!(record.txid.starts_with(&record.prefix));
+        assert!([11, 12].contains(&record.nonce));
+    }
+    std::fs::remove_file(path).unwrap();
+}
+
+/// Hashes nothing, as a broken hash path might.
+struct BlindBackend;
+
+struct BlindSearch;
+
+impl SearchBackend for BlindBackend {
+    fn name(&self) -> String {
+        "blind".to_string()
+    }
+
+    fn prepare<'a>(&self, _payload: &'a Payload) -> anyhow::Result<Box<dyn Search + 'a>> {
+        Ok(Box::new(BlindSearch))
+    }
+}
+
+impl Search for BlindSearch {
+    fn search(&self, _range: SequenceRange) -> Vec<Candidate> {
+        vec![]
+    }
+
+    fn search_near(
+        &self,
+        _range: SequenceRange,
+        _near: &BitworkMatcher,
+    ) -> Option<(Vec<Candidate>, Vec<NearMiss>)> {
+        Some((vec![], vec![]))
+    }
+}
+
+#[test]
+fn test_near_misses_stop_a_broken_search_early() {
+    let end = 5 * CHUNK_SIZE - 1;
+    let mut root = small_job("000000", end);
+    root.near_misses = Some(NearMissOptions {
+        levels: 4,
+        journal: None,
+    });
+    let outcome = mine_with(&root, &CancellationToken::new(), &BlindBackend).unwrap();
+    let MineOutcome::Stopped(report) = outcome else {
+        panic!("expected a stop, got {:?}", outcome);
+    };
+    assert_eq!(report.status, StopReason::Implausible);
+    assert!(report.attempts < 2 * (end as u64 + 1));
+    assert_eq!(report.near_misses.unwrap()[3].observed, 0);
+
+    // Searches that cannot report near misses are refused up front.
+    let backend = FakeBackend { sequence: 0 };
+    assert!(mine_with(&root, &CancellationToken::new(), &backend).is_err());
+}
+
+#[test]
+fn test_batch_chains_commits_through_change() {
+    let mut root = sample_root();
+    root.copied_data.args.bitworkc = Some("00".to_string());
+    root.worker_bitwork_info_commit.prefix = Some("00".to_string());
+    root.worker_options.address =
+        "bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr".to_string();
+    root.worker_options.dmt_options.mint_amount = 1000;
+    // Enough for two links; the second commit's change cannot fund a third.
+    root.funding_utxo.value = 25000;
+    let backend = HashSearchBackend {
+        hasher: HashBackend::Scalar,
+    };
+    let report = mine_batch(&root, 3, &CancellationToken::new(), &backend).unwrap();
+    assert_eq!(report.mints.len(), 2);
+    assert!(report.stopped.is_none());
+
+    let decode = |hex: &str| -> Transaction {
+        bitcoin::consensus::deserialize(&hex::decode(hex).unwrap()).unwrap()
+    };
+    let mut funding = root.funding_utxo.txid.clone();
+    for mint in &report.mints {
+        let commit = decode(&mint.commit_tx);
+        let reveal = decode(&mint.reveal_tx);
+        assert!(mint.commit_txid.starts_with("00"));
+        assert_eq!(commit.txid().to_string(), mint.commit_txid);
+        assert_eq!(commit.input[0].previous_output.txid.to_string(), funding);
+        assert_eq!(reveal.input[0].previous_output.txid, commit.txid());
+        funding = mint.commit_txid.clone();
+    }
+    let change = report.next_funding.unwrap();
+    assert_eq!(change.txid, funding);
+    assert_eq!((change.vout, change.value), (1, 25000 - 2 * 10730));
+
+    assert!(mine_batch(&root, MAX_BATCH + 1, &CancellationToken::new(), &backend).is_err());
+}
+
+#[test]
+fn test_fan_out_commit_reveals_each_output() {
+    let mut root = sample_root();
+    root.copied_data.args.bitworkc = Some("0".to_string());
+    root.worker_bitwork_info_commit.prefix = Some("0".to_string());
+    root.worker_options.address =
+        "bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr".to_string();
+    root.worker_options.dmt_options.mint_amount = 1000;
+    root.fan_out = 3;
+
+    let planned = plan(&root, Some(7588557), Some(1704688101)).unwrap();
+    assert_eq!(planned.required_funding, 3 * 10000 + 300 + 2 * 430);
+    assert_eq!(planned.fan_out_addresses.len(), 2);
+
+    let payload = get_payload(root.clone(), Some(1704688101), Some(7588557)).unwrap();
+    let sequence = (0..)
+        .find(|seq| predicate(*seq, &payload).unwrap())
+        .unwrap();
+    let commit = build_commit_tx(sequence, &payload).unwrap();
+    let values: Vec<u64> = commit.output.iter().map(|o| o.value.to_sat()).collect();
+    assert_eq!(
+        values,
+        [10000, 10000, 10000, 100000 - 30000 - 300 - 3 * 430]
+    );
+
+    let templates = reveal_templates(&root, &payload, commit.txid()).unwrap();
+    assert_eq!(templates.len(), 3);
+    let rules = MintRules {
+        ticker: Some("ttts".to_string()),
+        mint_amount: Some(1000),
+        mint_bitworkc: Some("0".to_string()),
+        mint_bitworkr: None,
+    };
+    for (output, template) in templates.iter().enumerate() {
+        let reveal = build_reveal_tx(template, 0, &payload).unwrap();
+        assert_eq!(reveal.input[0].previous_output.vout, output as u32);
+        let envelope = decode_envelope(reveal.input[0].witness.tapscript().unwrap()).unwrap();
+        assert_eq!(envelope.copied_data.args.nonce, 7588557 + output as u64);
+        let validation = validate_mint(&commit, &reveal, &rules);
+        assert!(validation.credited, "{:?}", validation.violations);
+    }
+
+    // Batched derivation agrees with the one-at-a-time outputs.
+    let pairs = [(1704688101, 7588557), (1704688101, 42)];
+    for (batched, (time, nonce)) in worker_payloads(&root, &pairs).unwrap().iter().zip(pairs) {
+        let expected = get_payload(root.clone(), Some(time), Some(nonce)).unwrap();
+        assert_eq!(
+            batched.fan_out_script_pubkeys,
+            expected.fan_out_script_pubkeys
+        );
+    }
+}
+
+#[test]
+fn test_bump_re_mines_a_replaceable_commit() {
+    let mut root = sample_root();
+    root.copied_data.args.bitworkc = Some("00".to_string());
+    root.worker_bitwork_info_commit.prefix = Some("00".to_string());
+    let payload = get_payload(root.clone(), Some(1704688101), Some(7588557)).unwrap();
+    let sequence = (0..)
+        .find(|seq| predicate(*seq, &payload).unwrap())
+        .unwrap();
+    let original = Claim {
+        sequence: sequence as u64,
+        nonce: 7588557,
+        time: 1704688101,
+        variant: Variant::default(),
+        txid: None,
+        tx: None,
+    };
+    let options = BumpOptions {
+        satsbyte: 50,
+        descendant_fees: 0,
+    };
+    let token = CancellationToken::new();
+    let bumped = bump(&root, &original, &options, &token, HashBackend::Scalar).unwrap();
+    assert_eq!(bumped.replaced_fee, 300 + 430);
+    assert!(bumped.commit_txid.starts_with("00"));
+    assert!(bumped.result.sequence <= MAX_RBF_SEQUENCE as u64);
+    let commit: Transaction =
+        bitcoin::consensus::deserialize(&hex::decode(&bumped.commit_tx).unwrap()).unwrap();
+    let vsize = commit.vsize() as u64;
+    assert!(bumped.fee >= 50 * vsize);
+    assert!(bumped.fee >= bumped.replaced_fee + vsize);
+    assert_eq!(
+        commit.output[0],
+        build_commit_tx(sequence, &payload).unwrap().output[0]
+    );
+    let verification = verify_claim(&root, &Claim::from(&bumped.result)).unwrap();
+    assert!(verification.valid);
+
+    // Evicting a reveal costs its fee on top.
+    let options = BumpOptions {
+        satsbyte: 1,
+        descendant_fees: 9000,
+    };
+    let bumped = bump(&root, &original, &options, &token, HashBackend::Scalar).unwrap();
+    assert_eq!(bumped.fee, 730 + 9000 + vsize);
+
+    let final_sequence = Claim {
+        sequence: 0xFFFFFFFE,
+        ..original.clone()
+    };
+    assert!(bump(
+        &root,
+        &final_sequence,
+        &options,
+        &token,
+        HashBackend::Scalar
+    )
+    .is_err());
+    let options = BumpOptions {
+        satsbyte: 1000,
+        descendant_fees: 0,
+    };
+    assert!(bump(&root, &original, &options, &token, HashBackend::Scalar).is_err());
+}
+
+#[test]
+fn test_sequence_policy_restricts_the_search() {
+    let range = |rbf, no_relative_lock_time| {
+        let range = sequence_range(&SequencePolicy {
+            rbf,
+            no_relative_lock_time,
+        });
+        (range.start, range.end)
+    };
+    assert_eq!(range(RbfPolicy::Any, false), (0, 0xfffffffe));
+    assert_eq!(range(RbfPolicy::Signal, false), (0, 0xfffffffd));
+    assert_eq!(range(RbfPolicy::Final, false), (0xfffffffe, 0xffffffff));
+    assert_eq!(range(RbfPolicy::Signal, true), (0x80000000, 0xfffffffd));
+    assert_eq!(range(RbfPolicy::Final, true), (0xfffffffe, 0xffffffff));
+
+    let final_only = SequenceRange {
+        start: 0xfffffffe,
+        end: 0xffffffff,
+    };
+    assert_eq!(
+        partition(4, final_only),
+        [(0xfffffffe, 0xfffffffe), (0xffffffff, 0xffffffff)]
+    );
+    let any = sequence_range(&SequencePolicy::default());
+    assert_eq!(
+        partition(2, any),
+        [(0, 0x7ffffffe), (0x7fffffff, 0xfffffffe)]
+    );
+
+    let mut root = sample_root();
+    root.copied_data.args.bitworkc = Some("00".to_string());
+    root.worker_bitwork_info_commit.prefix = Some("00".to_string());
+    root.sequence_policy = SequencePolicy {
+        rbf: RbfPolicy::Signal,
+        no_relative_lock_time: true,
+    };
+    let MineOutcome::Found(success) = mine(&root, &CancellationToken::new()).unwrap() else {
+        panic!("expected a hit");
+    };
+    assert!((0x80000000..=0xfffffffd).contains(&success.sequence));
+    assert!(verify_claim(&root, &Claim::from(&success)).unwrap().valid);
+
+    let outside = Claim {
+        sequence: 5,
+        ..Claim::from(&success)
+    };
+    let mismatches = verify_claim(&root, &outside).unwrap().mismatches;
+    assert!(mismatches.contains(&Mismatch::SequenceOutOfRange { sequence: 5 }));
+
+    let mut resumed = small_job("00", CHUNK_SIZE - 1);
+    resumed.sequence_policy = root.sequence_policy;
+    assert!(mine(&resumed, &CancellationToken::new()).is_err());
+
+    // Two final sequences are no search without a grind.
+    root.sequence_policy.rbf = RbfPolicy::Final;
+    assert!(plan(&root, Some(7588557), Some(1704688101)).is_err());
+    assert!(mine(&root, &CancellationToken::new()).is_err());
+    root.grind.max_lock_time = 1;
+    let planned = plan(&root, Some(7588557), Some(1704688101)).unwrap();
+    assert_eq!(planned.search_space, 4);
+}
+
+#[test]
+fn test_policy_flags_non_standard_transactions() {
+    let mut root = sample_root();
+    root.worker_options.address =
+        "bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr".to_string();
+    root.worker_options.dmt_options.mint_amount = 1000;
+    let payload = get_payload(root.clone(), Some(1704688101), Some(7588557)).unwrap();
+    let commit = build_commit_tx(0, &payload).unwrap();
+    let template = reveal_template(&root, &payload, commit.txid()).unwrap();
+    let reveal = build_reveal_tx(&template, 0, &payload).unwrap();
+    ensure_mint_standard(&root, &payload, &commit, std::slice::from_ref(&reveal)).unwrap();
+
+    let funding = TxOut {
+        value: payload.funding_utxo_value,
+        script_pubkey: payload.funding_private_script_pubkey.clone(),
+    };
+    let mut nonstandard = commit.clone();
+    nonstandard.version = bitcoin::transaction::Version(3);
+    nonstandard.output[0].value = Amount::from_sat(100);
+    nonstandard.output[1].value = payload.funding_utxo_value - Amount::from_sat(100);
+    assert_eq!(
+        check(&nonstandard, std::slice::from_ref(&funding), None).unwrap(),
+        [
+            Nonstandard::Version { version: 3 },
+            Nonstandard::Dust {
+                output: 0,
+                value: 100,
+                threshold: 330,
+            },
+            Nonstandard::FeeBelowRelay {
+                fee: 0,
+                required: nonstandard.vsize() as u64,
+            },
+        ]
+    );
+    assert!(ensure_standard(&nonstandard, std::slice::from_ref(&funding), None, false).is_err());
+    ensure_standard(&nonstandard, std::slice::from_ref(&funding), None, true).unwrap();
+
+    // Not final: locked past the job's height, or relatively locked.
+    let mut locked = commit.clone();
+    locked.version = bitcoin::transaction::Version::TWO;
+    locked.input[0].sequence = Sequence::from_height(6);
+    locked.lock_time = bitcoin::absolute::LockTime::from_consensus(800_001);
+    assert_eq!(
+        check(&locked, std::slice::from_ref(&funding), Some(800_000)).unwrap(),
+        [
+            Nonstandard::NonFinal {
+                lock_time: 800_001,
+                height: 800_000,
+            },
+            Nonstandard::RelativeLock {
+                input: 0,
+                sequence: 6,
+            },
+        ]
+    );
+    locked.input[0].sequence = Sequence::ENABLE_RBF_NO_LOCKTIME;
+    assert!(
+        check(&locked, std::slice::from_ref(&funding), Some(800_001))
+            .unwrap()
+            .is_empty()
+    );
+    root.block_height = Some(0);
+    ensure_mint_standard(&root, &payload, &commit, std::slice::from_ref(&reveal)).unwrap();
+
+    // A push past the element limit, as an unchunked envelope would make.
+    let mut oversized = reveal.clone();
+    let script = bitcoin::script::Builder::new()
+        .push_slice(bitcoin::script::PushBytesBuf::try_from(vec![0u8; 521]).unwrap())
+        .into_script();
+    let mut witness = Witness::new();
+    witness.push([0u8; 81]);
+    witness.push(script.as_bytes());
+    witness.push(template.control_block.serialize());
+    oversized.input[0].witness = witness;
+    let found = check(
+        &oversized,
+        std::slice::from_ref(&template.commit_output),
+        None,
+    )
+    .unwrap();
+    assert!(found.contains(&Nonstandard::PushTooLarge {
+        input: 0,
+        size: 521
+    }));
+    assert!(found.contains(&Nonstandard::WitnessItemTooLarge {
+        input: 0,
+        item: 0,
+        size: 81
+    }));
+}
+
+#[test]
+fn test_interpreter_verifies_signed_mints() {
+    let mut root = sample_root();
+    root.worker_options.address =
+        "bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr".to_string();
+    root.worker_options.dmt_options.mint_amount = 1000;
+    let payload = get_payload(root.clone(), Some(1704688101), Some(7588557)).unwrap();
+    let commit = build_commit_tx(0, &payload).unwrap();
+    let template = reveal_template(&root, &payload, commit.txid()).unwrap();
+    let reveal = build_reveal_tx(&template, 0, &payload).unwrap();
+    verify_mint(&payload, &commit, std::slice::from_ref(&reveal)).unwrap();
+
+    // A flipped signature bit fails the envelope's OP_CHECKSIG.
+    let mut forged = reveal.clone();
+    let mut items: Vec<Vec<u8>> = forged.input[0].witness.iter().map(<[u8]>::to_vec).collect();
+    items[0][0] ^= 1;
+    forged.input[0].witness = Witness::from_slice(&items);
+    let err = verify_mint(&payload, &commit, &[forged]).unwrap_err();
+    assert!(err.to_string().contains("input 0 of"), "{}", err);
+
+    // A leaf script the output does not commit to.
+    let mut swapped = reveal.clone();
+    items = reveal.input[0].witness.iter().map(<[u8]>::to_vec).collect();
+    items[1].push(0x75);
+    swapped.input[0].witness = Witness::from_slice(&items);
+    let reason = verify_input(
+        &payload.secp,
+        &swapped,
+        0,
+        std::slice::from_ref(&template.commit_output),
+    )
+    .unwrap_err();
+    assert!(reason.contains("does not commit"), "{}", reason);
+
+    // The key-path commit signs the funding amount it spends.
+    let mut prevout = funding_prevout(&payload);
+    prevout.value += Amount::from_sat(1);
+    assert!(verify_tx(&payload.secp, &commit, &[prevout]).is_err());
+    let mut underfunded = payload.clone();
+    underfunded.funding_utxo_value += Amount::from_sat(1);
+    let err = verify_mint(&underfunded, &commit, std::slice::from_ref(&reveal)).unwrap_err();
+    let named = format!(
+        "input 0 of {} (spending {})",
+        commit.txid(),
+        commit.input[0].previous_output
+    );
+    assert!(err.to_string().contains(&named), "{}", err);
+
+    // A signature relabelled SIGHASH_ALL no longer matches its hash.
+    let mut relabelled = reveal.clone();
+    items = reveal.input[0].witness.iter().map(<[u8]>::to_vec).collect();
+    items[0].push(TapSighashType::All as u8);
+    relabelled.input[0].witness = Witness::from_slice(&items);
+    let err = verify_mint(&payload, &commit, &[relabelled.clone()]).unwrap_err();
+    let named = format!("input 0 of {}", relabelled.txid());
+    assert!(err.to_string().contains(&named), "{}", err);
+    assert!(err.to_string().contains("invalid signature"), "{}", err);
+
+    // OP_SUCCESSx leaves would spend without a signature, so are refused.
+    let leaf = ScriptBuf::from_bytes(vec![0x50]);
+    let spend_info = bitcoin::taproot::TaprootBuilder::new()
+        .add_leaf(0, leaf.clone())
+        .unwrap()
+        .finalize(&payload.secp, payload.xonly_pub_key)
+        .unwrap();
+    let control_block = spend_info
+        .control_block(&(leaf.clone(), bitcoin::taproot::LeafVersion::TapScript))
+        .unwrap();
+    let prevout = TxOut {
+        value: Amount::from_sat(1000),
+        script_pubkey: ScriptBuf::new_p2tr_tweaked(spend_info.output_key()),
+    };
+    let mut success = reveal.clone();
+    success.input.truncate(1);
+    success.input[0].witness = Witness::from_slice(&[leaf.to_bytes(), control_block.serialize()]);
+    let reason = verify_input(&payload.secp, &success, 0, &[prevout]).unwrap_err();
+    assert!(reason.contains("succeeds unconditionally"), "{}", reason);
+}
+
+#[test]
+fn test_refund_leaf_recovers_unrevealed_commits() {
+    let secp = bitcoin::secp256k1::Secp256k1::new();
+    let recovery = PrivateKey::new(
+        bitcoin::secp256k1::SecretKey::from_slice(&[2u8; 32]).unwrap(),
+        Network::Bitcoin,
+    );
+    let (recovery_key, _) = bitcoin::XOnlyPublicKey::from_keypair(
+        &bitcoin::key::Keypair::from_secret_key(&secp, &recovery.inner),
+    );
+    let mut root = sample_root();
+    root.worker_options.address =
+        "bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr".to_string();
+    root.worker_options.dmt_options.mint_amount = 1000;
+    let plain = get_payload(root.clone(), Some(1704688101), Some(7588557)).unwrap();
+    root.refund = Some(RefundOptions {
+        key: recovery_key.to_string(),
+        blocks: 144,
+    });
+    let payload = get_payload(root.clone(), Some(1704688101), Some(7588557)).unwrap();
+    assert_ne!(
+        payload.fixed_output_script_pubkey,
+        plain.fixed_output_script_pubkey
+    );
+
+    // The envelope still reveals, with a one-step control block.
+    let commit = build_commit_tx(0, &payload).unwrap();
+    let template = reveal_template(&root, &payload, commit.txid()).unwrap();
+    assert_eq!(template.control_block.merkle_branch.len(), 1);
+    let reveal = build_reveal_tx(&template, 0, &payload).unwrap();
+    verify_mint(&payload, &commit, &[reveal]).unwrap();
+
+    let destination = payload.funding_private_script_pubkey.clone();
+    let by_key = recover(
+        &root,
+        &payload,
+        &commit,
+        0,
+        &RecoveryPath::Key,
+        destination.clone(),
+        2,
+    )
+    .unwrap();
+    assert_eq!(by_key.input[0].witness.len(), 1);
+    assert_eq!(
+        by_key.output[0].value.to_sat(),
+        payload.fixed_output_value.to_sat() - 2 * by_key.vsize() as u64
+    );
+
+    let by_refund = recover(
+        &root,
+        &payload,
+        &commit,
+        0,
+        &RecoveryPath::Refund(recovery),
+        destination.clone(),
+        2,
+    )
+    .unwrap();
+    assert_eq!(
+        by_refund.input[0].sequence,
+        bitcoin::Sequence::from_height(144)
+    );
+    // Spending before the timelock fails the leaf's OP_CSV.
+    let mut early = by_refund.clone();
+    early.input[0].sequence = bitcoin::Sequence::from_height(143);
+    let reason = verify_input(
+        &payload.secp,
+        &early,
+        0,
+        std::slice::from_ref(&commit.output[0]),
+    )
+    .unwrap_err();
+    assert!(reason.contains("OP_CSV"), "{}", reason);
+
+    // Only the recovery key signs the refund leaf, and only jobs with one.
+    let funding = RecoveryPath::Refund(PrivateKey::from_wif(&root.funding_wif).unwrap());
+    assert!(recover(
+        &root,
+        &payload,
+        &commit,
+        0,
+        &funding,
+        destination.clone(),
+        2
+    )
+    .is_err());
+    let plain_commit = build_commit_tx(0, &plain).unwrap();
+    root.refund = None;
+    let refund = RecoveryPath::Refund(recovery);
+    assert!(recover(&root, &plain, &plain_commit, 0, &refund, destination, 2).is_err());
+}
+
+#[test]
+fn test_commit_outputs_export_descriptors_and_control_blocks() {
+    assert_eq!(descriptor_checksum("raw(deadbeef)").unwrap(), "89f8spxm");
+
+    let mut root = sample_root();
+    root.worker_options.address =
+        "bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr".to_string();
+    root.worker_options.dmt_options.mint_amount = 1000;
+    let single = get_payload(root.clone(), Some(1704688101), Some(7588557)).unwrap();
+    let [output] = &commit_outputs(&root, &single).unwrap()[..] else {
+        panic!("expected one commit output");
+    };
+    assert_eq!(output.merkle_root, output.leaves[0].leaf_hash);
+    // Checksums from the BIP-380 reference implementation.
+    assert_eq!(
+        output.descriptor,
+        format!(
+            "tr({},rawleaf({}))#34degr4r",
+            single.xonly_pub_key, output.leaves[0].script
+        )
+    );
+    assert_eq!(
+        output.watch_descriptor,
+        "rawtr(1769c5af3347751423b2797751cbbd335579a0c5d8d5c89773e266d72ff8495c)#5g5l66gp"
+    );
+    assert_eq!(
+        single.fixed_output_script_pubkey,
+        ScriptBuf::from_hex("51201769c5af3347751423b2797751cbbd335579a0c5d8d5c89773e266d72ff8495c")
+            .unwrap()
+    );
+
+    root.fan_out = 2;
+    root.refund = Some(RefundOptions {
+        key: single.xonly_pub_key.to_string(),
+        blocks: 6,
+    });
+    let payload = get_payload(root.clone(), Some(1704688101), Some(7588557)).unwrap();
+    let commit = build_commit_tx(0, &payload).unwrap();
+    let outputs = plan(&root, Some(7588557), Some(1704688101))
+        .unwrap()
+        .commit_outputs;
+    assert_eq!(outputs.len(), 2);
+    for (vout, output) in outputs.iter().enumerate() {
+        assert_eq!(output.vout, vout as u32);
+        let script_pubkey = &commit.output[vout].script_pubkey;
+        let output_key =
+            bitcoin::XOnlyPublicKey::from_slice(&script_pubkey.as_bytes()[2..]).unwrap();
+        assert_eq!(output.leaves.len(), 2);
+        for leaf in &output.leaves {
+            let script = ScriptBuf::from_hex(&leaf.script).unwrap();
+            let control_block =
+                bitcoin::taproot::ControlBlock::decode(&hex::decode(&leaf.control_block).unwrap())
+                    .unwrap();
+            assert!(control_block.verify_taproot_commitment(&payload.secp, output_key, &script));
+        }
+        let (descriptor, checksum) = output.descriptor.split_once('#').unwrap();
+        assert_eq!(descriptor_checksum(descriptor).unwrap(), checksum);
+        assert!(descriptor.contains(&format!(
+            "{{rawleaf({}),rawleaf({})}}",
+            output.leaves[0].script, output.leaves[1].script
+        )));
+    }
+}
@@ -0,0 +1,508 @@
+use std::{collections::BTreeMap, sync::OnceLock};
+
+use anyhow::{anyhow, bail, Result};
+use bitcoin::{
+    hashes::{sha256, Hash, HashEngine},
+    key::{Parity, Secp256k1},
+    opcodes::all::OP_PUSHNUM_1,
+    script::Builder,
+    secp256k1,
+    taproot::{LeafVersion, TapLeafHash, TapNodeHash, TapTweakHash, TAPROOT_LEAF_TAPSCRIPT},
+    ScriptBuf, XOnlyPublicKey,
+};
+
+use crate::{types::CopiedData, utils::get_spend_info_by_copied_data};
+
+/// Largest push in the envelope; the CBOR payload must fit in one.
+const MAX_PUSH: usize = 520;
+
+/// A nonce takes at most this many more CBOR bytes than nonce 0.
+const MAX_NONCE_GROWTH: usize = 8;
+
+/// Derives the commit output script of one envelope for many nonces. Only
+/// the CBOR nonce changes between them, so the leaf script around it, the
+/// tag midstates and the internal key are prepared once, and the tweaked
+/// keys of a batch share a single field inversion.
+///
+/// The arithmetic is not constant time, which is fine for tweaks derived
+/// from public data.
+#[derive(Clone)]
+pub struct NonceRoller {
+    point: Affine,
+    leaf_engine: sha256::HashEngine,
+    tweak_engine: sha256::HashEngine,
+    script_head: Vec<u8>,
+    cbor_head: Vec<u8>,
+    cbor_tail: Vec<u8>,
+    /// Sibling of the envelope leaf when the tree has a refund leaf.
+    refund: Option<TapNodeHash>,
+}
+
+impl NonceRoller {
+    pub fn new(
+        secp: &Secp256k1<secp256k1::All>,
+        xonly_public_key: &XOnlyPublicKey,
+        copied_data: &CopiedData,
+        op_type: &String,
+        refund_leaf: Option<&ScriptBuf>,
+    ) -> Result<Self> {
+        let with_nonce = |nonce| {
+            let mut data = copied_data.clone();
+            data.args.nonce = nonce;
+            data
+        };
+        // Nonces 0 and 1 both encode to one byte, at the nonce's offset.
+        let zero = with_nonce(0).encode();
+        let offset = zero
+            .iter()
+            .zip(with_nonce(1).encode())
+            .position(|(a, b)| *a != b)
+            .ok_or_else(|| anyhow!("payload does not encode its nonce"))?;
+        if zero.len() + MAX_NONCE_GROWTH > MAX_PUSH {
+            bail!("payload of {} bytes does not fit in one push", zero.len());
+        }
+
+        // The leaf script ends with the payload push and OP_ENDIF.
+        let (_, script) = get_spend_info_by_copied_data(
+            secp,
+            xonly_public_key,
+            &with_nonce(0),
+            op_type,
+            refund_leaf,
+        );
+        let tail = [push_header(zero.len()), zero.clone(), vec![0x68]].concat();
+        let script = script.as_bytes();
+        if !script.ends_with(&tail) {
+            bail!("leaf script does not end with the payload push");
+        }
+
+        let uncompressed = xonly_public_key
+            .public_key(Parity::Even)
+            .serialize_uncompressed();
+        let mut leaf_engine = TapLeafHash::engine();
+        leaf_engine.input(&[TAPROOT_LEAF_TAPSCRIPT]);
+        let mut tweak_engine = TapTweakHash::engine();
+        tweak_engine.input(&xonly_public_key.serialize());
+        Ok(NonceRoller {
+            point: Affine {
+                x: Fe::from_bytes(uncompressed[1..33].try_into()?),
+                y: Fe::from_bytes(uncompressed[33..].try_into()?),
+            },
+            leaf_engine,
+            tweak_engine,
+            script_head: script[..script.len() - tail.len()].to_vec(),
+            cbor_head: zero[..offset].to_vec(),
+            cbor_tail: zero[offset + 1..].to_vec(),
+            refund: refund_leaf
+                .map(|leaf| TapLeafHash::from_script(leaf, LeafVersion::TapScript).into()),
+        })
+    }
+
+    /// The commit output script for each of `nonces`.
+    pub fn scripts(&self, nonces: &[u64]) -> Result<Vec<ScriptBuf>> {
+        let mut points = Vec::with_capacity(nonces.len());
+        for &nonce in nonces {
+            let tweak = self.tweak(nonce);
+            if tweak >= ORDER {
+                bail!("taproot tweak for nonce {} is out of range", nonce);
+            }
+            points.push(mul_g(&tweak).add_affine(&self.point));
+        }
+        let keys = normalize(&points).ok_or_else(|| anyhow!("tweaked key is infinity"))?;
+        Ok(keys
+            .iter()
+            .map(|key| {
+                Builder::new()
+                    .push_opcode(OP_PUSHNUM_1)
+                    .push_slice(key.x.to_bytes())
+                    .into_script()
+            })
+            .collect())
+    }
+
+    /// The taproot tweak of the envelope's tree for `nonce`.
+    fn tweak(&self, nonce: u64) -> [u8; 32] {
+        let mut cbor = Vec::with_capacity(self.cbor_head.len() + 9 + self.cbor_tail.len());
+        cbor.extend_from_slice(&self.cbor_head);
+        encode_uint(&mut cbor, nonce);
+        cbor.extend_from_slice(&self.cbor_tail);
+        let push = push_header(cbor.len());
+
+        let mut leaf = self.leaf_engine.clone();
+        let len = self.script_head.len() + push.len() + cbor.len() + 1;
+        if len < 0xfd {
+            leaf.input(&[len as u8]);
+        } else {
+            leaf.input(&[0xfd]);
+            leaf.input(&(len as u16).to_le_bytes());
+        }
+        leaf.input(&self.script_head);
+        leaf.input(&push);
+        leaf.input(&cbor);
+        leaf.input(&[0x68]);
+        let leaf = TapLeafHash::from_engine(leaf);
+
+        // A single leaf is its own merkle root; a refund leaf is its sibling.
+        let root = match self.refund {
+            Some(refund) => TapNodeHash::from_node_hashes(leaf.into(), refund),
+            None => leaf.into(),
+        };
+        let mut tweak = self.tweak_engine.clone();
+        tweak.input(root.as_byte_array());
+        TapTweakHash::from_engine(tweak).to_byte_array()
+    }
+}
+
+/// Commit output scripts for each `(time, nonce)` pair. Pairs sharing a time
+/// are derived in one batch.
+pub fn commit_scripts(
+    secp: &Secp256k1<secp256k1::All>,
+    xonly_public_key: &XOnlyPublicKey,
+    copied_data: &CopiedData,
+    op_type: &String,
+    refund_leaf: Option<&ScriptBuf>,
+    pairs: &[(u64, u64)],
+) -> Result<Vec<ScriptBuf>> {
+    let mut by_time: BTreeMap<u64, Vec<usize>> = BTreeMap::new();
+    for (i, (time, _)) in pairs.iter().enumerate() {
+        by_time.entry(*time).or_default().push(i);
+    }
+    let mut scripts = vec![ScriptBuf::new(); pairs.len()];
+    for (time, indices) in by_time {
+        let mut data = copied_data.clone();
+        data.args.time = time;
+        let roller = NonceRoller::new(secp, xonly_public_key, &data, op_type, refund_leaf)?;
+        let nonces: Vec<u64> = indices.iter().map(|&i| pairs[i].1).collect();
+        for (i, script) in indices.into_iter().zip(roller.scripts(&nonces)?) {
+            scripts[i] = script;
+        }
+    }
+    Ok(scripts)
+}
+
+/// Opcode and length bytes of a push of `len` bytes, up to [`MAX_PUSH`].
+fn push_header(len: usize) -> Vec<u8> {
+    match len {
+        0..=75 => vec![len as u8],
+        76..=0xff => vec![0x4c, len as u8],
+        _ => [&[0x4d][..], &(len as u16).to_le_bytes()].concat(),
+    }
+}
+
+/// Canonical CBOR encoding of an unsigned integer, as minicbor writes it.
+fn encode_uint(out: &mut Vec<u8>, n: u64) {
+    match n {
+        0..=23 => out.push(n as u8),
+        24..=0xff => out.extend_from_slice(&[0x18, n as u8]),
+        0x100..=0xffff => {
+            out.push(0x19);
+            out.extend_from_slice(&(n as u16).to_be_bytes());
+        }
+        0x1_0000..=0xffff_ffff => {
+            out.push(0x1a);
+            out.extend_from_slice(&(n as u32).to_be_bytes());
+        }
+        _ => {
+            out.push(0x1b);
+            out.extend_from_slice(&n.to_be_bytes());
+        }
+    }
+}
+
+/// The secp256k1 group order, big-endian.
+const ORDER: [u8; 32] = [
+    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xfe,
+    0xba, 0xae, 0xdc, 0xe6, 0xaf, 0x48, 0xa0, 0x3b, 0xbf, 0xd2, 0x5e, 0x8c, 0xd0, 0x36, 0x41, 0x41,
+];
+
+/// The field prime `2^256 - 2^32 - 977`, in little-endian limbs.
+const P: [u64; 4] = [
+    0xfffffffefffffc2f,
+    0xffffffffffffffff,
+    0xffffffffffffffff,
+    0xffffffffffffffff,
+];
+
+/// `2^256 mod p`.
+const R: u64 = 0x1000003d1;
+
+const G: Affine = Affine {
+    x: Fe([
+        0x59f2815b16f81798,
+        0x029bfcdb2dce28d9,
+        0x55a06295ce870b07,
+        0x79be667ef9dcbbac,
+    ]),
+    y: Fe([
+        0x9c47d08ffb10d4b8,
+        0xfd17b448a6855419,
+        0x5da4fbfc0e1108a8,
+        0x483ada7726a3c465,
+    ]),
+};
+
+/// A field element, fully reduced, in little-endian limbs.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct Fe([u64; 4]);
+
+impl Fe {
+    const ONE: Fe = Fe([1, 0, 0, 0]);
+
+    /// `bytes` must be below `p`, as coordinates from libsecp256k1 are.
+    fn from_bytes(bytes: &[u8; 32]) -> Fe {
+        Fe(std::array::from_fn(|i| {
+            u64::from_be_bytes(bytes[24 - 8 * i..32 - 8 * i].try_into().unwrap())
+        }))
+    }
+
+    fn to_bytes(self) -> [u8; 32] {
+        let mut bytes = [0; 32];
+        for (i, limb) in self.0.iter().enumerate() {
+            bytes[24 - 8 * i..32 - 8 * i].copy_from_slice(&limb.to_be_bytes());
+        }
+        bytes
+    }
+
+    fn is_zero(self) -> bool {
+        self.0 == [0; 4]
+    }
+
+    fn add(self, other: Fe) -> Fe {
+        let mut r = [0; 4];
+        let mut carry = 0u128;
+        for (i, limb) in r.iter_mut().enumerate() {
+            let v = self.0[i] as u128 + other.0[i] as u128 + carry;
+            *limb = v as u64;
+            carry = v >> 64;
+        }
+        if carry != 0 {
+            add_small(&mut r, R as u128);
+        }
+        Fe(r).reduce_once()
+    }
+
+    fn sub(self, other: Fe) -> Fe {
+        let (r, borrow) = sub_limbs(self.0, other.0);
+        // On a borrow `r` is the difference plus 2^256, which exceeds `R`.
+        if borrow {
+            Fe(sub_limbs(r, [R, 0, 0, 0]).0)
+        } else {
+            Fe(r)
+        }
+    }
+
+    fn double(self) -> Fe {
+        self.add(self)
+    }
+
+    fn mul(self, other: Fe) -> Fe {
+        let mut t = [0u64; 8];
+        for i in 0..4 {
+            let mut carry = 0u128;
+            for j in 0..4 {
+                let v = t[i + j] as u128 + self.0[i] as u128 * other.0[j] as u128 + carry;
+                t[i + j] = v as u64;
+                carry = v >> 64;
+            }
+            t[i + 4] = carry as u64;
+        }
+        // Fold the high half back in, since 2^256 = R (mod p).
+        let mut r = [0; 4];
+        let mut carry = 0u128;
+        for (i, limb) in r.iter_mut().enumerate() {
+            let v = t[i] as u128 + t[i + 4] as u128 * R as u128 + carry;
+            *limb = v as u64;
+            carry = v >> 64;
+        }
+        if add_small(&mut r, carry * R as u128) != 0 {
+            add_small(&mut r, R as u128);
+        }
+        Fe(r).reduce_once()
+    }
+
+    fn square(self) -> Fe {
+        self.mul(self)
+    }
+
+    /// `self^(p - 2)`; zero maps to zero.
+    fn inverse(self) -> Fe {
+        let exponent = sub_limbs(P, [2, 0, 0, 0]).0;
+        let mut r = Fe::ONE;
+        for bit in (0..256).rev() {
+            r = r.square();
+            if exponent[bit / 64] >> (bit % 64) & 1 == 1 {
+                r = r.mul(self);
+            }
+        }
+        r
+    }
+
+    fn reduce_once(self) -> Fe {
+        match sub_limbs(self.0, P) {
+            (r, false) => Fe(r),
+            (_, true) => self,
+        }
+    }
+}
+
+/// Adds `n` into `r`, returning the carry out of the top limb.
+fn add_small(r: &mut [u64; 4], mut n: u128) -> u128 {
+    for limb in r.iter_mut() {
+        let v = *limb as u128 + n;
+        *limb = v as u64;
+        n = v >> 64;
+    }
+    n
+}
+
+fn sub_limbs(a: [u64; 4], b: [u64; 4]) -> ([u64; 4], bool) {
+    let mut r = [0; 4];
+    let mut borrow = false;
+    for i in 0..4 {
+        let (v, b1) = a[i].overflowing_sub(b[i]);
+        let (v, b2) = v.overflowing_sub(borrow as u64);
+        r[i] = v;
+        borrow = b1 || b2;
+    }
+    (r, borrow)
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct Affine {
+    x: Fe,
+    y: Fe,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Jacobian {
+    x: Fe,
+    y: Fe,
+    z: Fe,
+    infinity: bool,
+}
+
+impl Jacobian {
+    const INFINITY: Jacobian = Jacobian {
+        x: Fe::ONE,
+        y: Fe::ONE,
+        z: Fe([0; 4]),
+        infinity: true,
+    };
+
+    fn from_affine(point: &Affine) -> Jacobian {
+        Jacobian {
+            x: point.x,
+            y: point.y,
+            z: Fe::ONE,
+            infinity: false,
+        }
+    }
+
+    fn double(&self) -> Jacobian {
+        if self.infinity || self.y.is_zero() {
+            return Jacobian::INFINITY;
+        }
+        let a = self.x.square();
+        let b = self.y.square();
+        let c = b.square();
+        let d = self.x.add(b).square().sub(a).sub(c).double();
+        let e = a.double().add(a);
+        let x = e.square().sub(d.double());
+        let y = e.mul(d.sub(x)).sub(c.double().double().double());
+        let z = self.y.mul(self.z).double();
+        Jacobian {
+            x,
+            y,
+            z,
+            infinity: false,
+        }
+    }
+
+    fn add_affine(&self, other: &Affine) -> Jacobian {
+        if self.infinity {
+            return Jacobian::from_affine(other);
+        }
+        let z2 = self.z.square();
+        let h = other.x.mul(z2).sub(self.x);
+        let r = other.y.mul(z2.mul(self.z)).sub(self.y);
+        if h.is_zero() {
+            return if r.is_zero() {
+                self.double()
+            } else {
+                Jacobian::INFINITY
+            };
+        }
+        let h2 = h.square();
+        let h3 = h2.mul(h);
+        let v = self.x.mul(h2);
+        let x = r.square().sub(h3).sub(v.double());
+        let y = r.mul(v.sub(x)).sub(self.y.mul(h3));
+        Jacobian {
+            x,
+            y,
+            z: self.z.mul(h),
+            infinity: false,
+        }
+    }
+}
+
+/// Converts `points` to affine with one inversion (Montgomery's trick), or
+/// `None` if any is infinity.
+fn normalize(points: &[Jacobian]) -> Option<Vec<Affine>> {
+    if points.iter().any(|point| point.infinity) {
+        return None;
+    }
+    let mut prefixes = Vec::with_capacity(points.len());
+    let mut product = Fe::ONE;
+    for point in points {
+        prefixes.push(product);
+        product = product.mul(point.z);
+    }
+    let mut inverse = product.inverse();
+    let mut affine = vec![G; points.len()];
+    for i in (0..points.len()).rev() {
+        let z_inv = inverse.mul(prefixes[i]);
+        inverse = inverse.mul(points[i].z);
+        let z_inv2 = z_inv.square();
+        affine[i] = Affine {
+            x: points[i].x.mul(z_inv2),
+            y: points[i].y.mul(z_inv2.mul(z_inv)),
+        };
+    }
+    Some(affine)
+}
+
+/// `table()[i][j - 1]` is `j * 256^i * G`, so multiplying `G` takes one
+/// addition per nonzero byte of the scalar.
+fn table() -> &'static [[Affine; 255]] {
+    static TABLE: OnceLock<Vec<[Affine; 255]>> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let mut table = Vec::with_capacity(32);
+        let mut base = G;
+        for _ in 0..32 {
+            let mut multiples = Vec::with_capacity(256);
+            let mut point = Jacobian::from_affine(&base);
+            multiples.push(point);
+            for _ in 1..256 {
+                point = point.add_affine(&base);
+                multiples.push(point);
+            }
+            let multiples = normalize(&multiples).expect("multiples of G below its order");
+            table.push(std::array::from_fn(|j| multiples[j]));
+            base = multiples[255];
+        }
+        table
+    })
+}
+
+/// `scalar * G` for a big-endian scalar.
+fn mul_g(scalar: &[u8; 32]) -> Jacobian {
+    let table = table();
+    let mut point = Jacobian::INFINITY;
+    for (i, byte) in scalar.iter().rev().enumerate() {
+        if *byte != 0 {
+            point = point.add_affine(&table[i][*byte as usize - 1]);
+        }
+    }
+    point
+}
@@ -1,5 +1,7 @@
+use std::str::FromStr;
+
 use bitcoin::{key::Secp256k1, secp256k1, Amount, PrivateKey, ScriptBuf, Txid, XOnlyPublicKey};
-use minicbor::{data::Int, Encoder};
+use minicbor::{data::Int, Decoder, Encoder};
 use serde::{Deserialize, Serialize};
 use serde_repr::{Deserialize_repr, Serialize_repr};
 
@@ -17,9 +19,17 @@ pub struct Payload {
     pub funding_value: Amount,
     pub fixed_output_script_pubkey: ScriptBuf,
     pub fixed_output_value: Amount,
+    /// Further commit outputs of a fan-out commit, in order, each worth
+    /// `fixed_output_value`.
+    pub fan_out_script_pubkeys: Vec<ScriptBuf>,
+    /// Refund leaf in every commit output's tree besides the envelope.
+    pub refund_leaf: Option<ScriptBuf>,
     pub need_change_fee_output: bool,
     pub valid_prefix: Option<String>,
     pub valid_ext: Option<u8>,
+    pub grind: GrindOptions,
+    /// The commit fields that `build_commit_tx` uses besides `nSequence`.
+    pub variant: Variant,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -36,6 +46,153 @@ pub struct Root {
     pub concurrency: u32,
     #[serde(default)]
     pub network: Network,
+    #[serde(default)]
+    pub resume: Option<Checkpoint>,
+    /// Search backend by name; auto-detected when unset.
+    #[serde(default)]
+    pub search_backend: Option<String>,
+    #[serde(default)]
+    pub grind: GrindOptions,
+    /// Pool mode: workers also submit txids meeting this lower bitwork as
+    /// shares, credited to whoever found them.
+    #[serde(default)]
+    pub share_bitwork: Option<String>,
+    /// Audit the run's hash distribution through near misses.
+    #[serde(default)]
+    pub near_misses: Option<NearMissOptions>,
+    /// Commit outputs in one commit, each with its own envelope at the next
+    /// nonce and revealed on its own. Zero or one for a single output.
+    #[serde(default)]
+    pub fan_out: u32,
+    #[serde(default)]
+    pub sequence_policy: SequencePolicy,
+    /// Emit transactions that default relay policy rejects.
+    #[serde(default)]
+    pub allow_non_standard: bool,
+    /// Current block height. Transactions locked past it are not final and
+    /// are not emitted.
+    #[serde(default)]
+    pub block_height: Option<u32>,
+    /// Add a timelocked refund leaf beside the envelope in every commit
+    /// output, spendable by `refund.key` if the reveal is never sent.
+    #[serde(default)]
+    pub refund: Option<RefundOptions>,
+}
+
+/// A second commit leaf, `<blocks> OP_CSV OP_DROP <key> OP_CHECKSIG`. It
+/// lengthens the reveal's control block by 32 bytes, which
+/// `fees.revealFeePlusOutputs` has to cover.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RefundOptions {
+    /// Hex x-only public key of the recovery key.
+    pub key: String,
+    /// Blocks the commit must be buried before the refund leaf is usable.
+    pub blocks: u16,
+}
+
+/// Which `nSequence` values commits and reveals may use, since the winning
+/// value also decides replaceability and, from version 2, a relative lock.
+#[derive(Default, Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SequencePolicy {
+    #[serde(default)]
+    pub rbf: RbfPolicy,
+    /// Keep the BIP-68 disable flag set so no sequence is a relative lock.
+    #[serde(default)]
+    pub no_relative_lock_time: bool,
+}
+
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RbfPolicy {
+    #[default]
+    Any,
+    /// Only sequences that signal BIP-125 replaceability.
+    Signal,
+    /// Only final sequences, which do not signal. There are two per variant,
+    /// so jobs are refused unless `grind` widens the search.
+    Final,
+}
+
+impl FromStr for RbfPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "any" => Ok(RbfPolicy::Any),
+            "signal" => Ok(RbfPolicy::Signal),
+            "final" => Ok(RbfPolicy::Final),
+            other => Err(format!("unknown rbf policy `{}`", other)),
+        }
+    }
+}
+
+/// Counting of txids that meet shorter prefixes of the commit bitwork, whose
+/// expected frequencies are known, so a broken hash path shows early.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NearMissOptions {
+    /// Levels counted, the first one nibble short of the target prefix and
+    /// each further one a nibble shorter.
+    #[serde(default = "default_near_miss_levels")]
+    pub levels: u8,
+    /// JSON-lines file each near miss is appended to.
+    #[serde(default)]
+    pub journal: Option<String>,
+}
+
+impl Default for NearMissOptions {
+    fn default() -> Self {
+        NearMissOptions {
+            levels: default_near_miss_levels(),
+            journal: None,
+        }
+    }
+}
+
+fn default_near_miss_levels() -> u8 {
+    2
+}
+
+/// Commit fields varied alongside `nSequence`. Changing them needs no
+/// elliptic-curve work, so every value multiplies the candidates per nonce.
+/// Their product is capped at [`crate::miner::MAX_VARIANTS`].
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GrindOptions {
+    /// Lock times `0..=maxLockTime` are tried. Keep it at or below the
+    /// current block height, or the commit is not final; with `blockHeight`
+    /// set, it is capped there.
+    #[serde(default)]
+    pub max_lock_time: u32,
+    /// Transaction versions to try, 1 or 2; only version 1 when empty.
+    /// Grinding version 2 keeps every searched sequence off relative locks,
+    /// unless `allowNonStandard` is set.
+    #[serde(default)]
+    pub versions: Vec<i32>,
+    /// Sats the change output may give up to fees, one at a time.
+    #[serde(default)]
+    pub change_tolerance: u64,
+}
+
+/// One combination of the [`GrindOptions`] dimensions.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Variant {
+    pub version: i32,
+    pub lock_time: u32,
+    pub change_reduction: u64,
+}
+
+impl Default for Variant {
+    fn default() -> Self {
+        Variant {
+            version: 1,
+            lock_time: 0,
+            change_reduction: 0,
+        }
+    }
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize_repr, Deserialize_repr)]
@@ -71,16 +228,44 @@ pub struct WorkerOptions {
     pub address: String,
     pub op_type: String,
     pub dmt_options: DmtOptions,
+    #[serde(default)]
+    pub bitcoind_rpc: Option<BitcoindRpc>,
+    #[serde(default)]
+    pub esplora_url: Option<String>,
+    /// When set, `satsbyte` is replaced by the backend's fee estimate for
+    /// confirmation within this many blocks.
+    #[serde(default)]
+    pub fee_target_blocks: Option<u16>,
 }
 
+/// The atomicals-electrumx HTTP proxy.
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ElectrumApi {
     pub base_url: String,
     pub use_post: bool,
+    /// The open state of the atomicals-js client's socket, which job files
+    /// written by it carry. Requests here are stateless HTTP, so it is
+    /// ignored.
+    #[deprecated(note = "ignored; requests are stateless HTTP")]
+    #[serde(default)]
     pub is_open_flag: bool,
 }
 
+/// Connection settings for a Bitcoin Core JSON-RPC endpoint. Authenticates
+/// with `cookie_file` when set, otherwise with `user` and `password`.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BitcoindRpc {
+    pub url: String,
+    #[serde(default)]
+    pub cookie_file: Option<String>,
+    #[serde(default)]
+    pub user: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+}
+
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct DmtOptions {
@@ -120,6 +305,199 @@ pub struct WorkerBitworkInfoCommit {
     pub ext: Option<u8>,
 }
 
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Success {
+    pub sequence: u64,
+    pub nonce: u64,
+    pub time: u64,
+    pub magic: String,
+    /// The resolved fee rate, reported when it came from `fee_target_blocks`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub satsbyte: Option<u64>,
+    /// Reported when the hit is not the plain version 1, lock time 0 commit.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub variant: Option<Variant>,
+}
+
+/// Result of a mempool acceptance test for one transaction.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MempoolAcceptance {
+    pub txid: String,
+    pub allowed: bool,
+    pub reject_reason: Option<String>,
+}
+
+/// What the `plan` command reports before any mining happens.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Plan {
+    pub commit_address: String,
+    pub nonce: u64,
+    pub time: u64,
+    pub commit_output_value: u64,
+    pub required_funding: u64,
+    pub required_funding_with_change: u64,
+    pub funding_value: u64,
+    pub change_output: bool,
+    /// Addresses of the further outputs of a fan-out commit.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fan_out_addresses: Vec<String>,
+    pub commit_outputs: Vec<CommitOutput>,
+    /// Candidate commits per nonce: allowed sequences times variants.
+    pub search_space: u64,
+}
+
+/// How to watch and spend one envelope output of a commit.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CommitOutput {
+    pub vout: u32,
+    pub address: String,
+    /// `tr()` descriptor with its checksum. Informational only: its leaves
+    /// are `rawleaf()`, which wallets cannot import.
+    pub descriptor: String,
+    /// `rawtr()` descriptor of the output key with its checksum, which
+    /// Bitcoin Core imports to watch the output.
+    pub watch_descriptor: String,
+    pub internal_key: String,
+    pub merkle_root: String,
+    /// The envelope leaf, then the refund leaf if there is one.
+    pub leaves: Vec<CommitLeaf>,
+}
+
+/// A tapscript leaf of a commit output, with the control block that spends
+/// it. Scripts and control blocks are hex.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CommitLeaf {
+    pub script: String,
+    pub leaf_hash: String,
+    pub control_block: String,
+}
+
+/// An inclusive range of `nSequence` values.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct SequenceRange {
+    pub start: u32,
+    pub end: u32,
+}
+
+/// The slice of the search space owned by one worker, and how much of it has
+/// already been mined. The nonce and time pin the commit address.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WorkerCheckpoint {
+    pub nonce: u64,
+    pub time: u64,
+    pub start: u32,
+    pub end: u32,
+    pub completed: Vec<SequenceRange>,
+}
+
+/// Enough state to restart an interrupted run without re-mining covered ranges.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub workers: Vec<WorkerCheckpoint>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StopReason {
+    Cancelled,
+    Exhausted,
+    /// Near-miss counts strayed too far from their expectation, which points
+    /// at a broken hash path.
+    Implausible,
+}
+
+/// Emitted instead of [`Success`] when a run ends without a solution.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StopReport {
+    pub status: StopReason,
+    pub attempts: u64,
+    pub ranges: Vec<SequenceRange>,
+    pub checkpoint: Checkpoint,
+    pub magic: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub near_misses: Option<Vec<NearMissLevel>>,
+}
+
+/// Txids seen meeting one near-miss prefix, against the count expected from
+/// the candidates searched.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NearMissLevel {
+    pub prefix: String,
+    pub observed: u64,
+    pub expected: f64,
+    /// A bound on the chance of a count at least this far off.
+    pub probability: f64,
+}
+
+/// One near miss, as written to the journal.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NearMissRecord {
+    pub nonce: u64,
+    pub time: u64,
+    pub sequence: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub variant: Option<Variant>,
+    pub txid: String,
+    /// The longest near-miss prefix the txid meets.
+    pub prefix: String,
+}
+
+/// One link of a chained batch, in broadcast order: commit, then reveal.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChainedMint {
+    pub result: Success,
+    pub commit_txid: String,
+    pub commit_tx: String,
+    pub reveal_txid: String,
+    pub reveal_tx: String,
+    pub commit_outputs: Vec<CommitOutput>,
+}
+
+/// The mints of a chained batch, with the report of the run that ended it
+/// early, if any, and the last commit's change for a further batch.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchReport {
+    pub mints: Vec<ChainedMint>,
+    pub stopped: Option<StopReport>,
+    pub next_funding: Option<FundingUtxo>,
+}
+
+/// A replacement for a mined commit that pays a higher fee from its change.
+/// `result` rebuilds it, and its reveal, like any other mining result.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Bump {
+    pub result: Success,
+    pub commit_txid: String,
+    pub commit_tx: String,
+    pub fee: u64,
+    pub satsbyte: f64,
+    pub replaced_txid: String,
+    pub replaced_fee: u64,
+}
+
+/// Search throughput at one thread count. `speedup` is measured against the
+/// per-thread rate of the first run, normally a single thread, and
+/// `efficiency` is the speedup divided by the thread count.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BenchmarkRun {
+    pub threads: usize,
+    pub candidates: u64,
+    pub seconds: f64,
+    pub hashes_per_second: f64,
+    pub speedup: f64,
+    pub efficiency: f64,
+}
+
 impl CopiedData {
     pub fn encode(&self) -> Vec<u8> {
         let buf = vec![];
@@ -169,6 +547,52 @@ impl CopiedData {
     }
 }
 
+impl CopiedData {
+    /// Inverse of [`CopiedData::encode`]. Unknown argument keys are skipped.
+    pub fn decode(bytes: &[u8]) -> anyhow::Result<Self> {
+        let mut decoder = Decoder::new(bytes);
+        let mut args = None;
+        let entries = decoder
+            .map()?
+            .ok_or_else(|| anyhow::anyhow!("indefinite maps are not supported"))?;
+        for _ in 0..entries {
+            if decoder.str()? != "args" {
+                decoder.skip()?;
+                continue;
+            }
+            args = Some(Args::decode(&mut decoder)?);
+        }
+        Ok(CopiedData {
+            args: args.ok_or_else(|| anyhow::anyhow!("payload has no `args` map"))?,
+        })
+    }
+}
+
+impl Args {
+    fn decode(decoder: &mut Decoder) -> anyhow::Result<Self> {
+        let mut args = Args::default();
+        let (mut time, mut nonce, mut mint_ticker) = (None, None, None);
+        let entries = decoder
+            .map()?
+            .ok_or_else(|| anyhow::anyhow!("indefinite maps are not supported"))?;
+        for _ in 0..entries {
+            match decoder.str()? {
+                "time" => time = Some(u64::try_from(decoder.int()?)?),
+                "nonce" => nonce = Some(u64::try_from(decoder.int()?)?),
+                "bitworkc" => args.bitworkc = Some(decoder.str()?.to_string()),
+                "bitworkr" => args.bitworkr = Some(decoder.str()?.to_string()),
+                "mint_ticker" => mint_ticker = Some(decoder.str()?.to_string()),
+                _ => decoder.skip()?,
+            }
+        }
+        args.time = time.ok_or_else(|| anyhow::anyhow!("args has no `time`"))?;
+        args.nonce = nonce.ok_or_else(|| anyhow::anyhow!("args has no `nonce`"))?;
+        args.mint_ticker =
+            mint_ticker.ok_or_else(|| anyhow::anyhow!("args has no `mint_ticker`"))?;
+        Ok(args)
+    }
+}
+
 impl From<Network> for bitcoin::Network {
     fn from(network: Network) -> Self {
         match network {
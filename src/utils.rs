@@ -3,13 +3,13 @@ use bitcoin::{
     key::{Keypair, Secp256k1, TapTweak},
     opcodes,
     psbt::Input,
-    script::{Builder, PushBytesBuf},
+    script::{Builder, Instruction, PushBytesBuf},
     secp256k1, taproot,
-    taproot::TaprootBuilder,
-    Address, Network, ScriptBuf, TapLeafHash, TapSighash, TapSighashType, XOnlyPublicKey,
+    taproot::{TaprootBuilder, TaprootSpendInfo},
+    Address, Network, Script, ScriptBuf, TapLeafHash, TapSighash, TapSighashType, XOnlyPublicKey,
 };
 
-use crate::types::{CopiedData, Fees};
+use crate::types::{CopiedData, Fees, RefundOptions};
 
 pub(crate) fn sign_psbt_taproot(
     secret_key: &secp256k1::SecretKey,
@@ -74,15 +74,131 @@ pub(crate) fn get_address_by_copied_data(
     xonly_public_key: &XOnlyPublicKey,
     copied_data: &CopiedData,
     op_type: &String,
+    refund_leaf: Option<&ScriptBuf>,
+    network: Network,
 ) -> (String, ScriptBuf) {
+    let (spend_info, _script) =
+        get_spend_info_by_copied_data(secp, xonly_public_key, copied_data, op_type, refund_leaf);
+    let _str = append_mint_update_reveal_script(xonly_public_key, copied_data);
+    let addr = Address::p2tr_tweaked(spend_info.output_key(), network);
+    (addr.to_string(), addr.script_pubkey())
+}
+
+/// Returns the taproot spend info of the commit output and its envelope leaf.
+/// With a refund leaf, the two leaves are siblings under the root.
+pub fn get_spend_info_by_copied_data(
+    secp: &Secp256k1<secp256k1::All>,
+    xonly_public_key: &XOnlyPublicKey,
+    copied_data: &CopiedData,
+    op_type: &String,
+    refund_leaf: Option<&ScriptBuf>,
+) -> (TaprootSpendInfo, ScriptBuf) {
     let script =
         append_mint_update_reveal_script_by_builder(xonly_public_key, copied_data, op_type);
-    let _str = append_mint_update_reveal_script(xonly_public_key, copied_data);
     let taproot_builder = TaprootBuilder::new();
-    let resp = taproot_builder.add_leaf(0, script).unwrap();
+    let resp = match refund_leaf {
+        Some(refund) => taproot_builder
+            .add_leaf(1, script.clone())
+            .unwrap()
+            .add_leaf(1, refund.clone())
+            .unwrap(),
+        None => taproot_builder.add_leaf(0, script.clone()).unwrap(),
+    };
     let spend_info = resp.finalize(secp, *xonly_public_key).unwrap();
-    let addr = Address::p2tr_tweaked(spend_info.output_key(), Network::Bitcoin);
-    (addr.to_string(), addr.script_pubkey())
+    (spend_info, script)
+}
+
+/// The refund leaf script, `<blocks> OP_CSV OP_DROP <key> OP_CHECKSIG`.
+pub fn refund_leaf(options: &RefundOptions) -> anyhow::Result<ScriptBuf> {
+    if options.blocks == 0 {
+        anyhow::bail!("a refund leaf needs a timelock of at least one block");
+    }
+    let key: XOnlyPublicKey = options.key.parse()?;
+    Ok(Builder::new()
+        .push_int(options.blocks as i64)
+        .push_opcode(opcodes::all::OP_CSV)
+        .push_opcode(opcodes::all::OP_DROP)
+        .push_x_only_key(&key)
+        .push_opcode(opcodes::all::OP_CHECKSIG)
+        .into_script())
+}
+
+/// Splits a bitwork string such as `"0000.5"` into its prefix and extension.
+pub fn parse_bitwork(bitwork: &str) -> anyhow::Result<(String, Option<u8>)> {
+    let (prefix, ext) = match bitwork.split_once('.') {
+        Some((prefix, ext)) => (prefix, Some(ext.parse::<u8>()?)),
+        None => (bitwork, None),
+    };
+    if !prefix
+        .chars()
+        .all(|c| c.is_ascii_hexdigit() && !c.is_ascii_uppercase())
+    {
+        anyhow::bail!("bitwork prefix `{}` is not lowercase hex", prefix);
+    }
+    if matches!(ext, Some(ext) if ext > 15) {
+        anyhow::bail!("bitwork extension in `{}` must be 0-15", bitwork);
+    }
+    Ok((prefix.to_string(), ext))
+}
+
+/// The parts of an atomicals envelope leaf.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Envelope {
+    pub xonly_public_key: XOnlyPublicKey,
+    pub op_type: String,
+    pub copied_data: CopiedData,
+}
+
+/// Parses a script produced by `append_mint_update_reveal_script_by_builder`.
+pub fn decode_envelope(script: &Script) -> anyhow::Result<Envelope> {
+    let (xonly_public_key, op_type, cbor) = decode_envelope_payload(script)?;
+    Ok(Envelope {
+        xonly_public_key,
+        op_type,
+        copied_data: CopiedData::decode(&cbor)?,
+    })
+}
+
+/// Like [`decode_envelope`], but leaves the CBOR payload undecoded.
+pub fn decode_envelope_payload(
+    script: &Script,
+) -> anyhow::Result<(XOnlyPublicKey, String, Vec<u8>)> {
+    let mut instructions = script.instructions();
+    let mut next = || -> anyhow::Result<Instruction> {
+        instructions
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("envelope script ended early"))?
+            .map_err(Into::into)
+    };
+    let xonly_public_key = match next()? {
+        Instruction::PushBytes(bytes) => XOnlyPublicKey::from_slice(bytes.as_bytes())?,
+        other => anyhow::bail!("expected a public key push, found {:?}", other),
+    };
+    for expected in [
+        opcodes::all::OP_CHECKSIG,
+        opcodes::all::OP_PUSHBYTES_0,
+        opcodes::all::OP_IF,
+    ] {
+        match next()? {
+            Instruction::Op(op) if op == expected => {}
+            Instruction::PushBytes(bytes)
+                if expected == opcodes::all::OP_PUSHBYTES_0 && bytes.is_empty() => {}
+            other => anyhow::bail!("expected {:?}, found {:?}", expected, other),
+        }
+    }
+    let mut pushes = vec![];
+    loop {
+        match next()? {
+            Instruction::PushBytes(bytes) => pushes.push(bytes.as_bytes().to_vec()),
+            Instruction::Op(opcodes::all::OP_ENDIF) => break,
+            other => anyhow::bail!("unexpected {:?} in envelope", other),
+        }
+    }
+    if pushes.len() < 3 || pushes[0] != b"atom" {
+        anyhow::bail!("script does not carry an atomicals envelope");
+    }
+    let op_type = String::from_utf8(pushes[1].clone())?;
+    Ok((xonly_public_key, op_type, pushes[2..].concat()))
 }
 
 fn append_mint_update_reveal_script_by_builder(
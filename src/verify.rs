@@ -0,0 +1,227 @@
+use anyhow::Result;
+use bitcoin::{
+    hashes::Hash,
+    key::Secp256k1,
+    secp256k1::{self, Message},
+    sighash::{Prevouts, SighashCache},
+    taproot, Transaction, TxOut, XOnlyPublicKey,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    miner::{commit_sequence_policy, get_payload, sequence_range},
+    types::{Root, Success, Variant},
+    utils::parse_bitwork,
+    worker::{build_commit_tx, has_valid_bitwork},
+};
+
+/// A result reported by a miner, optionally with the txid and transaction it
+/// claims to have produced.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Claim {
+    pub sequence: u64,
+    pub nonce: u64,
+    pub time: u64,
+    pub variant: Variant,
+    pub txid: Option<String>,
+    pub tx: Option<Transaction>,
+}
+
+impl From<&Success> for Claim {
+    fn from(success: &Success) -> Self {
+        Claim {
+            sequence: success.sequence,
+            nonce: success.nonce,
+            time: success.time,
+            variant: success.variant.unwrap_or_default(),
+            txid: None,
+            tx: None,
+        }
+    }
+}
+
+/// One way a claim disagrees with the job.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum Mismatch {
+    SequenceOutOfRange {
+        sequence: u64,
+    },
+    TxidMismatch {
+        claimed: String,
+        actual: String,
+    },
+    BitworkNotMet {
+        txid: String,
+        prefix: Option<String>,
+        ext: Option<u8>,
+    },
+    BitworkcDisagrees {
+        bitworkc: Option<String>,
+        prefix: Option<String>,
+        ext: Option<u8>,
+    },
+    TransactionDiffers {
+        field: String,
+    },
+    InvalidSignature {
+        input: usize,
+        reason: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Verification {
+    pub valid: bool,
+    pub txid: Option<String>,
+    pub mismatches: Vec<Mismatch>,
+}
+
+/// Rebuilds the commit for `claim` and checks it against the job. Every
+/// problem found is reported rather than stopping at the first.
+pub fn verify_claim(msg: &Root, claim: &Claim) -> Result<Verification> {
+    let mut mismatches = vec![];
+    let bitwork = &msg.worker_bitwork_info_commit;
+    let expected_bitworkc = msg
+        .copied_data
+        .args
+        .bitworkc
+        .as_deref()
+        .map(parse_bitwork)
+        .transpose()?;
+    if expected_bitworkc != bitwork.prefix.clone().map(|prefix| (prefix, bitwork.ext)) {
+        mismatches.push(Mismatch::BitworkcDisagrees {
+            bitworkc: msg.copied_data.args.bitworkc.clone(),
+            prefix: bitwork.prefix.clone(),
+            ext: bitwork.ext,
+        });
+    }
+    let Ok(sequence) = u32::try_from(claim.sequence) else {
+        mismatches.push(Mismatch::SequenceOutOfRange {
+            sequence: claim.sequence,
+        });
+        return Ok(Verification {
+            valid: false,
+            txid: None,
+            mismatches,
+        });
+    };
+
+    let allowed = sequence_range(&commit_sequence_policy(msg));
+    if sequence < allowed.start || sequence > allowed.end {
+        mismatches.push(Mismatch::SequenceOutOfRange {
+            sequence: claim.sequence,
+        });
+    }
+
+    let mut payload = get_payload(msg.clone(), Some(claim.time), Some(claim.nonce))?;
+    payload.variant = claim.variant;
+    let rebuilt = build_commit_tx(sequence, &payload)?;
+    let txid = rebuilt.txid().to_string();
+    if let Some(claimed) = &claim.txid {
+        if *claimed != txid {
+            mismatches.push(Mismatch::TxidMismatch {
+                claimed: claimed.clone(),
+                actual: txid.clone(),
+            });
+        }
+    }
+    if !has_valid_bitwork(&txid, &bitwork.prefix, &bitwork.ext) {
+        mismatches.push(Mismatch::BitworkNotMet {
+            txid: txid.clone(),
+            prefix: bitwork.prefix.clone(),
+            ext: bitwork.ext,
+        });
+    }
+
+    let prevout = TxOut {
+        value: payload.funding_utxo_value,
+        script_pubkey: payload.funding_private_script_pubkey.clone(),
+    };
+    let tx = match &claim.tx {
+        Some(tx) => {
+            mismatches.extend(compare_unsigned(tx, &rebuilt));
+            tx
+        }
+        None => &rebuilt,
+    };
+    if let Err(reason) = verify_key_spend(&payload.secp, tx, 0, std::slice::from_ref(&prevout)) {
+        mismatches.push(Mismatch::InvalidSignature { input: 0, reason });
+    }
+
+    Ok(Verification {
+        valid: mismatches.is_empty(),
+        txid: Some(txid),
+        mismatches,
+    })
+}
+
+/// Signatures are randomised, so only the parts the txid commits to are
+/// compared.
+fn compare_unsigned(claimed: &Transaction, rebuilt: &Transaction) -> Vec<Mismatch> {
+    let mut mismatches = vec![];
+    let mut differs = |field: String| mismatches.push(Mismatch::TransactionDiffers { field });
+    if claimed.version != rebuilt.version {
+        differs("version".to_string());
+    }
+    if claimed.lock_time != rebuilt.lock_time {
+        differs("lockTime".to_string());
+    }
+    if claimed.input.len() != rebuilt.input.len() {
+        differs("inputs".to_string());
+    }
+    for (i, (a, b)) in claimed.input.iter().zip(&rebuilt.input).enumerate() {
+        if a.previous_output != b.previous_output {
+            differs(format!("input[{}].previousOutput", i));
+        }
+        if a.sequence != b.sequence {
+            differs(format!("input[{}].sequence", i));
+        }
+        if a.script_sig != b.script_sig {
+            differs(format!("input[{}].scriptSig", i));
+        }
+    }
+    if claimed.output.len() != rebuilt.output.len() {
+        differs("outputs".to_string());
+    }
+    for (i, (a, b)) in claimed.output.iter().zip(&rebuilt.output).enumerate() {
+        if a.value != b.value {
+            differs(format!("output[{}].value", i));
+        }
+        if a.script_pubkey != b.script_pubkey {
+            differs(format!("output[{}].scriptPubkey", i));
+        }
+    }
+    mismatches
+}
+
+/// Checks the taproot key-path signature of `tx.input[index]` against the
+/// output key of its prevout.
+pub fn verify_key_spend(
+    secp: &Secp256k1<secp256k1::All>,
+    tx: &Transaction,
+    index: usize,
+    prevouts: &[TxOut],
+) -> Result<(), String> {
+    let input = tx.input.get(index).ok_or("input does not exist")?;
+    let prevout = prevouts.get(index).ok_or("missing prevout")?;
+    if !prevout.script_pubkey.is_p2tr() {
+        return Err("prevout is not a taproot output".to_string());
+    }
+    let output_key = XOnlyPublicKey::from_slice(&prevout.script_pubkey.as_bytes()[2..])
+        .map_err(|err| err.to_string())?;
+    if input.witness.len() != 1 {
+        return Err(format!(
+            "expected a single key-path signature, found {} witness elements",
+            input.witness.len()
+        ));
+    }
+    let signature =
+        taproot::Signature::from_slice(&input.witness[0]).map_err(|err| err.to_string())?;
+    let hash = SighashCache::new(tx)
+        .taproot_key_spend_signature_hash(index, &Prevouts::All(prevouts), signature.hash_ty)
+        .map_err(|err| err.to_string())?;
+    let msg = Message::from_digest(hash.to_byte_array());
+    secp.verify_schnorr(&signature.sig, &msg, &output_key)
+        .map_err(|err| err.to_string())
+}
@@ -5,32 +5,112 @@ use bitcoin::{
     psbt::{Input, Output},
     sighash::{Prevouts, SighashCache},
     transaction::Version,
-    OutPoint, Psbt, ScriptBuf, Sequence, TapSighashType, Transaction, TxIn, TxOut, Witness,
+    Amount, OutPoint, Psbt, ScriptBuf, Sequence, TapSighashType, Transaction, TxIn, TxOut, Witness,
 };
 
-use crate::{types::Payload, utils};
+use crate::{
+    types::{Payload, Variant},
+    utils,
+};
 
-pub(crate) const MAX_SEQUENCE: u32 = 0xFFFFFFFF;
+pub const MAX_SEQUENCE: u32 = 0xFFFFFFFF;
 
-pub(crate) fn predicate(seq: u32, payload: &Payload) -> anyhow::Result<bool> {
-    let mut psbt = Psbt {
-        unsigned_tx: Transaction {
-            version: Version::ONE,
-            lock_time: LockTime::ZERO,
-            input: vec![TxIn {
-                previous_output: OutPoint {
-                    txid: payload.funding_utxo_id,
-                    vout: payload.funding_utxo_vout,
-                },
-                script_sig: ScriptBuf::new(),
-                sequence: Sequence(seq), // Ignore nSequence.
-                witness: Witness::default(),
-            }],
-            output: vec![TxOut {
+/// BIP-68 flag that keeps an `nSequence` from meaning a relative lock time.
+pub const SEQUENCE_LOCK_TIME_DISABLE_FLAG: u32 = 1 << 31;
+
+/// BIP-68 flag that makes a relative lock count 512-second units, not blocks.
+pub const SEQUENCE_LOCK_TIME_TYPE_FLAG: u32 = 1 << 22;
+
+/// BIP-68 bits holding the relative lock's value.
+pub const SEQUENCE_LOCK_TIME_MASK: u32 = 0xffff;
+
+pub fn predicate(seq: u32, payload: &Payload) -> anyhow::Result<bool> {
+    let tx = build_commit_tx(seq, payload)?;
+    if has_valid_bitwork(
+        &tx.txid().to_string(),
+        &payload.valid_prefix,
+        &payload.valid_ext,
+    ) {
+        eprintln!("Found sequence: {}", seq);
+        eprintln!("Txid: {}", tx.txid());
+        return Ok(true);
+    }
+    Ok(false)
+}
+
+/// The funding output a commit spends.
+pub fn funding_prevout(payload: &Payload) -> TxOut {
+    TxOut {
+        value: payload.funding_utxo_value,
+        script_pubkey: payload.funding_private_script_pubkey.clone(),
+    }
+}
+
+/// The commit for the given `nSequence` and `payload.variant`, unsigned.
+pub fn unsigned_commit_tx(seq: u32, payload: &Payload) -> Transaction {
+    let mut tx = Transaction {
+        version: Version(payload.variant.version),
+        lock_time: LockTime::from_consensus(payload.variant.lock_time),
+        input: vec![TxIn {
+            previous_output: OutPoint {
+                txid: payload.funding_utxo_id,
+                vout: payload.funding_utxo_vout,
+            },
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence(seq), // Ignore nSequence.
+            witness: Witness::default(),
+        }],
+        output: vec![TxOut {
+            value: payload.fixed_output_value,
+            script_pubkey: payload.fixed_output_script_pubkey.clone(),
+        }],
+    };
+    // The further outputs of a fan-out commit come before the change.
+    tx.output.extend(
+        payload
+            .fan_out_script_pubkeys
+            .iter()
+            .map(|script_pubkey| TxOut {
                 value: payload.fixed_output_value,
-                script_pubkey: payload.fixed_output_script_pubkey.clone(),
-            }],
-        },
+                script_pubkey: script_pubkey.clone(),
+            }),
+    );
+    if payload.need_change_fee_output {
+        tx.output.push(TxOut {
+            value: change_value(payload, payload.variant),
+            script_pubkey: payload.funding_private_script_pubkey.clone(),
+        });
+    }
+    tx
+}
+
+/// Vout of the change output of `payload`'s commit, if it has one.
+pub fn change_vout(payload: &Payload) -> Option<usize> {
+    payload
+        .need_change_fee_output
+        .then(|| payload.fan_out_script_pubkeys.len() + 1)
+}
+
+/// Value of the change output of `payload`'s commit in `variant`.
+pub fn change_value(payload: &Payload, variant: Variant) -> Amount {
+    payload.funding_value - Amount::from_sat(variant.change_reduction)
+}
+
+/// Turns `tx`, an unsigned commit of `payload`, into the one of `variant`.
+/// Variants differ only in version, lock time and change value.
+pub fn apply_variant(tx: &mut Transaction, payload: &Payload, variant: Variant) {
+    tx.version = Version(variant.version);
+    tx.lock_time = LockTime::from_consensus(variant.lock_time);
+    if let Some(change) = change_vout(payload) {
+        tx.output[change].value = change_value(payload, variant);
+    }
+}
+
+/// Builds and signs the commit transaction for the given `nSequence` and
+/// `payload.variant`.
+pub fn build_commit_tx(seq: u32, payload: &Payload) -> anyhow::Result<Transaction> {
+    let mut psbt = Psbt {
+        unsigned_tx: unsigned_commit_tx(seq, payload),
         version: 0,
         xpub: Default::default(),
         proprietary: Default::default(),
@@ -54,16 +134,7 @@ pub(crate) fn predicate(seq: u32, payload: &Payload) -> anyhow::Result<bool> {
             },
         ],
     };
-    if payload.need_change_fee_output {
-        psbt.unsigned_tx.output.push(TxOut {
-            value: payload.funding_value,
-            script_pubkey: payload.funding_private_script_pubkey.clone(),
-        });
-    }
-    let input_txouts = TxOut {
-        value: payload.funding_utxo_value,
-        script_pubkey: payload.funding_private_script_pubkey.clone(),
-    };
+    let input_txouts = funding_prevout(payload);
     // SIGNER
     let unsigned_tx = psbt.unsigned_tx.clone();
     psbt.inputs
@@ -76,7 +147,7 @@ pub(crate) fn predicate(seq: u32, payload: &Payload) -> anyhow::Result<bool> {
                 .unwrap_or(TapSighashType::Default);
             let hash = SighashCache::new(&unsigned_tx).taproot_key_spend_signature_hash(
                 0,
-                &Prevouts::All(&[input_txouts.clone()]),
+                &Prevouts::All(std::slice::from_ref(&input_txouts)),
                 hash_ty,
             )?;
 
@@ -109,20 +180,10 @@ pub(crate) fn predicate(seq: u32, payload: &Payload) -> anyhow::Result<bool> {
     });
 
     // EXTRACTOR
-    let tx = psbt.extract_tx_unchecked_fee_rate();
-    if has_valid_bitwork(
-        &tx.txid().to_string(),
-        &payload.valid_prefix,
-        &payload.valid_ext,
-    ) {
-        println!("Found sequence: {}", seq);
-        println!("Txid: {}", tx.txid());
-        return Ok(true);
-    }
-    Ok(false)
+    Ok(psbt.extract_tx_unchecked_fee_rate())
 }
 
-fn has_valid_bitwork(txid: &str, bitwork: &Option<String>, bitworkx: &Option<u8>) -> bool {
+pub fn has_valid_bitwork(txid: &str, bitwork: &Option<String>, bitworkx: &Option<u8>) -> bool {
     if let Some(bitwork) = bitwork {
         if txid.starts_with(bitwork.as_str()) {
             if let Some(bitworkx_value) = bitworkx {
@@ -143,3 +204,108 @@ fn has_valid_bitwork(txid: &str, bitwork: &Option<String>, bitworkx: &Option<u8>
     }
     false
 }
+
+/// How close a txid came to the target bitwork.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum BitworkGrade {
+    Miss,
+    /// Meets the lower share bitwork only.
+    Share,
+    Solution,
+}
+
+/// [`has_valid_bitwork`] with a lower share difficulty for pool mode.
+pub fn grade_bitwork(
+    txid: &str,
+    target: (&Option<String>, &Option<u8>),
+    share: (&Option<String>, &Option<u8>),
+) -> BitworkGrade {
+    if has_valid_bitwork(txid, target.0, target.1) {
+        BitworkGrade::Solution
+    } else if has_valid_bitwork(txid, share.0, share.1) {
+        BitworkGrade::Share
+    } else {
+        BitworkGrade::Miss
+    }
+}
+
+/// Whether every txid meeting the target bitwork also meets the share
+/// bitwork, which must be strictly easier.
+pub fn is_share_bitwork(target: (&str, Option<u8>), share: (&str, Option<u8>)) -> bool {
+    let (prefix, ext) = target;
+    let (share_prefix, share_ext) = share;
+    let implied = match prefix.strip_prefix(share_prefix) {
+        // The target's next nibble has to clear the share extension.
+        Some(rest) if !rest.is_empty() => match (share_ext, rest.chars().next()) {
+            (None, _) => true,
+            (Some(share_ext), Some(next)) => next.to_digit(16).unwrap_or(0) >= share_ext as u32,
+            (Some(_), None) => false,
+        },
+        Some(_) => share_ext.is_none() || share_ext <= ext,
+        None => false,
+    };
+    implied && bitwork_difficulty(share_prefix, share_ext) < bitwork_difficulty(prefix, ext)
+}
+
+/// [`has_valid_bitwork`] for txids in internal byte order, as produced by
+/// the hash backends, without formatting them as hex.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BitworkMatcher {
+    nibbles: Vec<u8>,
+    ext: Option<u8>,
+}
+
+impl BitworkMatcher {
+    /// `None` when nothing can match: no prefix, or one that is not lowercase
+    /// hex or is longer than a txid.
+    pub fn new(bitwork: &Option<String>, bitworkx: &Option<u8>) -> Option<Self> {
+        let bitwork = bitwork.as_ref()?;
+        if bitwork.len() > 64 {
+            return None;
+        }
+        let nibbles = bitwork
+            .chars()
+            .map(|ch| match ch {
+                '0'..='9' | 'a'..='f' => ch.to_digit(16).map(|n| n as u8),
+                _ => None,
+            })
+            .collect::<Option<Vec<u8>>>()?;
+        Some(BitworkMatcher {
+            nibbles,
+            ext: *bitworkx,
+        })
+    }
+
+    pub fn matches(&self, hash: &[u8; 32]) -> bool {
+        // Txids are displayed byte-reversed, high nibble first.
+        let nibble = |i: usize| {
+            let byte = hash[31 - i / 2];
+            if i.is_multiple_of(2) {
+                byte >> 4
+            } else {
+                byte & 0x0f
+            }
+        };
+        if !self
+            .nibbles
+            .iter()
+            .enumerate()
+            .all(|(i, n)| nibble(i) == *n)
+        {
+            return false;
+        }
+        match self.ext {
+            Some(ext) => self.nibbles.len() < 64 && nibble(self.nibbles.len()) >= ext,
+            None => true,
+        }
+    }
+}
+
+/// Expected number of candidates needed to satisfy a bitwork.
+pub fn bitwork_difficulty(prefix: &str, ext: Option<u8>) -> f64 {
+    let mut difficulty = 16f64.powi(prefix.len() as i32);
+    if let Some(ext) = ext {
+        difficulty *= 16.0 / (16 - ext.min(15)) as f64;
+    }
+    difficulty
+}